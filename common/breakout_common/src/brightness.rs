@@ -0,0 +1,157 @@
+//! A brightness setting for the display.
+//!
+//! On the SSD1351, brightness is set using the master contrast, which scales the current of all
+//! pixels in steps of 1/16. Every level of [`Brightness`] is two such steps, i.e. 1/8 of the full
+//! current. The color contrast is left alone: it balances the three colors of the panel against
+//! each other (see [`DisplayConfig`]), and the master contrast scales all of them alike, so white
+//! stays white at every level. On the host, the colors of the image are scaled instead; since monitors and the
+//! grayscale table [`GAMMA_2_2`] both apply gamma 2.2, scaling the light output by a factor means
+//! scaling the color values by the 2.2th root of that factor.
+//!
+//! [`DisplayConfig`]: crate::display_config::DisplayConfig
+//! [`GAMMA_2_2`]: crate::grayscale::GAMMA_2_2
+
+
+use crate::color::Color;
+use crate::display::DisplayInterface;
+use crate::grayscale::pow;
+use crate::ssd1351::DisplayCommand;
+
+
+/// The number of brightness levels.
+pub const BRIGHTNESS_LEVELS: u8 = 8;
+
+/// The gamma value by which the host scales color values; see the module documentation.
+const HOST_GAMMA: f32 = 2.2;
+
+/// The factors by which the host scales color values at each level, in 1/256.
+const COLOR_SCALES: [u16; BRIGHTNESS_LEVELS as usize] = {
+    let mut scales = [0u16; BRIGHTNESS_LEVELS as usize];
+    let mut level = 0;
+    while level < BRIGHTNESS_LEVELS as usize {
+        let light = (level + 1) as f32 / BRIGHTNESS_LEVELS as f32;
+        scales[level] = (pow(light, 1.0 / HOST_GAMMA) * 256.0 + 0.5) as u16;
+        level += 1;
+    }
+    scales
+};
+
+
+/// How bright the display shines, from level 0 (1/8 of the full current) to
+/// `BRIGHTNESS_LEVELS - 1` (the full current).
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Brightness {
+    level: u8,
+}
+impl Brightness {
+    pub const MIN: Self = Self { level: 0 };
+    pub const MAX: Self = Self { level: BRIGHTNESS_LEVELS - 1 };
+
+    /// Returns `None` if the level is not below [`BRIGHTNESS_LEVELS`].
+    #[inline]
+    pub const fn new(level: u8) -> Option<Self> {
+        if level < BRIGHTNESS_LEVELS {
+            Some(Self { level })
+        } else {
+            None
+        }
+    }
+
+    #[inline]
+    pub const fn level(&self) -> u8 { self.level }
+
+    /// The next brighter level, or this one if it is the brightest.
+    #[inline]
+    pub const fn brighter(&self) -> Self {
+        if self.level < Self::MAX.level {
+            Self { level: self.level + 1 }
+        } else {
+            *self
+        }
+    }
+
+    /// The next dimmer level, or this one if it is the dimmest.
+    #[inline]
+    pub const fn dimmer(&self) -> Self {
+        if self.level > Self::MIN.level {
+            Self { level: self.level - 1 }
+        } else {
+            *self
+        }
+    }
+
+    /// The value of the master contrast that gives this brightness.
+    #[inline]
+    pub const fn master_contrast(&self) -> u8 { 2 * self.level + 1 }
+
+    /// The command that sets the display to this brightness.
+    #[inline]
+    pub const fn command(&self) -> DisplayCommand<'static> {
+        DisplayCommand::SetMasterContrast { contrast: self.master_contrast() }
+    }
+
+    /// Scales a color so that a monitor shows it as the display would at this brightness.
+    pub const fn scale_color(&self, color: Color) -> Color {
+        let scale = COLOR_SCALES[self.level as usize];
+        Color::new(
+            scale_channel(color.r, scale),
+            scale_channel(color.g, scale),
+            scale_channel(color.b, scale),
+        )
+    }
+}
+impl Default for Brightness {
+    fn default() -> Self { Self::MAX }
+}
+
+
+#[inline]
+const fn scale_channel(channel: u8, scale: u16) -> u8 {
+    ((channel as u16 * scale) >> 8) as u8
+}
+
+
+/// Changes the brightness of a running display.
+pub fn set_brightness<DI: DisplayInterface>(display_interface: &mut DI, brightness: Brightness) {
+    brightness.command()
+        .transmit(display_interface);
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_levels() {
+        assert_eq!(Brightness::new(BRIGHTNESS_LEVELS), None);
+        let mut brightness = Brightness::MIN;
+        assert_eq!(brightness.dimmer(), Brightness::MIN);
+        for level in 0..BRIGHTNESS_LEVELS {
+            assert_eq!(Some(brightness), Brightness::new(level));
+            assert!(brightness.command().is_valid());
+            brightness = brightness.brighter();
+        }
+        assert_eq!(brightness, Brightness::MAX);
+        assert_eq!(Brightness::MAX.dimmer().level(), BRIGHTNESS_LEVELS - 2);
+        assert_eq!(Brightness::default(), Brightness::MAX);
+    }
+
+    #[test]
+    fn test_master_contrast() {
+        assert_eq!(Brightness::MIN.master_contrast(), 0x01);
+        assert_eq!(Brightness::new(3).unwrap().master_contrast(), 0x07);
+        assert_eq!(Brightness::MAX.master_contrast(), 0x0F);
+        assert_eq!(Brightness::MAX.command(), DisplayCommand::SetMasterContrast { contrast: 0x0F });
+    }
+
+    #[test]
+    fn test_scale_color() {
+        let color = Color::new(0xFF, 0x80, 0x00);
+        assert_eq!(Brightness::MAX.scale_color(color), color);
+
+        // half the light needs 0.5^(1/2.2) = 73% of the color value
+        assert_eq!(Brightness::new(3).unwrap().scale_color(color), Color::new(0xBA, 0x5D, 0x00));
+        assert_eq!(Brightness::MIN.scale_color(Color::WHITE), Color::new(0x62, 0x62, 0x62));
+    }
+}
@@ -0,0 +1,295 @@
+//! Protection of the OLED panel against burn-in.
+//!
+//! Pixels that shine for a long time age faster than their neighbors, so the static border and HUD
+//! would eventually remain visible as a shadow. The [`BurnInGuard`] counters this in two ways:
+//!
+//! * It moves the image up and down by a pixel or two every now and then using the display offset
+//!   of the SSD1351, so that edges are spread over neighboring rows. This needs no redraw; the rows
+//!   moved into view are outside the RAM window and therefore black (or, on a panel using all 128
+//!   rows, the opposite edge of the image).
+//!
+//! * It dims the display when no key has been pressed for a while and eventually turns it off
+//!   until the next key press.
+
+
+use core::time::Duration;
+
+use arrayvec::ArrayVec;
+
+use crate::brightness::Brightness;
+use crate::layout::CONTROLLER_RAM_EXTENT;
+use crate::ssd1351::DisplayCommand;
+
+
+/// The maximum number of commands returned by [`BurnInGuard::advance`].
+pub const MAX_GUARD_COMMANDS: usize = 3;
+
+/// The commands that bring the display in line with the [`BurnInGuard`].
+pub type GuardCommands = ArrayVec<DisplayCommand<'static>, MAX_GUARD_COMMANDS>;
+
+
+/// When and how far the [`BurnInGuard`] acts.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct BurnInConfig {
+    /// How long the image stays in place before it is moved by a row.
+    pub shift_interval: Duration,
+
+    /// How many rows the image is moved up and down at most; 0 disables moving it.
+    pub max_shift: u8,
+
+    /// How long without key presses until the display is dimmed.
+    pub dim_after: Duration,
+
+    /// How bright the display is while dimmed (unless it is set to be even dimmer).
+    pub dimmed_brightness: Brightness,
+
+    /// How long without key presses until the display is turned off.
+    pub off_after: Duration,
+}
+impl BurnInConfig {
+    pub const DEFAULT: Self = Self {
+        shift_interval: Duration::from_secs(60),
+        max_shift: 1,
+        dim_after: Duration::from_secs(30),
+        dimmed_brightness: Brightness::MIN,
+        off_after: Duration::from_secs(5 * 60),
+    };
+}
+impl Default for BurnInConfig {
+    fn default() -> Self { Self::DEFAULT }
+}
+
+
+/// Whether the panel is showing the image and how brightly.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum PanelState {
+    /// Keys have been pressed recently; the display has the brightness set by the user.
+    Active,
+
+    /// The display shines at the dimmed brightness.
+    Dimmed,
+
+    /// The display is turned off.
+    Off,
+}
+
+
+/// Keeps track of idle time and of the position of the image on the panel.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct BurnInGuard {
+    config: BurnInConfig,
+    state: PanelState,
+    idle_time: Duration,
+    since_shift: Duration,
+    shift_step: u16,
+}
+impl BurnInGuard {
+    /// Starts out active and unshifted, which is how [`initialize`] leaves the display.
+    ///
+    /// [`initialize`]: crate::display::initialize
+    pub const fn new(config: BurnInConfig) -> Self {
+        Self {
+            config,
+            state: PanelState::Active,
+            idle_time: Duration::ZERO,
+            since_shift: Duration::ZERO,
+            shift_step: 0,
+        }
+    }
+
+    #[inline]
+    pub const fn config(&self) -> &BurnInConfig { &self.config }
+
+    #[inline]
+    pub const fn state(&self) -> PanelState { self.state }
+
+    /// The number of rows by which the image is currently moved up; negative values move it down.
+    ///
+    /// The image moves one row per step, sweeping back and forth between `-max_shift` and
+    /// `max_shift`.
+    pub const fn shift(&self) -> i8 {
+        let max_shift = self.config.max_shift as i16;
+        let step = self.shift_step as i16;
+        let shift = if step <= max_shift {
+            step
+        } else if step <= 3 * max_shift {
+            2 * max_shift - step
+        } else {
+            step - 4 * max_shift
+        };
+        shift as i8
+    }
+
+    /// The display offset that moves the image by [`BurnInGuard::shift`].
+    pub const fn display_offset(&self) -> u8 {
+        (self.shift() as i16).rem_euclid(CONTROLLER_RAM_EXTENT as i16) as u8
+    }
+
+    /// Accounts for the given amount of elapsed time, during which keys were pressed if `activity`
+    /// is set, and returns the commands that bring the display in line.
+    ///
+    /// `brightness` is the brightness set by the user; it is restored when the display becomes
+    /// active again.
+    pub fn advance(
+        &mut self,
+        elapsed: Duration,
+        activity: bool,
+        brightness: Brightness,
+    ) -> GuardCommands {
+        let mut commands = GuardCommands::new();
+
+        self.since_shift += elapsed;
+        if self.config.max_shift > 0 && self.since_shift >= self.config.shift_interval {
+            self.since_shift = Duration::ZERO;
+            self.shift_step = (self.shift_step + 1) % (4 * u16::from(self.config.max_shift));
+            commands.push(DisplayCommand::SetDisplayOffset { offset: self.display_offset() });
+        }
+
+        let previous_state = self.state;
+        if activity {
+            self.idle_time = Duration::ZERO;
+            self.state = PanelState::Active;
+        } else {
+            self.idle_time += elapsed;
+            if self.idle_time >= self.config.off_after {
+                self.state = PanelState::Off;
+            } else if self.idle_time >= self.config.dim_after {
+                self.state = PanelState::Dimmed;
+            }
+        }
+
+        if self.state != previous_state {
+            if previous_state == PanelState::Off {
+                commands.push(DisplayCommand::DisplayOn);
+            }
+            match self.state {
+                PanelState::Active => {
+                    commands.push(brightness.command());
+                },
+                PanelState::Dimmed => {
+                    commands.push(brightness.min(self.config.dimmed_brightness).command());
+                },
+                PanelState::Off => {
+                    commands.push(DisplayCommand::DisplayOff);
+                },
+            }
+        }
+
+        commands
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::vec;
+    use std::vec::Vec;
+
+    use super::*;
+
+    const SECOND: Duration = Duration::from_secs(1);
+
+    #[test]
+    fn test_shift_sequence() {
+        for (max_shift, expected) in [
+            (1, vec![0, 1, 0, -1, 0]),
+            (2, vec![0, 1, 2, 1, 0, -1, -2, -1, 0]),
+        ] {
+            let config = BurnInConfig { max_shift, ..BurnInConfig::DEFAULT };
+            let mut guard = BurnInGuard::new(config);
+            let mut shifts = vec![guard.shift()];
+            for _ in 1..expected.len() {
+                let commands = guard.advance(config.shift_interval, true, Brightness::MAX);
+                assert_eq!(
+                    commands.as_slice(),
+                    &[DisplayCommand::SetDisplayOffset { offset: guard.display_offset() }],
+                );
+                shifts.push(guard.shift());
+            }
+            assert_eq!(shifts, expected);
+        }
+    }
+
+    #[test]
+    fn test_display_offset() {
+        let config = BurnInConfig { max_shift: 2, ..BurnInConfig::DEFAULT };
+        let mut guard = BurnInGuard::new(config);
+        let mut offsets = Vec::new();
+        for _ in 0..8 {
+            guard.advance(config.shift_interval, true, Brightness::MAX);
+            offsets.push(guard.display_offset());
+        }
+        assert_eq!(offsets, vec![1, 2, 1, 0, 127, 126, 127, 0]);
+    }
+
+    #[test]
+    fn test_no_shift() {
+        let config = BurnInConfig { max_shift: 0, ..BurnInConfig::DEFAULT };
+        let mut guard = BurnInGuard::new(config);
+        for _ in 0..10 {
+            assert!(guard.advance(config.shift_interval, true, Brightness::MAX).is_empty());
+        }
+        assert_eq!(guard.shift(), 0);
+    }
+
+    #[test]
+    fn test_idle() {
+        let config = BurnInConfig {
+            shift_interval: Duration::from_secs(1000),
+            dim_after: 10 * SECOND,
+            off_after: 20 * SECOND,
+            dimmed_brightness: Brightness::new(1).unwrap(),
+            ..BurnInConfig::DEFAULT
+        };
+        let brightness = Brightness::new(5).unwrap();
+        let mut guard = BurnInGuard::new(config);
+
+        assert!(guard.advance(9 * SECOND, false, brightness).is_empty());
+        assert_eq!(guard.state(), PanelState::Active);
+        assert_eq!(
+            guard.advance(SECOND, false, brightness).as_slice(),
+            &[DisplayCommand::SetMasterContrast { contrast: 0x03 }],
+        );
+        assert_eq!(guard.state(), PanelState::Dimmed);
+
+        // a key press restores the brightness and restarts the countdown
+        assert_eq!(
+            guard.advance(SECOND, true, brightness).as_slice(),
+            &[DisplayCommand::SetMasterContrast { contrast: 0x0B }],
+        );
+        assert_eq!(guard.state(), PanelState::Active);
+        assert!(guard.advance(9 * SECOND, false, brightness).is_empty());
+
+        guard.advance(SECOND, false, brightness);
+        assert_eq!(
+            guard.advance(10 * SECOND, false, brightness).as_slice(),
+            &[DisplayCommand::DisplayOff],
+        );
+        assert_eq!(guard.state(), PanelState::Off);
+        assert!(guard.advance(100 * SECOND, false, brightness).is_empty());
+
+        assert_eq!(
+            guard.advance(SECOND, true, brightness).as_slice(),
+            &[DisplayCommand::DisplayOn, DisplayCommand::SetMasterContrast { contrast: 0x0B }],
+        );
+        assert_eq!(guard.state(), PanelState::Active);
+    }
+
+    #[test]
+    fn test_dimming_never_brightens() {
+        let mut guard = BurnInGuard::new(BurnInConfig::DEFAULT);
+        let commands = guard.advance(BurnInConfig::DEFAULT.dim_after, false, Brightness::MIN);
+        assert_eq!(
+            commands.as_slice(),
+            &[DisplayCommand::SetMasterContrast { contrast: Brightness::MIN.master_contrast() }],
+        );
+
+        let config = BurnInConfig { dimmed_brightness: Brightness::MAX, ..BurnInConfig::DEFAULT };
+        let mut guard = BurnInGuard::new(config);
+        let dimmed = Brightness::new(2).unwrap();
+        let commands = guard.advance(config.dim_after, false, dimmed);
+        assert_eq!(commands.as_slice(), &[dimmed.command()]);
+    }
+}
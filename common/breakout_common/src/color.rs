@@ -0,0 +1,103 @@
+//! Colors and their encoding in the frame buffer.
+
+
+/// A color with 8 bits per channel.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+impl Color {
+    pub const BLACK: Self = Self::new(0x00, 0x00, 0x00);
+    pub const WHITE: Self = Self::new(0xFF, 0xFF, 0xFF);
+
+    #[inline]
+    pub const fn new(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b }
+    }
+
+    /// Decodes a color from its R5:G6:B5 representation.
+    ///
+    /// The channels are expanded to 8 bits by replicating their most significant bits, so that
+    /// black and white remain black and white.
+    #[inline]
+    pub const fn from_rgb565(value: u16) -> Self {
+        let r5 = ((value >> 11) & 0b1_1111) as u8;
+        let g6 = ((value >> 5) & 0b11_1111) as u8;
+        let b5 = (value & 0b1_1111) as u8;
+        Self {
+            r: (r5 << 3) | (r5 >> 2),
+            g: (g6 << 2) | (g6 >> 4),
+            b: (b5 << 3) | (b5 >> 2),
+        }
+    }
+
+    /// Encodes this color into its R5:G6:B5 representation, truncating the channels.
+    #[inline]
+    pub const fn to_rgb565(&self) -> u16 {
+        ((self.r as u16 >> 3) << 11)
+        | ((self.g as u16 >> 2) << 5)
+        | (self.b as u16 >> 3)
+    }
+
+    /// Decodes a color from the two bytes of a pixel in the frame buffer.
+    ///
+    /// Pixels are stored in R5:G6:B5 encoding with the most significant byte first, which is the
+    /// order in which the SSD1351 expects them.
+    #[inline]
+    pub const fn from_pixel_bytes(bytes: [u8; 2]) -> Self {
+        Self::from_rgb565(u16::from_be_bytes(bytes))
+    }
+
+    /// Encodes this color into the two bytes of a pixel in the frame buffer.
+    #[inline]
+    pub const fn to_pixel_bytes(&self) -> [u8; 2] {
+        self.to_rgb565().to_be_bytes()
+    }
+
+    /// The color with all channels inverted, as shown by the display in inverted mode.
+    #[inline]
+    pub const fn inverted(&self) -> Self {
+        Self::new(!self.r, !self.g, !self.b)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rgb565() {
+        assert_eq!(Color::BLACK.to_rgb565(), 0x0000);
+        assert_eq!(Color::WHITE.to_rgb565(), 0xFFFF);
+        assert_eq!(Color::new(0xFF, 0x00, 0x00).to_rgb565(), 0xF800);
+        assert_eq!(Color::new(0x00, 0xFF, 0x00).to_rgb565(), 0x07E0);
+        assert_eq!(Color::new(0x00, 0x00, 0xFF).to_rgb565(), 0x001F);
+
+        assert_eq!(Color::from_rgb565(0x0000), Color::BLACK);
+        assert_eq!(Color::from_rgb565(0xFFFF), Color::WHITE);
+        assert_eq!(Color::from_rgb565(0xF800), Color::new(0xFF, 0x00, 0x00));
+        assert_eq!(Color::from_rgb565(0x8410), Color::new(0x84, 0x82, 0x84));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for value in 0..=u16::MAX {
+            assert_eq!(Color::from_rgb565(value).to_rgb565(), value);
+        }
+    }
+
+    #[test]
+    fn test_pixel_bytes() {
+        assert_eq!(Color::new(0xFF, 0x00, 0x00).to_pixel_bytes(), [0xF8, 0x00]);
+        assert_eq!(Color::from_pixel_bytes([0x00, 0x1F]), Color::new(0x00, 0x00, 0xFF));
+    }
+
+    #[test]
+    fn test_inverted() {
+        assert_eq!(Color::BLACK.inverted(), Color::WHITE);
+        assert_eq!(Color::new(0xF0, 0x0F, 0x80).inverted(), Color::new(0x0F, 0xF0, 0x7F));
+    }
+}
@@ -0,0 +1,286 @@
+//! Collecting display commands and data so that they can be sent in a single transaction.
+//!
+//! Every call to [`DisplayInterface::send`] selects the display, sends one command with its
+//! parameters and deselects it again. A [`CommandQueue`] gathers several commands and blocks of
+//! data first; [`DisplayInterface::send_queue`] then sends them while keeping the display selected
+//! throughout, only switching the data/~command pin in between.
+
+
+use core::fmt;
+
+use arrayvec::ArrayVec;
+
+use crate::display::DisplayInterface;
+use crate::layout::{BYTES_PER_PIXEL, DisplayLayout, Rect};
+use crate::ssd1351::DisplayCommand;
+
+
+/// The maximum number of commands (and blocks of data not preceded by a command) in a queue.
+pub const MAX_QUEUED_COMMANDS: usize = 32;
+
+
+/// The queue has no room left for the command or data.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct QueueFullError;
+impl fmt::Display for QueueFullError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "command queue is full")
+    }
+}
+
+
+/// A command byte (if any) and the end of the data following it in the byte buffer.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+struct Entry {
+    command: Option<u8>,
+    data_end: usize,
+}
+
+
+/// Commands and data waiting to be sent to the display, with room for `N` bytes of parameters and
+/// data.
+#[derive(Clone, Debug, Default)]
+pub struct CommandQueue<const N: usize> {
+    entries: ArrayVec<Entry, MAX_QUEUED_COMMANDS>,
+    data: ArrayVec<u8, N>,
+}
+impl<const N: usize> CommandQueue<N> {
+    pub const fn new() -> Self {
+        Self {
+            entries: ArrayVec::new_const(),
+            data: ArrayVec::new_const(),
+        }
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool { self.entries.is_empty() }
+
+    /// The number of bytes that will be sent, command bytes included.
+    pub fn byte_count(&self) -> usize {
+        self.entries.iter().filter(|entry| entry.command.is_some()).count() + self.data.len()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.data.clear();
+    }
+
+    /// Appends a command and its parameters.
+    pub fn push(&mut self, command: &DisplayCommand<'_>) -> Result<(), QueueFullError> {
+        debug_assert!(command.is_valid());
+        let (command_code, parameters) = command.encode();
+        self.push_raw(command_code, &parameters)
+    }
+
+    /// Appends a command byte, sent with the data/~command pin pulled low, and the bytes following
+    /// it, sent with the pin high. This is for controllers other than the SSD1351.
+    pub fn push_raw(&mut self, command_code: u8, parameters: &[u8]) -> Result<(), QueueFullError> {
+        if self.entries.is_full() || self.data.remaining_capacity() < parameters.len() {
+            return Err(QueueFullError);
+        }
+        self.data.try_extend_from_slice(parameters)
+            .expect("capacity was checked");
+        self.entries.push(Entry { command: Some(command_code), data_end: self.data.len() });
+        Ok(())
+    }
+
+    /// Appends data, e.g. pixels following [`DisplayCommand::WriteRam`]. The data is sent right
+    /// after whatever was appended before.
+    pub fn push_data(&mut self, data: &[u8]) -> Result<(), QueueFullError> {
+        if self.data.remaining_capacity() < data.len() {
+            return Err(QueueFullError);
+        }
+        match self.entries.last_mut() {
+            Some(entry) => {
+                self.data.try_extend_from_slice(data)
+                    .expect("capacity was checked");
+                entry.data_end = self.data.len();
+            },
+            None => {
+                self.data.try_extend_from_slice(data)
+                    .expect("capacity was checked");
+                self.entries.push(Entry { command: None, data_end: self.data.len() });
+            },
+        }
+        Ok(())
+    }
+
+    /// Appends everything needed to redraw an area of the display: the RAM window, the command to
+    /// write RAM and the pixels of the area in row-major order, as drawn by e.g.
+    /// [`Playfield::draw_area`]. The RAM window is left pointing at the area.
+    ///
+    /// If there is not enough room, the queue is left unchanged.
+    ///
+    /// [`Playfield::draw_area`]: crate::playfield::Playfield::draw_area
+    pub fn push_area_update(
+        &mut self,
+        layout: &DisplayLayout,
+        area: Rect,
+        pixels: &[u8],
+    ) -> Result<(), QueueFullError> {
+        debug_assert_eq!(pixels.len(), area.area() * BYTES_PER_PIXEL);
+        let (entry_count, data_length) = (self.entries.len(), self.data.len());
+        let result = self.push_window(layout, area)
+            .and_then(|()| self.push_data(pixels));
+        if result.is_err() {
+            self.entries.truncate(entry_count);
+            self.data.truncate(data_length);
+        }
+        result
+    }
+
+    /// Appends the commands that point the RAM window at an area of the display and prepare for
+    /// receiving its pixels.
+    pub fn push_window(
+        &mut self,
+        layout: &DisplayLayout,
+        area: Rect,
+    ) -> Result<(), QueueFullError> {
+        let window = layout.ram_rect(area);
+        self.push(&DisplayCommand::SetColumnAddress {
+            start: window.left as u8,
+            end: (window.right() - 1) as u8,
+        })?;
+        self.push(&DisplayCommand::SetRowAddress {
+            start: window.top as u8,
+            end: (window.bottom() - 1) as u8,
+        })?;
+        self.push(&DisplayCommand::WriteRam)
+    }
+
+    /// The queued commands in order, each with the data that follows it. Data at the start of the
+    /// queue comes without a command.
+    pub fn entries(&self) -> impl Iterator<Item = (Option<u8>, &[u8])> + '_ {
+        let mut data_start = 0;
+        self.entries.iter().map(move |entry| {
+            let data = &self.data[data_start..entry.data_end];
+            data_start = entry.data_end;
+            (entry.command, data)
+        })
+    }
+
+    /// Sends the queued commands and data to the display and empties the queue.
+    pub fn flush<DI: DisplayInterface>(&mut self, display_interface: &mut DI) {
+        if !self.is_empty() {
+            display_interface.send_queue(self);
+        }
+        self.clear();
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::format;
+    use std::vec;
+    use std::vec::Vec;
+
+    use crate::hal::mock::{Event, display_interface, new_log};
+
+    use super::*;
+
+    fn entries<const N: usize>(queue: &CommandQueue<N>) -> Vec<(Option<u8>, Vec<u8>)> {
+        queue.entries()
+            .map(|(command, data)| (command, data.to_vec()))
+            .collect()
+    }
+
+    #[test]
+    fn test_push() {
+        let mut queue = CommandQueue::<16>::new();
+        assert!(queue.is_empty());
+        queue.push_data(&[1, 2]).unwrap();
+        queue.push(&DisplayCommand::DisplayOn).unwrap();
+        queue.push(&DisplayCommand::SetMasterContrast { contrast: 0x0A }).unwrap();
+        queue.push_data(&[3]).unwrap();
+        assert_eq!(
+            entries(&queue),
+            vec![(None, vec![1, 2]), (Some(0xAF), vec![]), (Some(0xC7), vec![0x0A, 3])],
+        );
+        assert_eq!(queue.byte_count(), 6);
+
+        queue.clear();
+        assert!(queue.is_empty());
+        assert_eq!(queue.entries().count(), 0);
+    }
+
+    #[test]
+    fn test_full() {
+        let mut queue = CommandQueue::<3>::new();
+        queue.push(&DisplayCommand::SetColumnAddress { start: 0, end: 1 }).unwrap();
+        assert_eq!(
+            queue.push(&DisplayCommand::SetRowAddress { start: 0, end: 1 }),
+            Err(QueueFullError),
+        );
+        assert_eq!(queue.push_data(&[0; 2]), Err(QueueFullError));
+        queue.push_data(&[0; 1]).unwrap();
+        queue.push(&DisplayCommand::WriteRam).unwrap();
+
+        let mut queue = CommandQueue::<1>::new();
+        for _ in 0..MAX_QUEUED_COMMANDS {
+            queue.push(&DisplayCommand::NopD1).unwrap();
+        }
+        assert_eq!(queue.push(&DisplayCommand::NopD1), Err(QueueFullError));
+        assert_eq!(format!("{}", QueueFullError), "command queue is full");
+    }
+
+    #[test]
+    fn test_area_update() {
+        let layout = DisplayLayout::PSP27801;
+        let mut queue = CommandQueue::<16>::new();
+        queue.push_area_update(&layout, Rect::new(4, 10, 2, 1), &[7; 4]).unwrap();
+        assert_eq!(
+            entries(&queue),
+            vec![
+                (Some(0x15), vec![20, 21]),
+                (Some(0x75), vec![10, 10]),
+                (Some(0x5C), vec![7; 4]),
+            ],
+        );
+
+        // an update that does not fit leaves the queue as it was
+        assert_eq!(
+            queue.push_area_update(&layout, Rect::new(0, 0, 2, 2), &[7; 8]),
+            Err(QueueFullError),
+        );
+        assert_eq!(queue.byte_count(), 3 + 8);
+    }
+
+    #[test]
+    fn test_single_transaction() {
+        let log = new_log();
+        let mut display = display_interface(&log);
+
+        let mut queue = CommandQueue::<16>::new();
+        queue.push_area_update(&DisplayLayout::PSP27801, Rect::new(0, 0, 1, 1), &[0xAB, 0xCD])
+            .unwrap();
+        queue.flush(&mut display);
+        assert!(queue.is_empty());
+        assert_eq!(
+            *log.borrow(),
+            vec![
+                Event::PinLow("cs"),
+                Event::PinLow("dc"),
+                Event::SpiWrite(vec![0x15]),
+                Event::PinHigh("dc"),
+                Event::SpiWrite(vec![16, 16]),
+                Event::PinLow("dc"),
+                Event::SpiWrite(vec![0x75]),
+                Event::PinHigh("dc"),
+                Event::SpiWrite(vec![0, 0]),
+                Event::PinLow("dc"),
+                Event::SpiWrite(vec![0x5C]),
+                Event::PinHigh("dc"),
+                Event::SpiWrite(vec![0xAB, 0xCD]),
+                Event::PinHigh("cs"),
+            ],
+        );
+
+        // flushing an empty queue does nothing
+        log.borrow_mut().clear();
+        queue.flush(&mut display);
+        assert!(log.borrow().is_empty());
+    }
+}
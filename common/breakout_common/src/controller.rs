@@ -0,0 +1,170 @@
+//! Display controllers that can show the game, behind a common interface.
+//!
+//! The game was written for the SSD1351 and describes what the display should do (e.g. in
+//! [`BurnInGuard`] and [`Effects`]) using its [`DisplayCommand`]s. Other controllers translate
+//! these commands into their own as far as they have an equivalent.
+//!
+//! [`BurnInGuard`]: crate::burn_in::BurnInGuard
+//! [`Effects`]: crate::effects::Effects
+
+
+use crate::brightness::Brightness;
+use crate::command_queue::CommandQueue;
+use crate::display::{DisplayInterface, initialize};
+use crate::display_config::DisplayConfig;
+use crate::layout::{DisplayLayout, Rect};
+use crate::ssd1351::DisplayCommand;
+
+
+/// Operations that every supported display controller offers.
+pub trait DisplayController {
+    /// Prepares a powered-up controller that has just left reset for showing images with the given
+    /// layout, clears its RAM and turns the display on.
+    fn initialize<DI: DisplayInterface>(&self, display_interface: &mut DI, layout: &DisplayLayout);
+
+    /// Points the controller's RAM window at an area of the display and prepares for receiving its
+    /// pixels in row-major order, which are then sent as data.
+    fn start_writing_area<DI: DisplayInterface>(
+        &self,
+        display_interface: &mut DI,
+        layout: &DisplayLayout,
+        area: Rect,
+    );
+
+    /// Sends an SSD1351 command, or the equivalent command of this controller.
+    ///
+    /// Returns `false` (and sends nothing) if this controller has no equivalent.
+    fn transmit<DI: DisplayInterface>(
+        &self,
+        display_interface: &mut DI,
+        command: &DisplayCommand<'_>,
+    ) -> bool;
+
+    /// Turns the display off and puts the controller to sleep, or wakes it up again.
+    fn set_sleeping<DI: DisplayInterface>(&self, display_interface: &mut DI, sleeping: bool) {
+        let command = if sleeping { DisplayCommand::DisplayOff } else { DisplayCommand::DisplayOn };
+        self.transmit(display_interface, &command);
+    }
+
+    /// Changes the brightness of a running display.
+    fn set_brightness<DI: DisplayInterface>(
+        &self,
+        display_interface: &mut DI,
+        brightness: Brightness,
+    ) {
+        self.transmit(display_interface, &brightness.command());
+    }
+}
+
+
+/// The SSD1351, set up with the given panel settings.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct Ssd1351Controller {
+    pub config: DisplayConfig,
+}
+impl Ssd1351Controller {
+    #[inline]
+    pub const fn new(config: DisplayConfig) -> Self {
+        Self { config }
+    }
+}
+impl DisplayController for Ssd1351Controller {
+    /// Panics if the panel settings are invalid.
+    fn initialize<DI: DisplayInterface>(&self, display_interface: &mut DI, layout: &DisplayLayout) {
+        initialize(display_interface, layout, &self.config);
+    }
+
+    fn start_writing_area<DI: DisplayInterface>(
+        &self,
+        display_interface: &mut DI,
+        layout: &DisplayLayout,
+        area: Rect,
+    ) {
+        // the three commands take 4 bytes of parameters
+        let mut queue = CommandQueue::<4>::new();
+        queue.push_window(layout, area)
+            .expect("window commands do not fit into queue");
+        queue.flush(display_interface);
+    }
+
+    fn transmit<DI: DisplayInterface>(
+        &self,
+        display_interface: &mut DI,
+        command: &DisplayCommand<'_>,
+    ) -> bool {
+        command.transmit(display_interface);
+        true
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::vec;
+    use std::vec::Vec;
+
+    use crate::hal::mock::{Event, display_events};
+
+    use super::*;
+
+    #[test]
+    fn test_ssd1351_initialize() {
+        let controller = Ssd1351Controller::new(DisplayConfig::PSP27801);
+        let layout = DisplayLayout::PSP27801;
+        let expected =
+            display_events(|display| initialize(display, &layout, &DisplayConfig::PSP27801));
+        assert_eq!(display_events(|display| controller.initialize(display, &layout)), expected);
+    }
+
+    #[test]
+    fn test_ssd1351_window() {
+        let controller = Ssd1351Controller::new(DisplayConfig::PSP27801);
+        let area = Rect::new(4, 10, 2, 3);
+        let layout = DisplayLayout::PSP27801;
+        assert_eq!(
+            display_events(|display| controller.start_writing_area(display, &layout, area)),
+            vec![
+                Event::PinLow("cs"),
+                Event::PinLow("dc"),
+                Event::SpiWrite(vec![0x15]),
+                Event::PinHigh("dc"),
+                Event::SpiWrite(vec![20, 21]),
+                Event::PinLow("dc"),
+                Event::SpiWrite(vec![0x75]),
+                Event::PinHigh("dc"),
+                Event::SpiWrite(vec![10, 12]),
+                Event::PinLow("dc"),
+                Event::SpiWrite(vec![0x5C]),
+                Event::PinHigh("dc"),
+                Event::PinHigh("cs"),
+            ],
+        );
+    }
+
+    #[test]
+    fn test_ssd1351_sleep_and_brightness() {
+        let controller = Ssd1351Controller::new(DisplayConfig::PSP27801);
+        let written = |events: Vec<Event>| -> Vec<Vec<u8>> {
+            events.into_iter()
+                .filter_map(|event| match event {
+                    Event::SpiWrite(data) => Some(data),
+                    _ => None,
+                })
+                .collect()
+        };
+        assert_eq!(
+            written(display_events(|display| controller.set_sleeping(display, true))),
+            vec![vec![0xAE], vec![]],
+        );
+        assert_eq!(
+            written(display_events(|display| controller.set_sleeping(display, false))),
+            vec![vec![0xAF], vec![]],
+        );
+        assert_eq!(
+            written(display_events(|display| controller.set_brightness(display, Brightness::MIN))),
+            vec![vec![0xC7], vec![0x01]],
+        );
+    }
+}
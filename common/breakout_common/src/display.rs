@@ -0,0 +1,496 @@
+//! Driver for SSD1351 displays connected via SPI.
+
+
+use arrayvec::ArrayVec;
+
+use crate::command_queue::CommandQueue;
+use crate::controller::DisplayController;
+use crate::display_config::DisplayConfig;
+use crate::hal::{BackgroundSpiBus, OutputPin, SpiDevice};
+use crate::layout::{BYTES_PER_PIXEL, CONTROLLER_RAM_EXTENT, DisplayLayout, Rect};
+use crate::ssd1351::{AddressIncrement, ColorDepth, DisplayCommand};
+
+
+/// Low-level interface to the display.
+pub trait DisplayInterface {
+    fn send(&mut self, command: Option<u8>, data: &[u8]);
+    fn receive(&mut self, command: Option<u8>, buffer: &mut [u8]);
+
+    /// Sends all commands and data in the queue, in order.
+    ///
+    /// By default, each command is sent on its own; interfaces that can keep the display selected
+    /// from one command to the next should do so.
+    fn send_queue<const N: usize>(&mut self, queue: &CommandQueue<N>) {
+        for (command, data) in queue.entries() {
+            self.send(command, data);
+        }
+    }
+}
+
+
+/// A display connected via SPI, with a data/~command pin.
+pub struct SpiDisplayInterface<SPI, DC> {
+    spi: SPI,
+    data_command: DC,
+    sending_in_background: bool,
+}
+impl<SPI: SpiDevice, DC: OutputPin> SpiDisplayInterface<SPI, DC> {
+    /// Takes over the device and the data/~command pin.
+    pub fn new(spi: SPI, mut data_command: DC) -> Self {
+        data_command.set_high();
+        Self {
+            spi,
+            data_command,
+            sending_in_background: false,
+        }
+    }
+
+    /// Sends the command byte (if any) with the data/~command pin pulled low. You must select the
+    /// display before calling this function!
+    fn send_command_byte(&mut self, command: Option<u8>) {
+        if let Some(cmd) = command {
+            self.data_command.set_low();
+            self.spi.write(&[cmd]);
+
+            // always pull data/~command pin back up
+            self.data_command.set_high();
+        }
+    }
+
+    /// Releases the device and the data/~command pin.
+    pub fn release(self) -> (SPI, DC) {
+        debug_assert!(!self.sending_in_background);
+        (self.spi, self.data_command)
+    }
+}
+impl<SPI: SpiDevice + BackgroundSpiBus, DC: OutputPin> SpiDisplayInterface<SPI, DC> {
+    /// Sends the command byte (if any), then starts sending the first `length` bytes of the buffer
+    /// in the background. The display stays selected until the transfer is finished.
+    ///
+    /// A previous background transfer is finished first; its buffer is returned.
+    pub fn start_send(
+        &mut self,
+        command: Option<u8>,
+        buffer: &'static mut [u8],
+        length: usize,
+    ) -> Option<&'static mut [u8]> {
+        let previous = self.finish_send();
+
+        // select the display controller
+        self.spi.select();
+
+        self.send_command_byte(command);
+        self.spi.start_write(buffer, length);
+        self.sending_in_background = true;
+
+        previous
+    }
+
+    /// Waits for the background transfer (if any) to end, unselects the display and returns the
+    /// buffer of the transfer.
+    ///
+    /// This must be called before [`DisplayInterface::send`] or [`DisplayInterface::receive`] may
+    /// be used again, or before the bus is used to talk to another device.
+    pub fn finish_send(&mut self) -> Option<&'static mut [u8]> {
+        if !self.sending_in_background {
+            return None;
+        }
+        let buffer = self.spi.finish_write();
+        self.sending_in_background = false;
+
+        // unselect the display controller
+        self.spi.deselect();
+
+        buffer
+    }
+}
+impl<SPI: SpiDevice, DC: OutputPin> DisplayInterface for SpiDisplayInterface<SPI, DC> {
+    fn send(&mut self, command: Option<u8>, data: &[u8]) {
+        debug_assert!(!self.sending_in_background);
+
+        // select the display controller
+        self.spi.select();
+
+        self.send_command_byte(command);
+        self.spi.write(data);
+
+        // unselect the display controller
+        self.spi.deselect();
+    }
+
+    fn receive(&mut self, command: Option<u8>, buffer: &mut [u8]) {
+        debug_assert!(!self.sending_in_background);
+
+        // send zeroes
+        for b in &mut *buffer {
+            *b = 0x00;
+        }
+
+        // select the display controller
+        self.spi.select();
+
+        self.send_command_byte(command);
+        self.spi.transfer(buffer);
+
+        // unselect the display controller
+        self.spi.deselect();
+    }
+
+    fn send_queue<const N: usize>(&mut self, queue: &CommandQueue<N>) {
+        debug_assert!(!self.sending_in_background);
+
+        // keep the display controller selected throughout
+        self.spi.select();
+
+        for (command, data) in queue.entries() {
+            self.send_command_byte(command);
+            if !data.is_empty() {
+                self.spi.write(data);
+            }
+        }
+
+        // unselect the display controller
+        self.spi.deselect();
+    }
+}
+
+
+impl DisplayCommand<'_> {
+    /// Sends this command and its parameters to the display.
+    pub fn transmit<DI: DisplayInterface>(&self, display_interface: &mut DI) {
+        debug_assert!(self.is_valid());
+        let (command_code, parameters) = self.encode();
+        display_interface.send(Some(command_code), &parameters);
+    }
+}
+
+
+/// Prepares a powered-up display controller that has just left reset for showing images with the
+/// given layout, applying the given panel settings.
+///
+/// Panics if the settings are invalid.
+pub fn initialize<DI: DisplayInterface>(
+    display_interface: &mut DI,
+    layout: &DisplayLayout,
+    config: &DisplayConfig,
+) {
+    // 0. apply the panel settings (this turns the display off)
+    let config_commands = config.commands()
+        .expect("invalid display configuration");
+    for command in &config_commands {
+        command.transmit(display_interface);
+    }
+
+    // 1. scan the RAM according to the requested orientation
+    let mapping = layout.orientation.scan_mapping();
+    (DisplayCommand::SetMapping {
+        address_increment: if mapping.vertical_increment {
+            AddressIncrement::Vertical
+        } else {
+            AddressIncrement::Horizontal
+        },
+        reverse_columns: mapping.reverse_columns,
+        swap_color_sequence: false,
+        backward_scan: mapping.backward_scan,
+        com_split_odd_even: true, // power-on default
+        color_depth: ColorDepth::Colors65k,
+    })
+        .transmit(display_interface);
+
+    // 2. clear out display RAM
+    let last = (CONTROLLER_RAM_EXTENT - 1) as u8;
+    (DisplayCommand::SetColumnAddress { start: 0, end: last })
+        .transmit(display_interface);
+    (DisplayCommand::SetRowAddress { start: 0, end: last })
+        .transmit(display_interface);
+    DisplayCommand::WriteRam.transmit(display_interface);
+    for _ in 0..(CONTROLLER_RAM_EXTENT*CONTROLLER_RAM_EXTENT)/32 {
+        let chunk = [0u8; 32*2];
+        display_interface.send(None, &chunk);
+    }
+
+    // 3. start at row and column as actually connected to the display
+    let window = layout.ram_window();
+    (DisplayCommand::SetColumnAddress {
+        start: window.left as u8,
+        end: (window.right() - 1) as u8,
+    })
+        .transmit(display_interface);
+    (DisplayCommand::SetRowAddress {
+        start: window.top as u8,
+        end: (window.bottom() - 1) as u8,
+    })
+        .transmit(display_interface);
+
+    // 4. stop sleeping
+    DisplayCommand::DisplayOn.transmit(display_interface);
+}
+
+
+/// Redraws an area of the display without touching the rest of it.
+///
+/// The controller's RAM window is pointed at the area, then `draw` is called to render the area in
+/// chunks of as many whole rows as fit into `buffer`, each of which is sent to the display. `draw`
+/// receives the part of the display to render and a buffer of exactly the right size for it, e.g.
+/// [`Playfield::draw_area`]. The RAM window is left pointing at the area.
+///
+/// [`Playfield::draw_area`]: crate::playfield::Playfield::draw_area
+pub fn update_area<DI, C, F>(
+    display_interface: &mut DI,
+    controller: &C,
+    layout: &DisplayLayout,
+    area: Rect,
+    buffer: &mut [u8],
+    mut draw: F,
+)
+where
+    DI: DisplayInterface,
+    C: DisplayController,
+    F: FnMut(Rect, &mut [u8]),
+{
+    if area.is_empty() {
+        return;
+    }
+    let row_bytes = area.width * BYTES_PER_PIXEL;
+    let chunk_rows = buffer.len() / row_bytes;
+    assert!(chunk_rows > 0);
+
+    controller.start_writing_area(display_interface, layout, area);
+
+    for first_row in (area.top..area.bottom()).step_by(chunk_rows) {
+        let rows = chunk_rows.min(area.bottom() - first_row);
+        let chunk = &mut buffer[..rows * row_bytes];
+        draw(Rect::new(area.left, first_row, area.width, rows), chunk);
+        display_interface.send(None, chunk);
+    }
+}
+
+
+/// Two equally large buffers for [`update_area_in_background`]: while one of them is being sent to
+/// the display, the next part of the image is rendered into the other.
+pub struct DoubleBuffer {
+    free: ArrayVec<&'static mut [u8], 2>,
+}
+impl DoubleBuffer {
+    pub fn new(first: &'static mut [u8], second: &'static mut [u8]) -> Self {
+        debug_assert_eq!(first.len(), second.len());
+        let mut free = ArrayVec::new();
+        free.push(first);
+        free.push(second);
+        Self { free }
+    }
+
+    /// Waits for the display to finish sending in the background and takes the buffer back.
+    pub fn reclaim<SPI, DC>(&mut self, display: &mut SpiDisplayInterface<SPI, DC>)
+    where
+        SPI: SpiDevice + BackgroundSpiBus,
+        DC: OutputPin,
+    {
+        if let Some(buffer) = display.finish_send() {
+            self.free.push(buffer);
+        }
+    }
+
+    /// Whether both buffers are available, i.e. nothing is being sent in the background.
+    pub fn is_idle(&self) -> bool { self.free.is_full() }
+}
+
+
+/// Like [`update_area`], but renders each chunk of the area while the previous one is being sent
+/// in the background.
+///
+/// Returns while the last chunk is still being sent, so that the caller can get on with other work.
+/// Call [`DoubleBuffer::reclaim`] before using the bus for anything else.
+pub fn update_area_in_background<SPI, DC, C, F>(
+    display: &mut SpiDisplayInterface<SPI, DC>,
+    controller: &C,
+    layout: &DisplayLayout,
+    area: Rect,
+    buffers: &mut DoubleBuffer,
+    mut draw: F,
+)
+where
+    SPI: SpiDevice + BackgroundSpiBus,
+    DC: OutputPin,
+    C: DisplayController,
+    F: FnMut(Rect, &mut [u8]),
+{
+    if area.is_empty() {
+        return;
+    }
+    buffers.reclaim(display);
+    let row_bytes = area.width * BYTES_PER_PIXEL;
+    let chunk_rows = buffers.free[0].len() / row_bytes;
+    assert!(chunk_rows > 0);
+
+    controller.start_writing_area(display, layout, area);
+
+    for first_row in (area.top..area.bottom()).step_by(chunk_rows) {
+        let rows = chunk_rows.min(area.bottom() - first_row);
+        let buffer = buffers.free.pop()
+            .expect("no buffer available");
+        draw(Rect::new(area.left, first_row, area.width, rows), &mut buffer[..rows * row_bytes]);
+        if let Some(previous) = display.start_send(None, buffer, rows * row_bytes) {
+            buffers.free.push(previous);
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::vec;
+
+    use crate::controller::Ssd1351Controller;
+    use crate::hal::mock::{
+        Event, MockBackgroundSpi, MockSpi, display_interface, display_interface_on, new_log,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_send_command() {
+        let log = new_log();
+        let mut display = display_interface(&log);
+
+        (DisplayCommand::SetColumnAddress { start: 16, end: 111 })
+            .transmit(&mut display);
+        assert_eq!(
+            *log.borrow(),
+            vec![
+                Event::PinLow("cs"),
+                Event::PinLow("dc"),
+                Event::SpiWrite(vec![0x15]),
+                Event::PinHigh("dc"),
+                Event::SpiWrite(vec![16, 111]),
+                Event::PinHigh("cs"),
+            ],
+        );
+    }
+
+    #[test]
+    fn test_receive() {
+        let log = new_log();
+        let mut display =
+            display_interface_on(&log, MockSpi { log: log.clone(), responses: vec![0xAB, 0xCD] });
+
+        let mut buffer = [0xFF; 2];
+        display.receive(None, &mut buffer);
+        assert_eq!(buffer, [0xAB, 0xCD]);
+        assert_eq!(
+            *log.borrow(),
+            vec![
+                Event::PinLow("cs"),
+                Event::SpiTransfer(vec![0x00, 0x00]),
+                Event::PinHigh("cs"),
+            ],
+        );
+    }
+
+    #[test]
+    fn test_initialize() {
+        let log = new_log();
+        let mut display = display_interface(&log);
+        initialize(&mut display, &DisplayLayout::PSP27801, &DisplayConfig::PSP27801);
+
+        // collect what was sent to the controller while it was selected
+        let log = log.borrow();
+        let written: usize = log.iter()
+            .map(|event| match event {
+                Event::SpiWrite(data) => data.len(),
+                _ => 0,
+            })
+            .sum();
+        let config_bytes = 2 + 2 + 1 + 2 + 2 + 2 + 2 + 2 + 2 + 4 + 2 + 2 + 2 + 4 + 2 + 1;
+        assert_eq!(written, config_bytes + 2 + 3 + 3 + 1 + 128*128*2 + 3 + 3 + 1);
+        assert_eq!(log.last(), Some(&Event::PinHigh("cs")));
+        assert!(log.contains(&Event::SpiWrite(vec![16, 111])));
+        assert!(log.contains(&Event::SpiWrite(vec![0xAF])));
+    }
+
+    /// Leaks a buffer so that it can be handed to a background transfer.
+    fn static_buffer(length: usize) -> &'static mut [u8] {
+        std::boxed::Box::leak(vec![0u8; length].into_boxed_slice())
+    }
+
+    #[test]
+    fn test_update_area_in_background() {
+        let log = new_log();
+        let mut display =
+            display_interface_on(&log, MockBackgroundSpi { log: log.clone(), in_flight: None });
+
+        // 3 rows of 2 pixels, 2 rows per buffer
+        let mut buffers = DoubleBuffer::new(static_buffer(8), static_buffer(8));
+        update_area_in_background(
+            &mut display, &Ssd1351Controller::new(DisplayConfig::PSP27801),
+            &DisplayLayout::PSP27801, Rect::new(4, 10, 2, 3), &mut buffers,
+            |part, chunk| chunk.fill(part.top as u8),
+        );
+        assert!(!buffers.is_idle());
+        buffers.reclaim(&mut display);
+        assert!(buffers.is_idle());
+
+        let log = log.borrow();
+        assert_eq!(
+            log[log.len() - 8..],
+            [
+                Event::PinLow("cs"),
+                Event::SpiWriteStarted(vec![10; 8]),
+                Event::SpiWriteFinished,
+                Event::PinHigh("cs"),
+                Event::PinLow("cs"),
+                Event::SpiWriteStarted(vec![12; 4]),
+                Event::SpiWriteFinished,
+                Event::PinHigh("cs"),
+            ],
+        );
+
+        // nothing else happens while data is being sent in the background
+        let mut in_flight = false;
+        for event in log.iter() {
+            match event {
+                Event::SpiWriteStarted(_) => in_flight = true,
+                Event::SpiWriteFinished => in_flight = false,
+                _ => assert!(!in_flight, "{:?} during background transfer", event),
+            }
+        }
+    }
+
+    #[test]
+    fn test_update_area() {
+        let log = new_log();
+        let mut display = display_interface(&log);
+
+        // 3 rows of 2 pixels, but only room for 2 rows at once
+        let mut buffer = [0u8; 2 * 2 * BYTES_PER_PIXEL + 1];
+        let mut drawn = vec![];
+        update_area(
+            &mut display, &Ssd1351Controller::new(DisplayConfig::PSP27801),
+            &DisplayLayout::PSP27801, Rect::new(4, 10, 2, 3), &mut buffer,
+            |part, chunk| {
+                drawn.push(part);
+                chunk.fill(part.top as u8);
+            },
+        );
+        assert_eq!(drawn, vec![Rect::new(4, 10, 2, 2), Rect::new(4, 12, 2, 1)]);
+
+        let written: std::vec::Vec<_> = log.borrow().iter()
+            .filter_map(|event| match event {
+                Event::SpiWrite(data) => Some(data.clone()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            written,
+            vec![
+                vec![0x15], vec![20, 21],
+                vec![0x75], vec![10, 12],
+                vec![0x5C],
+                vec![10; 8],
+                vec![12; 4],
+            ],
+        );
+    }
+}
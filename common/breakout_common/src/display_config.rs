@@ -0,0 +1,286 @@
+//! The analog and timing settings of the SSD1351 and the commands that apply them.
+
+
+use core::fmt;
+
+use arrayvec::ArrayVec;
+
+use crate::ssd1351::{DisplayCommand, LockState, MAX_PARAMETER_COUNT, ParallelInterface};
+
+
+/// The number of entries in the grayscale lookup table.
+pub const GRAYSCALE_TABLE_LENGTH: usize = MAX_PARAMETER_COUNT;
+
+/// The maximum number of commands produced by [`DisplayConfig::commands`].
+pub const MAX_CONFIG_COMMANDS: usize = 16;
+
+/// The commands that apply a [`DisplayConfig`], in the order in which they must be sent.
+pub type ConfigCommands<'a> = ArrayVec<DisplayCommand<'a>, MAX_CONFIG_COMMANDS>;
+
+
+/// A setting of a [`DisplayConfig`] is outside the range accepted by the controller.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct InvalidConfigError {
+    /// The code of the command that would apply the setting.
+    pub command_code: u8,
+}
+impl fmt::Display for InvalidConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid parameters for command 0x{:02X}", self.command_code)
+    }
+}
+
+
+/// The settings of the SSD1351 that depend on the panel rather than on the image shown on it.
+///
+/// The values are the raw parameters of the respective commands. Start from one of the presets
+/// and adjust it using the `with_` methods; [`DisplayConfig::commands`] checks the result.
+///
+/// The start line and display offset are always set to 0 and the whole RAM is scanned by default,
+/// since [`DisplayLayout::ram_window`] expects the RAM rows to map directly onto the commons.
+///
+/// [`DisplayLayout::ram_window`]: crate::layout::DisplayLayout::ram_window
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct DisplayConfig {
+    /// The number of commons that are scanned, minus 1.
+    pub mux_ratio: u8,
+
+    /// The logarithm of the divider of the display clock.
+    pub front_clock_divider_2pow: u8,
+
+    /// The frequency of the internal oscillator, from 0 (slowest) to 15 (fastest).
+    pub oscillator_frequency: u8,
+
+    /// The lengths of the reset and first pre-charge phases, in display clocks.
+    pub phase_periods: (u8, u8),
+
+    /// The length of the second pre-charge phase, in display clocks.
+    pub second_pre_charge_period: u8,
+
+    /// The pre-charge voltage, in steps from 0.2 to 0.6 times VCC.
+    pub pre_charge_voltage_level: u8,
+
+    /// The COM deselect voltage, in steps from 0.72 to 0.86 times VCC.
+    pub com_deselect_voltage_level: u8,
+
+    /// The contrast of color A, B and C.
+    pub color_contrast: [u8; 3],
+
+    /// The master contrast, scaling all colors from 1/16 to 16/16.
+    pub master_contrast: u8,
+
+    /// The grayscale lookup table, e.g. one of [`crate::grayscale`]; `None` selects the built-in
+    /// linear table.
+    pub grayscale_table: Option<[u8; GRAYSCALE_TABLE_LENGTH]>,
+
+    /// Whether the display enhancement is enabled.
+    pub enhance_display: bool,
+
+    /// Whether the internal VDD regulator is used.
+    pub internal_vdd_regulator: bool,
+}
+impl DisplayConfig {
+    /// The settings of the SSD1351 after a reset.
+    pub const POWER_ON_DEFAULTS: Self = Self {
+        mux_ratio: 127,
+        front_clock_divider_2pow: 0b0001,
+        oscillator_frequency: 0b1101,
+        phase_periods: (2, 8),
+        second_pre_charge_period: 8,
+        pre_charge_voltage_level: 0x17,
+        com_deselect_voltage_level: 0x05,
+        color_contrast: [0x8A, 0x51, 0x8A],
+        master_contrast: 0x0F,
+        grayscale_table: None,
+        enhance_display: false,
+        internal_vdd_regulator: true,
+    };
+
+    /// Settings for the 96x96 PSP27801 panel as found on the OLED C click.
+    pub const PSP27801: Self = Self {
+        front_clock_divider_2pow: 0b0001,
+        oscillator_frequency: 0b1111,
+        phase_periods: (2, 3),
+        second_pre_charge_period: 1,
+        color_contrast: [0xC8, 0x80, 0xC8],
+        ..Self::POWER_ON_DEFAULTS
+    };
+
+    /// Settings for a 128x128 panel using the whole RAM of the SSD1351.
+    pub const SSD1351_128X128: Self = Self {
+        front_clock_divider_2pow: 0b0001,
+        oscillator_frequency: 0b1111,
+        phase_periods: (2, 3),
+        second_pre_charge_period: 1,
+        color_contrast: [0xC8, 0x80, 0xC8],
+        enhance_display: true,
+        ..Self::POWER_ON_DEFAULTS
+    };
+
+    pub const fn with_mux_ratio(self, mux_ratio: u8) -> Self {
+        Self { mux_ratio, ..self }
+    }
+
+    pub const fn with_clock(self, front_clock_divider_2pow: u8, oscillator_frequency: u8) -> Self {
+        Self { front_clock_divider_2pow, oscillator_frequency, ..self }
+    }
+
+    pub const fn with_phase_periods(self, phase1: u8, phase2: u8) -> Self {
+        Self { phase_periods: (phase1, phase2), ..self }
+    }
+
+    pub const fn with_second_pre_charge_period(self, second_pre_charge_period: u8) -> Self {
+        Self { second_pre_charge_period, ..self }
+    }
+
+    pub const fn with_pre_charge_voltage_level(self, pre_charge_voltage_level: u8) -> Self {
+        Self { pre_charge_voltage_level, ..self }
+    }
+
+    pub const fn with_com_deselect_voltage_level(self, com_deselect_voltage_level: u8) -> Self {
+        Self { com_deselect_voltage_level, ..self }
+    }
+
+    pub const fn with_color_contrast(self, color_contrast: [u8; 3]) -> Self {
+        Self { color_contrast, ..self }
+    }
+
+    pub const fn with_master_contrast(self, master_contrast: u8) -> Self {
+        Self { master_contrast, ..self }
+    }
+
+    pub const fn with_grayscale_table(
+        self,
+        grayscale_table: Option<[u8; GRAYSCALE_TABLE_LENGTH]>,
+    ) -> Self {
+        Self { grayscale_table, ..self }
+    }
+
+    pub const fn with_display_enhancement(self, enhance_display: bool) -> Self {
+        Self { enhance_display, ..self }
+    }
+
+    pub const fn with_internal_vdd_regulator(self, internal_vdd_regulator: bool) -> Self {
+        Self { internal_vdd_regulator, ..self }
+    }
+
+    /// The commands that apply these settings, in order, starting with unlocking all commands and
+    /// turning the display off. Fails if any of the commands is invalid.
+    pub fn commands(&self) -> Result<ConfigCommands<'_>, InvalidConfigError> {
+        let (phase1, phase2) = self.phase_periods;
+        let [color1_contrast, color2_contrast, color3_contrast] = self.color_contrast;
+        let grayscale = match &self.grayscale_table {
+            Some(table) => DisplayCommand::SetGrayscaleLookUpTable { table },
+            None => DisplayCommand::ResetGrayscaleLookUpTable,
+        };
+
+        let commands = [
+            // the analog settings are advanced commands
+            DisplayCommand::SetCommandLock { state: LockState::CommandsUnlocked },
+            DisplayCommand::SetCommandLock { state: LockState::AdvancedCommandsUnlocked },
+            DisplayCommand::DisplayOff,
+            DisplayCommand::SetFrequency {
+                front_clock_divider_2pow: self.front_clock_divider_2pow,
+                oscillator_frequency: self.oscillator_frequency,
+            },
+            DisplayCommand::SetMuxRatio { ratio: self.mux_ratio },
+            DisplayCommand::SetDisplayStartLine { line: 0 },
+            DisplayCommand::SetDisplayOffset { offset: 0 },
+            DisplayCommand::FunctionSelection {
+                internal_vdd_regulator: self.internal_vdd_regulator,
+                interface: ParallelInterface::EightBit,
+            },
+            DisplayCommand::SetPhasePeriods { phase1, phase2 },
+            DisplayCommand::DisplayEnhancement { enhance: self.enhance_display },
+            DisplayCommand::SetPreChargeVoltageLevel {
+                voltage_level: self.pre_charge_voltage_level,
+            },
+            DisplayCommand::SetSecondPreChargePeriod { period: self.second_pre_charge_period },
+            DisplayCommand::SetComDeselectVoltageLevel {
+                voltage_level: self.com_deselect_voltage_level,
+            },
+            DisplayCommand::SetColorContrast { color1_contrast, color2_contrast, color3_contrast },
+            DisplayCommand::SetMasterContrast { contrast: self.master_contrast },
+            grayscale,
+        ];
+
+        let mut valid_commands = ConfigCommands::new();
+        for command in commands {
+            if !command.is_valid() {
+                return Err(InvalidConfigError { command_code: command.as_command_code() });
+            }
+            valid_commands.push(command);
+        }
+        Ok(valid_commands)
+    }
+}
+impl Default for DisplayConfig {
+    fn default() -> Self { Self::POWER_ON_DEFAULTS }
+}
+
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::format;
+
+    use super::*;
+
+    #[test]
+    fn test_presets_are_valid() {
+        for config in [
+            DisplayConfig::POWER_ON_DEFAULTS,
+            DisplayConfig::PSP27801,
+            DisplayConfig::SSD1351_128X128,
+        ] {
+            let commands = config.commands()
+                .expect("preset is invalid");
+            assert_eq!(commands.len(), MAX_CONFIG_COMMANDS);
+            assert_eq!(
+                commands[0],
+                DisplayCommand::SetCommandLock { state: LockState::CommandsUnlocked },
+            );
+            assert!(commands.iter().all(|command| command.is_valid()));
+        }
+    }
+
+    #[test]
+    fn test_builder() {
+        let table = [3u8; GRAYSCALE_TABLE_LENGTH];
+        let config = DisplayConfig::PSP27801
+            .with_mux_ratio(95)
+            .with_master_contrast(0x08)
+            .with_grayscale_table(Some(table));
+        let commands = config.commands()
+            .expect("config is invalid");
+        assert!(commands.contains(&DisplayCommand::SetMuxRatio { ratio: 95 }));
+        assert!(commands.contains(&DisplayCommand::SetMasterContrast { contrast: 0x08 }));
+        assert_eq!(
+            commands.last(),
+            Some(&DisplayCommand::SetGrayscaleLookUpTable { table: &table }),
+        );
+        assert_eq!(config.color_contrast, DisplayConfig::PSP27801.color_contrast);
+    }
+
+    #[test]
+    fn test_invalid_settings() {
+        let cases = [
+            (DisplayConfig::PSP27801.with_mux_ratio(14), 0xCA),
+            (DisplayConfig::PSP27801.with_phase_periods(1, 3), 0xB1),
+            (DisplayConfig::PSP27801.with_phase_periods(2, 16), 0xB1),
+            (DisplayConfig::PSP27801.with_clock(0b1011, 0), 0xB3),
+            (DisplayConfig::PSP27801.with_second_pre_charge_period(0), 0xB6),
+            (DisplayConfig::PSP27801.with_pre_charge_voltage_level(0x20), 0xBB),
+            (DisplayConfig::PSP27801.with_com_deselect_voltage_level(0x08), 0xBE),
+            (DisplayConfig::PSP27801.with_master_contrast(0x10), 0xC7),
+        ];
+        for (config, command_code) in cases {
+            assert_eq!(config.commands(), Err(InvalidConfigError { command_code }));
+        }
+        assert_eq!(
+            format!("{}", InvalidConfigError { command_code: 0xB1 }),
+            "invalid parameters for command 0xB1",
+        );
+    }
+}
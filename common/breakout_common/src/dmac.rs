@@ -0,0 +1,316 @@
+//! Transfer descriptors for the SAM D21's Direct Memory Access Controller (DMAC).
+//!
+//! The DMAC reads its descriptors from RAM, so their layout is defined here bit by bit; this keeps
+//! it independent of the peripheral access crate and testable on the host.
+
+
+use core::fmt;
+
+
+/// The maximum number of beats in a single block transfer.
+pub const MAX_BLOCK_TRANSFER_COUNT: usize = u16::MAX as usize;
+
+const BTCTRL_VALID: u16 = 1 << 0;
+const BTCTRL_BLOCKACT_SHIFT: u16 = 3;
+const BTCTRL_BLOCKACT_MASK: u16 = 0b11 << BTCTRL_BLOCKACT_SHIFT;
+const BTCTRL_BEATSIZE_SHIFT: u16 = 8;
+const BTCTRL_BEATSIZE_MASK: u16 = 0b11 << BTCTRL_BEATSIZE_SHIFT;
+const BTCTRL_SRCINC: u16 = 1 << 10;
+const BTCTRL_DSTINC: u16 = 1 << 11;
+
+
+/// The trigger source with which a SERCOM requests a new byte to transmit.
+#[inline]
+pub const fn sercom_tx_trigger(sercom: u8) -> u8 { 0x02 + 2 * sercom }
+
+/// The trigger source with which a SERCOM signals that it has received a byte.
+#[inline]
+pub const fn sercom_rx_trigger(sercom: u8) -> u8 { 0x01 + 2 * sercom }
+
+
+/// The size of a single beat, i.e. of the data moved per trigger.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum BeatSize {
+    Byte,
+    HalfWord,
+    Word,
+}
+impl BeatSize {
+    pub const fn as_bits(&self) -> u16 {
+        match self {
+            Self::Byte => 0b00,
+            Self::HalfWord => 0b01,
+            Self::Word => 0b10,
+        }
+    }
+
+    pub const fn from_bits(bits: u16) -> Option<Self> {
+        match bits {
+            0b00 => Some(Self::Byte),
+            0b01 => Some(Self::HalfWord),
+            0b10 => Some(Self::Word),
+            _ => None,
+        }
+    }
+
+    /// The number of bytes in a beat.
+    pub const fn bytes(&self) -> usize {
+        match self {
+            Self::Byte => 1,
+            Self::HalfWord => 2,
+            Self::Word => 4,
+        }
+    }
+}
+
+
+/// What the DMAC does once a block transfer is complete.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum BlockAction {
+    /// Disable the channel if this was the last block transfer.
+    NoAction,
+
+    /// Additionally raise the transfer-complete interrupt.
+    Interrupt,
+
+    /// Suspend the channel.
+    Suspend,
+
+    /// Raise the interrupt and suspend the channel.
+    Both,
+}
+impl BlockAction {
+    pub const fn as_bits(&self) -> u16 {
+        match self {
+            Self::NoAction => 0b00,
+            Self::Interrupt => 0b01,
+            Self::Suspend => 0b10,
+            Self::Both => 0b11,
+        }
+    }
+
+    pub const fn from_bits(bits: u16) -> Self {
+        match bits & 0b11 {
+            0b00 => Self::NoAction,
+            0b01 => Self::Interrupt,
+            0b10 => Self::Suspend,
+            _ => Self::Both,
+        }
+    }
+}
+
+
+/// The reason why a transfer descriptor could not be created.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum DescriptorError {
+    /// There is nothing to transfer; the DMAC cannot transfer zero beats.
+    Empty,
+
+    /// More beats than fit into a single block transfer.
+    TooLong(usize),
+
+    /// The buffer extends beyond the end of the address space.
+    AddressOverflow,
+}
+impl fmt::Display for DescriptorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Empty => write!(f, "empty transfer"),
+            Self::TooLong(length)
+                => write!(f, "{} beats exceed the maximum of {}", length, MAX_BLOCK_TRANSFER_COUNT),
+            Self::AddressOverflow => write!(f, "buffer extends beyond the address space"),
+        }
+    }
+}
+
+
+/// A DMAC transfer descriptor, laid out exactly as the DMAC expects it in RAM.
+///
+/// When the address of a side is incremented, the DMAC expects the address just past the end of
+/// the data instead of its start; [`TransferDescriptor::memory_to_register`] takes care of this.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+#[repr(C, align(16))]
+pub struct TransferDescriptor {
+    block_transfer_control: u16,
+    block_transfer_count: u16,
+    source_address: u32,
+    destination_address: u32,
+    next_descriptor_address: u32,
+}
+impl TransferDescriptor {
+    /// A descriptor that the DMAC will not act upon.
+    pub const INVALID: Self = Self {
+        block_transfer_control: 0,
+        block_transfer_count: 0,
+        source_address: 0,
+        destination_address: 0,
+        next_descriptor_address: 0,
+    };
+
+    /// A descriptor for transferring `length` bytes, starting at the address `source`, one by one
+    /// into the peripheral register at the address `register`. This is the last descriptor of the
+    /// transfer; the channel is disabled once it is done.
+    pub const fn memory_to_register(
+        source: u32,
+        length: usize,
+        register: u32,
+    ) -> Result<Self, DescriptorError> {
+        if length == 0 {
+            return Err(DescriptorError::Empty);
+        }
+        if length > MAX_BLOCK_TRANSFER_COUNT {
+            return Err(DescriptorError::TooLong(length));
+        }
+        let source_end = match source.checked_add(length as u32) {
+            Some(end) => end,
+            None => return Err(DescriptorError::AddressOverflow),
+        };
+
+        Ok(Self {
+            block_transfer_control: BTCTRL_VALID
+                | (BlockAction::NoAction.as_bits() << BTCTRL_BLOCKACT_SHIFT)
+                | (BeatSize::Byte.as_bits() << BTCTRL_BEATSIZE_SHIFT)
+                | BTCTRL_SRCINC,
+            block_transfer_count: length as u16,
+            source_address: source_end,
+            destination_address: register,
+            next_descriptor_address: 0,
+        })
+    }
+
+    #[inline]
+    pub const fn is_valid(&self) -> bool { self.block_transfer_control & BTCTRL_VALID != 0 }
+
+    #[inline]
+    pub const fn block_action(&self) -> BlockAction {
+        BlockAction::from_bits(
+            (self.block_transfer_control & BTCTRL_BLOCKACT_MASK) >> BTCTRL_BLOCKACT_SHIFT
+        )
+    }
+
+    #[inline]
+    pub const fn beat_size(&self) -> Option<BeatSize> {
+        BeatSize::from_bits(
+            (self.block_transfer_control & BTCTRL_BEATSIZE_MASK) >> BTCTRL_BEATSIZE_SHIFT
+        )
+    }
+
+    #[inline]
+    pub const fn increments_source(&self) -> bool {
+        self.block_transfer_control & BTCTRL_SRCINC != 0
+    }
+
+    #[inline]
+    pub const fn increments_destination(&self) -> bool {
+        self.block_transfer_control & BTCTRL_DSTINC != 0
+    }
+
+    /// The raw value of the BTCTRL register.
+    #[inline]
+    pub const fn block_transfer_control(&self) -> u16 { self.block_transfer_control }
+
+    /// The number of beats in the block transfer.
+    #[inline]
+    pub const fn block_transfer_count(&self) -> u16 { self.block_transfer_count }
+
+    /// The source address as stored in the descriptor (past the end if it is incremented).
+    #[inline]
+    pub const fn source_address(&self) -> u32 { self.source_address }
+
+    /// The destination address as stored in the descriptor (past the end if it is incremented).
+    #[inline]
+    pub const fn destination_address(&self) -> u32 { self.destination_address }
+
+    /// The address of the descriptor of the next block transfer, or 0 if this is the last one.
+    #[inline]
+    pub const fn next_descriptor_address(&self) -> u32 { self.next_descriptor_address }
+}
+
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::format;
+
+    use super::*;
+
+    #[test]
+    fn test_layout() {
+        assert_eq!(core::mem::size_of::<TransferDescriptor>(), 16);
+        assert_eq!(core::mem::align_of::<TransferDescriptor>(), 16);
+
+        let descriptor = TransferDescriptor::memory_to_register(0x2000_0100, 96 * 2, 0x4200_0C28)
+            .expect("valid descriptor");
+
+        // UNSAFE: TransferDescriptor is repr(C) and consists of plain integers without padding
+        let words: [u32; 4] = unsafe { core::mem::transmute(descriptor) };
+        let btctrl_btcnt = if cfg!(target_endian = "little") {
+            (192 << 16) | 0x0401
+        } else {
+            (0x0401 << 16) | 192
+        };
+        assert_eq!(words, [btctrl_btcnt, 0x2000_01C0, 0x4200_0C28, 0]);
+    }
+
+    #[test]
+    fn test_memory_to_register() {
+        let descriptor = TransferDescriptor::memory_to_register(0x2000_0000, 1, 0x4200_1028)
+            .expect("valid descriptor");
+        assert!(descriptor.is_valid());
+        assert_eq!(descriptor.beat_size(), Some(BeatSize::Byte));
+        assert_eq!(descriptor.block_action(), BlockAction::NoAction);
+        assert!(descriptor.increments_source());
+        assert!(!descriptor.increments_destination());
+        assert_eq!(descriptor.block_transfer_count(), 1);
+        assert_eq!(descriptor.source_address(), 0x2000_0001);
+        assert_eq!(descriptor.destination_address(), 0x4200_1028);
+        assert_eq!(descriptor.next_descriptor_address(), 0);
+
+        assert!(!TransferDescriptor::INVALID.is_valid());
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(
+            TransferDescriptor::memory_to_register(0x2000_0000, 0, 0x4200_0C28),
+            Err(DescriptorError::Empty),
+        );
+        assert_eq!(
+            TransferDescriptor::memory_to_register(0x2000_0000, 65_536, 0x4200_0C28),
+            Err(DescriptorError::TooLong(65_536)),
+        );
+        assert!(TransferDescriptor::memory_to_register(0x2000_0000, 65_535, 0x4200_0C28).is_ok());
+        assert_eq!(
+            TransferDescriptor::memory_to_register(0xFFFF_FF00, 0x100, 0x4200_0C28),
+            Err(DescriptorError::AddressOverflow),
+        );
+        assert_eq!(
+            format!("{}", DescriptorError::TooLong(70_000)),
+            "70000 beats exceed the maximum of 65535",
+        );
+    }
+
+    #[test]
+    fn test_triggers() {
+        assert_eq!(sercom_rx_trigger(0), 0x01);
+        assert_eq!(sercom_tx_trigger(0), 0x02);
+        assert_eq!(sercom_rx_trigger(1), 0x03);
+        assert_eq!(sercom_tx_trigger(1), 0x04);
+        assert_eq!(sercom_tx_trigger(5), 0x0C);
+    }
+
+    #[test]
+    fn test_bits_round_trip() {
+        for beat_size in [BeatSize::Byte, BeatSize::HalfWord, BeatSize::Word] {
+            assert_eq!(BeatSize::from_bits(beat_size.as_bits()), Some(beat_size));
+        }
+        assert_eq!(BeatSize::from_bits(0b11), None);
+        let actions = [
+            BlockAction::NoAction, BlockAction::Interrupt, BlockAction::Suspend, BlockAction::Both,
+        ];
+        for action in actions {
+            assert_eq!(BlockAction::from_bits(action.as_bits()), action);
+        }
+    }
+}
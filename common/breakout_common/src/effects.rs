@@ -0,0 +1,368 @@
+//! Short screen effects that use the display modes, display offset and scrolling of the SSD1351.
+//!
+//! An effect runs for a fixed number of frames. [`Effects::advance`] is called once per frame and
+//! returns the commands that show the next frame of the effect; once the effect is over (or is
+//! replaced by another one), the display is returned to its regular state. Where the host has no
+//! display controller, [`Effects::state`] describes what the panel shows so that the effect can be
+//! rendered in software.
+//!
+//! The effects are meant for losing a life ([`Effect::Flash`]), an explosive brick going off
+//! ([`Effect::Shake`]) and moving on to the next level ([`Effect::Wipe`]).
+
+
+use arrayvec::ArrayVec;
+
+use crate::layout::CONTROLLER_RAM_EXTENT;
+use crate::ssd1351::{DisplayCommand, ScrollSpeed};
+
+
+/// The number of frames for which the colors stay inverted or regular while flashing.
+const FLASH_PHASE_FRAMES: u16 = 6;
+
+/// The number of times the colors are inverted while flashing.
+const FLASH_COUNT: u16 = 2;
+
+/// The number of rows by which the image is moved up in each frame of shaking.
+const SHAKE_SHIFTS: [i8; 12] = [2, -2, 2, -2, 1, -1, 1, -1, 1, -1, 0, 0];
+
+/// The number of frames for which the image scrolls during a wipe.
+const WIPE_FRAMES: u16 = 32;
+
+/// The number of columns by which the image is scrolled in each scrolling step of a wipe.
+const WIPE_SCROLL_COLUMNS: u8 = 1;
+
+/// The number of columns by which the image scrolls per frame when rendered in software. The
+/// controller takes a step every few display clocks, so this is an approximation.
+pub const WIPE_COLUMNS_PER_FRAME: usize = 4;
+
+/// The maximum number of commands returned by [`Effects::advance`].
+pub const MAX_EFFECT_COMMANDS: usize = 5;
+
+/// The commands that show a frame of an effect.
+pub type EffectCommands = ArrayVec<DisplayCommand<'static>, MAX_EFFECT_COMMANDS>;
+
+
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Effect {
+    /// Inverts the colors of the display twice in a row.
+    Flash,
+
+    /// Moves the image up and down a few rows, decreasingly.
+    Shake,
+
+    /// Scrolls the image sideways. Since scrolling changes the display RAM, the display must not
+    /// be updated while the wipe runs and has to be redrawn completely afterwards.
+    Wipe,
+}
+impl Effect {
+    /// The number of frames the effect lasts.
+    pub const fn frame_count(&self) -> u16 {
+        match self {
+            Self::Flash => 2 * FLASH_COUNT * FLASH_PHASE_FRAMES,
+            Self::Shake => SHAKE_SHIFTS.len() as u16,
+            Self::Wipe => WIPE_FRAMES,
+        }
+    }
+
+    /// What the panel shows in the given frame of the effect.
+    const fn state(&self, frame: u16) -> EffectState {
+        match self {
+            Self::Flash => EffectState {
+                inverted: (frame / FLASH_PHASE_FRAMES).is_multiple_of(2),
+                ..EffectState::REGULAR
+            },
+            Self::Shake => EffectState {
+                vertical_shift: SHAKE_SHIFTS[frame as usize],
+                ..EffectState::REGULAR
+            },
+            Self::Wipe => EffectState {
+                scrolled_columns: Some(
+                    (frame as usize * WIPE_COLUMNS_PER_FRAME) % CONTROLLER_RAM_EXTENT
+                ),
+                ..EffectState::REGULAR
+            },
+        }
+    }
+
+    /// Adds the commands that show the given frame of the effect.
+    fn push_frame_commands(&self, frame: u16, base_offset: u8, commands: &mut EffectCommands) {
+        match self {
+            Self::Flash => {
+                if frame.is_multiple_of(FLASH_PHASE_FRAMES) {
+                    commands.push(if self.state(frame).inverted {
+                        DisplayCommand::DisplayModeInverted
+                    } else {
+                        DisplayCommand::DisplayModeRegular
+                    });
+                }
+            },
+            Self::Shake => {
+                let shift = self.state(frame).vertical_shift;
+                commands.push(DisplayCommand::SetDisplayOffset {
+                    offset: shifted_offset(base_offset, shift),
+                });
+            },
+            Self::Wipe => {
+                if frame == 0 {
+                    commands.push(DisplayCommand::HorizontalScroll {
+                        scrolling: WIPE_SCROLL_COLUMNS,
+                        start_row: 0,
+                        scroll_row_count: CONTROLLER_RAM_EXTENT as u8,
+                        scroll_speed: ScrollSpeed::Normal,
+                    });
+                    commands.push(DisplayCommand::StartMoving);
+                }
+            },
+        }
+    }
+
+    /// Adds the commands that return the display to its regular state.
+    fn push_cleanup_commands(&self, base_offset: u8, commands: &mut EffectCommands) {
+        commands.push(match self {
+            Self::Flash => DisplayCommand::DisplayModeRegular,
+            Self::Shake => DisplayCommand::SetDisplayOffset { offset: base_offset },
+            Self::Wipe => DisplayCommand::StopMoving,
+        });
+    }
+}
+
+
+/// What the panel shows while an effect is running, for rendering it in software.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct EffectState {
+    /// Whether the colors are inverted.
+    pub inverted: bool,
+
+    /// The number of rows by which the image is moved up, in the panel's native orientation.
+    pub vertical_shift: i8,
+
+    /// While the image is scrolling, the number of columns by which it has moved towards the
+    /// last column of the controller's RAM, in the panel's native orientation. Columns scrolled
+    /// past the end of the RAM reappear at its start.
+    pub scrolled_columns: Option<usize>,
+}
+impl EffectState {
+    /// The state without any effect.
+    pub const REGULAR: Self = Self {
+        inverted: false,
+        vertical_shift: 0,
+        scrolled_columns: None,
+    };
+}
+impl Default for EffectState {
+    fn default() -> Self { Self::REGULAR }
+}
+
+
+/// What has to happen to the display for the next frame.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct EffectStep {
+    pub commands: EffectCommands,
+
+    /// Whether the display has to be redrawn completely after sending the commands.
+    pub redraw: bool,
+}
+
+
+/// Runs one effect at a time.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Effects {
+    /// The running effect and the number of its frames shown so far.
+    running: Option<(Effect, u16)>,
+
+    /// Effects that have been replaced and still need to be cleaned up.
+    replaced: ArrayVec<Effect, 3>,
+}
+impl Effects {
+    pub const fn new() -> Self {
+        Self {
+            running: None,
+            replaced: ArrayVec::new_const(),
+        }
+    }
+
+    /// Starts an effect with the next frame, replacing the running one.
+    pub fn start(&mut self, effect: Effect) {
+        if let Some((previous, frames_shown)) = self.running.replace((effect, 0)) {
+            if frames_shown > 0 && !self.replaced.contains(&previous) {
+                self.replaced.push(previous);
+            }
+        }
+    }
+
+    #[inline]
+    pub fn running(&self) -> Option<Effect> {
+        self.running.map(|(effect, _)| effect)
+    }
+
+    /// What the panel shows in the current frame.
+    pub fn state(&self) -> EffectState {
+        match self.running {
+            Some((effect, frames_shown)) if frames_shown > 0 => effect.state(frames_shown - 1),
+            _ => EffectState::REGULAR,
+        }
+    }
+
+    /// Whether the display RAM may be written to; it must not be while the image is scrolling.
+    #[inline]
+    pub fn may_update_display(&self) -> bool {
+        self.state().scrolled_columns.is_none()
+    }
+
+    /// Moves on to the next frame and returns what has to be sent to the display for it.
+    ///
+    /// `base_offset` is the display offset outside of any effect, e.g. as set by the
+    /// [`BurnInGuard`](crate::burn_in::BurnInGuard).
+    pub fn advance(&mut self, base_offset: u8) -> EffectStep {
+        let mut step = EffectStep::default();
+        for effect in self.replaced.drain(..) {
+            effect.push_cleanup_commands(base_offset, &mut step.commands);
+            step.redraw |= effect == Effect::Wipe;
+        }
+
+        if let Some((effect, frames_shown)) = &mut self.running {
+            if *frames_shown < effect.frame_count() {
+                effect.push_frame_commands(*frames_shown, base_offset, &mut step.commands);
+                *frames_shown += 1;
+            } else {
+                effect.push_cleanup_commands(base_offset, &mut step.commands);
+                step.redraw |= *effect == Effect::Wipe;
+                self.running = None;
+            }
+        }
+        step
+    }
+}
+
+
+/// The display offset that moves the image up by `shift` rows from `base_offset`.
+const fn shifted_offset(base_offset: u8, shift: i8) -> u8 {
+    (base_offset as i16 + shift as i16).rem_euclid(CONTROLLER_RAM_EXTENT as i16) as u8
+}
+
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::vec;
+    use std::vec::Vec;
+
+    use super::*;
+
+    /// Runs the effects until they are over, collecting the commands of each frame.
+    fn run(effects: &mut Effects, base_offset: u8) -> Vec<EffectStep> {
+        let mut steps = Vec::new();
+        while effects.running().is_some() {
+            steps.push(effects.advance(base_offset));
+            assert!(steps.len() < 100);
+        }
+        steps
+    }
+
+    #[test]
+    fn test_flash() {
+        let mut effects = Effects::new();
+        effects.start(Effect::Flash);
+        assert_eq!(effects.state(), EffectState::REGULAR);
+
+        let mut inverted = Vec::new();
+        let mut commands = Vec::new();
+        while effects.running().is_some() {
+            let step = effects.advance(0);
+            inverted.push(effects.state().inverted);
+            commands.extend(step.commands);
+            assert!(!step.redraw);
+        }
+        assert_eq!(inverted.len(), 25);
+        assert_eq!(inverted.iter().filter(|&&inverted| inverted).count(), 12);
+        assert!(inverted[0] && !inverted[6] && inverted[12] && !inverted[18] && !inverted[24]);
+        assert_eq!(
+            commands,
+            vec![
+                DisplayCommand::DisplayModeInverted,
+                DisplayCommand::DisplayModeRegular,
+                DisplayCommand::DisplayModeInverted,
+                DisplayCommand::DisplayModeRegular,
+                DisplayCommand::DisplayModeRegular,
+            ],
+        );
+    }
+
+    #[test]
+    fn test_shake() {
+        let mut effects = Effects::new();
+        effects.start(Effect::Shake);
+        let steps = run(&mut effects, 1);
+        assert_eq!(steps.len(), SHAKE_SHIFTS.len() + 1);
+        assert_eq!(
+            steps[0].commands.as_slice(),
+            &[DisplayCommand::SetDisplayOffset { offset: 3 }],
+        );
+        assert_eq!(
+            steps[1].commands.as_slice(),
+            &[DisplayCommand::SetDisplayOffset { offset: 127 }],
+        );
+        assert_eq!(
+            steps.last().unwrap().commands.as_slice(),
+            &[DisplayCommand::SetDisplayOffset { offset: 1 }],
+        );
+        assert_eq!(effects.state(), EffectState::REGULAR);
+    }
+
+    #[test]
+    fn test_wipe() {
+        let mut effects = Effects::new();
+        effects.start(Effect::Wipe);
+        assert!(effects.may_update_display());
+
+        let first = effects.advance(0);
+        assert_eq!(first.commands.len(), 2);
+        assert!(first.commands.iter().all(|command| command.is_valid()));
+        assert_eq!(first.commands[1], DisplayCommand::StartMoving);
+        assert!(!effects.may_update_display());
+        assert_eq!(effects.state().scrolled_columns, Some(0));
+        assert!(effects.advance(0).commands.is_empty());
+        assert_eq!(effects.state().scrolled_columns, Some(WIPE_COLUMNS_PER_FRAME));
+
+        let steps = run(&mut effects, 0);
+        assert_eq!(steps.len(), usize::from(WIPE_FRAMES) - 1);
+        let last = steps.last().unwrap();
+        assert_eq!(last.commands.as_slice(), &[DisplayCommand::StopMoving]);
+        assert!(last.redraw);
+        assert!(effects.may_update_display());
+    }
+
+    #[test]
+    fn test_replace() {
+        let mut effects = Effects::new();
+        effects.start(Effect::Wipe);
+        effects.advance(0);
+        effects.advance(0);
+
+        // the wipe is stopped before shaking starts
+        effects.start(Effect::Shake);
+        let step = effects.advance(5);
+        assert_eq!(
+            step.commands.as_slice(),
+            &[DisplayCommand::StopMoving, DisplayCommand::SetDisplayOffset { offset: 7 }],
+        );
+        assert!(step.redraw);
+        assert_eq!(effects.running(), Some(Effect::Shake));
+
+        // an effect that has not shown a frame yet needs no cleanup
+        effects.start(Effect::Flash);
+        effects.start(Effect::Wipe);
+        let step = effects.advance(5);
+        assert_eq!(step.commands[0], DisplayCommand::SetDisplayOffset { offset: 5 });
+        assert_eq!(step.commands.len(), 3);
+    }
+
+    #[test]
+    fn test_idle() {
+        let mut effects = Effects::new();
+        assert_eq!(effects.advance(0), EffectStep::default());
+        assert_eq!(effects.state(), EffectState::REGULAR);
+        assert!(effects.may_update_display());
+    }
+}
@@ -1,7 +1,9 @@
 //! Implementation of fixed-point arithmetic.
 
 
+use core::fmt;
 use core::ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Neg, Sub, SubAssign};
+use core::str::FromStr;
 
 
 pub type FixedPointValue = i16;
@@ -10,9 +12,19 @@ pub type FixedPointMulResult = i32; // double size from FixedPointValue
 
 pub const EXPONENT: u8 = 8;
 const MUL_RESULT_MASK: FixedPointMulResult = 0xFFFF; // mask after right-shift by EXPONENT
+const FRAC_MASK: FixedPointValue = (1 << EXPONENT) - 1;
 
+// 1/2**EXPONENT = 0.00390625 => every fractional part can be represented exactly with 8 decimal
+// digits; one unit in the last place of the fractional part is then 10**8/2**EXPONENT = 390625
+const FRAC_DECIMAL_DIGITS: usize = 8;
+const FRAC_DECIMAL_UNIT: u32 = 100_000_000 >> EXPONENT;
 
-#[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+/// The maximum number of fractional digits output when formatting with an explicit precision.
+/// Any further digits would be zero anyway.
+pub const MAX_FORMAT_PRECISION: usize = 32;
+
+
+#[derive(Clone, Copy, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
 pub struct FixedPoint {
     value: FixedPointValue,
 }
@@ -47,7 +59,6 @@ impl FixedPoint {
 
     #[inline]
     pub const fn is_integer(&self) -> bool {
-        const FRAC_MASK: FixedPointValue = (1 << EXPONENT) - 1;
         (self.value & FRAC_MASK) == 0
     }
 }
@@ -109,9 +120,204 @@ impl SubAssign for FixedPoint {
     fn sub_assign(&mut self, rhs: Self) { *self = *self - rhs; }
 }
 
+impl fmt::Debug for FixedPoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "FixedPoint({} = 0x{:04X})", self, self.value)
+    }
+}
+impl fmt::Display for FixedPoint {
+    /// Outputs the value in decimal notation.
+    ///
+    /// Without an explicit precision, as many fractional digits as necessary to represent the value
+    /// exactly are output (at most 8). With an explicit precision (e.g. `{:.2}`), the value is
+    /// rounded half away from zero to that many fractional digits. Width, fill, alignment and sign
+    /// flags are honored as with integers.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // integer part (max. 3 digits) + decimal point + fractional digits
+        let mut buf = [0u8; 3 + 1 + MAX_FORMAT_PRECISION];
+        let mut len = 0;
+
+        // i16::MIN has no positive counterpart; work with the magnitude in a wider type
+        let magnitude = i32::from(self.value).unsigned_abs();
+        let mut integer_part = magnitude >> EXPONENT;
+        let mut frac_decimal = (magnitude & (FRAC_MASK as u32)) * FRAC_DECIMAL_UNIT;
+
+        let frac_digit_count = match f.precision() {
+            Some(precision) => {
+                let precision = precision.min(MAX_FORMAT_PRECISION);
+                if precision < FRAC_DECIMAL_DIGITS {
+                    // round to the requested precision
+                    let divisor = 10u32.pow((FRAC_DECIMAL_DIGITS - precision) as u32);
+                    frac_decimal = (frac_decimal + divisor / 2) / divisor * divisor;
+                    if frac_decimal >= 10u32.pow(FRAC_DECIMAL_DIGITS as u32) {
+                        // carry into integer part
+                        frac_decimal -= 10u32.pow(FRAC_DECIMAL_DIGITS as u32);
+                        integer_part += 1;
+                    }
+                }
+                precision
+            },
+            None => {
+                // strip trailing zeroes
+                let mut count = FRAC_DECIMAL_DIGITS;
+                let mut remaining = frac_decimal;
+                while count > 0 && remaining.is_multiple_of(10) {
+                    remaining /= 10;
+                    count -= 1;
+                }
+                count
+            },
+        };
+
+        // integer part
+        let mut divisor = 100;
+        let mut leading = true;
+        while divisor > 0 {
+            let digit = (integer_part / divisor) % 10;
+            if digit != 0 || !leading || divisor == 1 {
+                buf[len] = b'0' + (digit as u8);
+                len += 1;
+                leading = false;
+            }
+            divisor /= 10;
+        }
+
+        // fractional part
+        if frac_digit_count > 0 {
+            buf[len] = b'.';
+            len += 1;
+
+            let mut divisor = 10u32.pow((FRAC_DECIMAL_DIGITS - 1) as u32);
+            for _ in 0..frac_digit_count {
+                let digit = frac_decimal.checked_div(divisor).map_or(0, |d| d % 10);
+                buf[len] = b'0' + (digit as u8);
+                len += 1;
+                divisor /= 10;
+            }
+        }
+
+        // UNSAFE: only ASCII digits and '.' have been written into the buffer
+        let digits = unsafe { core::str::from_utf8_unchecked(&buf[0..len]) };
+        f.pad_integral(self.value >= 0, "", digits)
+    }
+}
+impl fmt::LowerHex for FixedPoint {
+    /// Outputs the raw value (two's complement) in lowercase hexadecimal notation.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::LowerHex::fmt(&self.value, f)
+    }
+}
+impl fmt::UpperHex for FixedPoint {
+    /// Outputs the raw value (two's complement) in uppercase hexadecimal notation.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::UpperHex::fmt(&self.value, f)
+    }
+}
+
+
+/// The reason why a string could not be parsed into a [`FixedPoint`].
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum ParseFixedPointError {
+    /// The string is empty or only consists of a sign.
+    Empty,
+
+    /// The string contains a character that is not a decimal digit, a leading sign or a single
+    /// decimal point, or it contains no digits.
+    InvalidDigit,
+
+    /// The value is too large or too small to be represented.
+    OutOfRange,
+}
+impl fmt::Display for ParseFixedPointError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Empty => write!(f, "cannot parse fixed-point value from empty string"),
+            Self::InvalidDigit => write!(f, "invalid digit found in string"),
+            Self::OutOfRange => write!(f, "value out of range for fixed-point type"),
+        }
+    }
+}
+
+impl FromStr for FixedPoint {
+    type Err = ParseFixedPointError;
+
+    /// Parses a decimal string such as `-12.375` into the nearest representable value.
+    ///
+    /// Accepted are an optional sign followed by decimal digits with at most one decimal point
+    /// (`5`, `+5.`, `.5`, `-0.25`). Ties are rounded away from zero.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // at most this many fractional digits are considered; further digits cannot change the
+        // rounded result except in pathological cases
+        const MAX_FRAC_DIGITS: u32 = 18;
+
+        let bytes = s.as_bytes();
+        let (negative, rest) = match bytes.first() {
+            Some(b'-') => (true, &bytes[1..]),
+            Some(b'+') => (false, &bytes[1..]),
+            _ => (false, bytes),
+        };
+        if rest.is_empty() {
+            return Err(ParseFixedPointError::Empty);
+        }
+
+        let mut integer_part: u32 = 0;
+        let mut frac_numerator: u64 = 0;
+        let mut frac_denominator: u64 = 1;
+        let mut frac_digit_count: u32 = 0;
+        let mut seen_point = false;
+        let mut seen_digit = false;
+        for &b in rest {
+            match b {
+                b'0'..=b'9' => {
+                    let digit = b - b'0';
+                    seen_digit = true;
+                    if seen_point {
+                        if frac_digit_count < MAX_FRAC_DIGITS {
+                            frac_numerator = frac_numerator * 10 + u64::from(digit);
+                            frac_denominator *= 10;
+                            frac_digit_count += 1;
+                        }
+                    } else {
+                        integer_part = integer_part.checked_mul(10)
+                            .and_then(|ip| ip.checked_add(u32::from(digit)))
+                            .ok_or(ParseFixedPointError::OutOfRange)?;
+                    }
+                },
+                b'.' if !seen_point => {
+                    seen_point = true;
+                },
+                _ => return Err(ParseFixedPointError::InvalidDigit),
+            }
+        }
+        if !seen_digit {
+            return Err(ParseFixedPointError::InvalidDigit);
+        }
+
+        // scale the fraction to 2**EXPONENT, rounding half away from zero; with 18 digits, the
+        // shifted numerator no longer fits into 64 bits
+        let frac_numerator = u128::from(frac_numerator);
+        let frac_denominator = u128::from(frac_denominator);
+        let raw_frac = ((frac_numerator << EXPONENT) + frac_denominator / 2) / frac_denominator;
+        let raw_frac = raw_frac as u64;
+        let magnitude = u64::from(integer_part)
+            .checked_shl(EXPONENT.into())
+            .filter(|m| (m >> EXPONENT) == u64::from(integer_part))
+            .ok_or(ParseFixedPointError::OutOfRange)?
+            + raw_frac;
+
+        let value: i64 = if negative { -(magnitude as i64) } else { magnitude as i64 };
+        FixedPointValue::try_from(value)
+            .map(FixedPoint::new_raw)
+            .map_err(|_| ParseFixedPointError::OutOfRange)
+    }
+}
+
 
 #[cfg(test)]
 mod tests {
+    extern crate std;
+    use std::format;
+
     use super::*;
 
     fn i(int: FixedPointIntegerValue) -> FixedPoint { FixedPoint::new_integer(int) }
@@ -151,4 +357,99 @@ mod tests {
         assert!(!(i(4) / i(8)).is_integer());
         assert_eq!((i(4) / i(8)) * i(2), i(1));
     }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(format!("{}", i(0)), "0");
+        assert_eq!(format!("{}", i(42)), "42");
+        assert_eq!(format!("{}", i(-19)), "-19");
+        assert_eq!(format!("{}", FixedPoint::new_raw(1456)), "5.6875");
+        assert_eq!(format!("{}", FixedPoint::new_raw(-1456)), "-5.6875");
+        assert_eq!(format!("{}", FixedPoint::new_raw(1)), "0.00390625");
+        assert_eq!(format!("{}", FixedPoint::new_raw(-1)), "-0.00390625");
+        assert_eq!(format!("{}", FixedPoint::new_raw(i16::MAX)), "127.99609375");
+        assert_eq!(format!("{}", FixedPoint::new_raw(i16::MIN)), "-128");
+    }
+
+    #[test]
+    fn test_display_precision() {
+        assert_eq!(format!("{:.2}", FixedPoint::new_raw(1456)), "5.69");
+        assert_eq!(format!("{:.0}", FixedPoint::new_raw(1456)), "6");
+        assert_eq!(format!("{:.4}", FixedPoint::new_raw(1456)), "5.6875");
+        assert_eq!(format!("{:.6}", FixedPoint::new_raw(1456)), "5.687500");
+        assert_eq!(format!("{:.2}", i(3)), "3.00");
+        assert_eq!(format!("{:.1}", FixedPoint::new_raw(i16::MAX)), "128.0");
+        assert_eq!(format!("{:.2}", FixedPoint::new_raw(-1)), "-0.00");
+        assert_eq!(format!("{:.10}", FixedPoint::new_raw(1)), "0.0039062500");
+    }
+
+    #[test]
+    fn test_display_padding() {
+        assert_eq!(format!("{:8}", FixedPoint::new_raw(1456)), "  5.6875");
+        assert_eq!(format!("{:<8}|", i(-2)), "-2      |");
+        assert_eq!(format!("{:+}", i(2)), "+2");
+        assert_eq!(format!("{:08.2}", FixedPoint::new_raw(-1456)), "-0005.69");
+    }
+
+    #[test]
+    fn test_hex() {
+        assert_eq!(format!("{:x}", FixedPoint::new_raw(1456)), "5b0");
+        assert_eq!(format!("{:#06X}", FixedPoint::new_raw(1456)), "0x05B0");
+        assert_eq!(format!("{:x}", FixedPoint::new_raw(-1)), "ffff");
+        assert_eq!(format!("{:?}", FixedPoint::new_raw(1456)), "FixedPoint(5.6875 = 0x05B0)");
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!("0".parse(), Ok(i(0)));
+        assert_eq!("42".parse(), Ok(i(42)));
+        assert_eq!("-19".parse(), Ok(i(-19)));
+        assert_eq!("+7".parse(), Ok(i(7)));
+        assert_eq!("5.6875".parse(), Ok(FixedPoint::new_raw(1456)));
+        assert_eq!("-5.6875".parse(), Ok(FixedPoint::new_raw(-1456)));
+        assert_eq!(".5".parse(), Ok(FixedPoint::new_raw(128)));
+        assert_eq!("3.".parse(), Ok(i(3)));
+        assert_eq!("127.99609375".parse(), Ok(FixedPoint::new_raw(i16::MAX)));
+        assert_eq!("-128".parse(), Ok(FixedPoint::new_raw(i16::MIN)));
+    }
+
+    #[test]
+    fn test_parse_rounding() {
+        // 0.1 * 256 = 25.6
+        assert_eq!("0.1".parse(), Ok(FixedPoint::new_raw(26)));
+        assert_eq!("-0.1".parse(), Ok(FixedPoint::new_raw(-26)));
+        // 0.001953125 * 256 = 0.5
+        assert_eq!("0.001953125".parse(), Ok(FixedPoint::new_raw(1)));
+        assert_eq!("0.0019531249".parse(), Ok(FixedPoint::new_raw(0)));
+        // rounds up into the integer part
+        assert_eq!("1.999".parse(), Ok(i(2)));
+
+        // as many fractional digits as are considered, and more
+        assert_eq!("0.99999999999999999".parse(), Ok(i(1)));
+        assert_eq!("0.999999999999999999".parse(), Ok(i(1)));
+        assert_eq!("-0.99999999999999999999".parse(), Ok(i(-1)));
+        assert_eq!("0.000000000000000001".parse(), Ok(i(0)));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!("".parse::<FixedPoint>(), Err(ParseFixedPointError::Empty));
+        assert_eq!("-".parse::<FixedPoint>(), Err(ParseFixedPointError::Empty));
+        assert_eq!(".".parse::<FixedPoint>(), Err(ParseFixedPointError::InvalidDigit));
+        assert_eq!("1.2.3".parse::<FixedPoint>(), Err(ParseFixedPointError::InvalidDigit));
+        assert_eq!("1e3".parse::<FixedPoint>(), Err(ParseFixedPointError::InvalidDigit));
+        assert_eq!(" 1".parse::<FixedPoint>(), Err(ParseFixedPointError::InvalidDigit));
+        assert_eq!("128".parse::<FixedPoint>(), Err(ParseFixedPointError::OutOfRange));
+        assert_eq!("127.999".parse::<FixedPoint>(), Err(ParseFixedPointError::OutOfRange));
+        assert_eq!("-128.002".parse::<FixedPoint>(), Err(ParseFixedPointError::OutOfRange));
+        assert_eq!("99999999999".parse::<FixedPoint>(), Err(ParseFixedPointError::OutOfRange));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for raw in FixedPointValue::MIN..=FixedPointValue::MAX {
+            let value = FixedPoint::new_raw(raw);
+            assert_eq!(format!("{}", value).parse(), Ok(value));
+        }
+    }
 }
@@ -0,0 +1,253 @@
+//! Grayscale lookup tables that give the SSD1351 a gamma curve.
+//!
+//! The controller turns the level of each color channel into the width of the current pulse that
+//! drives the pixel, and the brightness of an OLED pixel is roughly proportional to that width. The
+//! built-in table is linear, so images look washed out compared to a monitor showing the same
+//! pixels; a table following the monitor's gamma curve (about 2.2 for sRGB) makes them match.
+//!
+//! There is no `powf` in `core`, so the curve is computed using the small approximations of
+//! `log2` and `exp2` below. They are exact to far more digits than a pulse width has.
+
+
+use core::fmt;
+
+use crate::display::DisplayInterface;
+use crate::display_config::GRAYSCALE_TABLE_LENGTH;
+use crate::ssd1351::{DisplayCommand, MAX_GRAYSCALE_PULSE_WIDTH, is_valid_grayscale_table};
+
+
+/// The smallest gamma value accepted by [`gamma_table`].
+pub const MIN_GAMMA: f32 = 1.0;
+
+/// The largest gamma value accepted by [`gamma_table`].
+pub const MAX_GAMMA: f32 = 4.0;
+
+/// The pulse widths of the grayscale levels 1 to 63; level 0 is always off.
+pub type GrayscaleTable = [u8; GRAYSCALE_TABLE_LENGTH];
+
+/// A table for gamma 1.8, as used by older Macs; brighter shadows than sRGB.
+pub const GAMMA_1_8: GrayscaleTable = expect_table(gamma_table(1.8));
+
+/// A table for gamma 2.2, matching images as they look on an sRGB monitor.
+pub const GAMMA_2_2: GrayscaleTable = expect_table(gamma_table(2.2));
+
+/// A table for gamma 2.5, with deeper shadows.
+pub const GAMMA_2_5: GrayscaleTable = expect_table(gamma_table(2.5));
+
+
+/// A gamma value outside the range from [`MIN_GAMMA`] to [`MAX_GAMMA`], or not a number at all.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct InvalidGammaError {
+    pub gamma: f32,
+}
+impl fmt::Display for InvalidGammaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "gamma {} is not between {} and {}", self.gamma, MIN_GAMMA, MAX_GAMMA)
+    }
+}
+
+
+/// Computes the grayscale table for the given gamma value.
+///
+/// Level `n` gets a pulse width of `(n / 63)^gamma` times [`MAX_GRAYSCALE_PULSE_WIDTH`], rounded.
+/// Where the curve is flatter than one display clock per level (in the shadows, for larger gamma
+/// values), the widths are spread out so that each level stays distinguishable; the table is
+/// therefore strictly increasing and ends at the maximum width.
+pub const fn gamma_table(gamma: f32) -> Result<GrayscaleTable, InvalidGammaError> {
+    // written this way round so that NaN is rejected
+    if !(gamma >= MIN_GAMMA && gamma <= MAX_GAMMA) {
+        return Err(InvalidGammaError { gamma });
+    }
+
+    let mut table = [0u8; GRAYSCALE_TABLE_LENGTH];
+    let mut previous = 0u8;
+    let mut i = 0;
+    while i < GRAYSCALE_TABLE_LENGTH {
+        let level = (i + 1) as f32 / GRAYSCALE_TABLE_LENGTH as f32;
+        let width = pow(level, gamma) * MAX_GRAYSCALE_PULSE_WIDTH as f32;
+        let mut rounded = (width + 0.5) as u8;
+
+        // leave room for the remaining levels above and keep this one above the previous one
+        let highest = MAX_GRAYSCALE_PULSE_WIDTH - (GRAYSCALE_TABLE_LENGTH - 1 - i) as u8;
+        if rounded > highest {
+            rounded = highest;
+        }
+        if rounded <= previous {
+            rounded = previous + 1;
+        }
+
+        table[i] = rounded;
+        previous = rounded;
+        i += 1;
+    }
+    debug_assert!(is_valid_grayscale_table(&table));
+    Ok(table)
+}
+
+const fn expect_table(result: Result<GrayscaleTable, InvalidGammaError>) -> GrayscaleTable {
+    match result {
+        Ok(table) => table,
+        Err(_) => panic!("invalid gamma value"),
+    }
+}
+
+
+/// Replaces the grayscale table of a running display; `None` restores the built-in linear one.
+///
+/// This can happen at any time, even between two parts of an image being sent; the new table
+/// takes effect with the next frame the panel scans out.
+pub fn set_grayscale_table<DI: DisplayInterface>(
+    display_interface: &mut DI,
+    table: Option<&GrayscaleTable>,
+) {
+    match table {
+        Some(table) => DisplayCommand::SetGrayscaleLookUpTable { table },
+        None => DisplayCommand::ResetGrayscaleLookUpTable,
+    }
+        .transmit(display_interface);
+}
+
+
+/// `base` to the power of `exponent`, for a base between 0 and 1 and a non-negative exponent.
+pub(crate) const fn pow(base: f32, exponent: f32) -> f32 {
+    debug_assert!(base > 0.0 && base <= 1.0 && exponent >= 0.0);
+    exp2(exponent * log2(base))
+}
+
+/// The base-2 logarithm of a positive, normal number.
+const fn log2(x: f32) -> f32 {
+    // split into exponent and mantissa in [1, 2)
+    let bits = x.to_bits();
+    let exponent = ((bits >> 23) & 0xFF) as i32 - 127;
+    let mantissa = f32::from_bits((bits & 0x007F_FFFF) | 0x3F80_0000);
+
+    // ln(m) = 2 atanh((m - 1) / (m + 1)); the argument is at most 1/3, so the series converges
+    // quickly
+    let t = (mantissa - 1.0) / (mantissa + 1.0);
+    let t2 = t * t;
+    let mut term = t;
+    let mut sum = 0.0;
+    let mut k = 1;
+    while k <= 11 {
+        sum += term / k as f32;
+        term *= t2;
+        k += 2;
+    }
+    exponent as f32 + 2.0 * sum / core::f32::consts::LN_2
+}
+
+/// Two to the power of a number of at most zero.
+const fn exp2(y: f32) -> f32 {
+    debug_assert!(y <= 0.0);
+
+    // split into integer part and fraction in [0, 1)
+    let mut integer = y as i32;
+    if (integer as f32) > y {
+        integer -= 1;
+    }
+    if integer < -126 {
+        // smaller than the smallest normal number; this is zero as far as pulse widths go
+        return 0.0;
+    }
+    let fraction = y - integer as f32;
+
+    // e^(f ln 2) as a Taylor series
+    let x = fraction * core::f32::consts::LN_2;
+    let mut term = 1.0;
+    let mut sum = 1.0;
+    let mut k = 1;
+    while k <= 9 {
+        term *= x / k as f32;
+        sum += term;
+        k += 1;
+    }
+    sum * f32::from_bits(((integer + 127) as u32) << 23)
+}
+
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::{format, vec};
+
+    use crate::hal::mock::{Event, display_events};
+
+    use super::*;
+
+    #[test]
+    fn test_approximations() {
+        let logarithms = [(1.0, 0.0), (0.5, -1.0), (0.75, -0.415_037_5), (1.0 / 63.0, -5.977_28)];
+        for (x, expected) in logarithms {
+            assert!((log2(x) - expected).abs() < 1e-5, "log2({})", x);
+        }
+        let powers = [(0.0, 1.0), (-1.0, 0.5), (-1.5, 0.353_553_4), (-10.25, 0.000_821_19)];
+        for (y, expected) in powers {
+            assert!((exp2(y) - expected).abs() < 1e-5 * expected, "exp2({})", y);
+        }
+        assert_eq!(exp2(-200.0), 0.0);
+        assert!((pow(0.5, 2.2) - 0.217_637_6).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_gamma_tables() {
+        for gamma in [1.0, 1.8, 2.2, 2.5, 3.0, 4.0] {
+            let table = gamma_table(gamma)
+                .expect("valid gamma");
+            assert!(is_valid_grayscale_table(&table));
+            assert!(table.windows(2).all(|pair| pair[0] < pair[1]), "gamma {}", gamma);
+            assert_eq!(table[GRAYSCALE_TABLE_LENGTH - 1], MAX_GRAYSCALE_PULSE_WIDTH);
+        }
+
+        // gamma 1 is a straight line
+        let linear = gamma_table(1.0)
+            .expect("valid gamma");
+        assert_eq!(&linear[..4], &[3, 6, 9, 11]);
+        assert_eq!(linear[31], 91);
+
+        // the middle level is darker the larger the gamma value: 180 * 0.5^gamma, roughly
+        assert_eq!(GAMMA_1_8[31], 53);
+        assert_eq!(GAMMA_2_2[31], 41);
+        assert_eq!(GAMMA_2_5[31], 33);
+
+        // the shadows are spread out to keep levels apart
+        assert_eq!(&GAMMA_2_5[..3], &[1, 2, 3]);
+    }
+
+    #[test]
+    fn test_invalid_gamma() {
+        for gamma in [0.9, 4.1, -2.2, f32::NAN, f32::INFINITY] {
+            assert!(gamma_table(gamma).is_err(), "gamma {}", gamma);
+        }
+        assert_eq!(
+            format!("{}", InvalidGammaError { gamma: 0.5 }),
+            "gamma 0.5 is not between 1 and 4",
+        );
+    }
+
+    #[test]
+    fn test_set_grayscale_table() {
+        assert_eq!(
+            display_events(|display| set_grayscale_table(display, Some(&GAMMA_2_2))),
+            vec![
+                Event::PinLow("cs"),
+                Event::PinLow("dc"),
+                Event::SpiWrite(vec![0xB8]),
+                Event::PinHigh("dc"),
+                Event::SpiWrite(GAMMA_2_2.to_vec()),
+                Event::PinHigh("cs"),
+            ],
+        );
+        assert_eq!(
+            display_events(|display| set_grayscale_table(display, None)),
+            vec![
+                Event::PinLow("cs"),
+                Event::PinLow("dc"),
+                Event::SpiWrite(vec![0xB9]),
+                Event::PinHigh("dc"),
+                Event::SpiWrite(vec![]),
+                Event::PinHigh("cs"),
+            ],
+        );
+    }
+}
@@ -0,0 +1,261 @@
+//! Small hardware abstraction traits that the drivers are written against.
+//!
+//! Board crates implement these traits for their peripherals; host tests implement them with
+//! mocks that record what the drivers do.
+
+
+/// A Serial Peripheral Interface bus on which this device is the controller.
+pub trait SpiBus {
+    /// Sends the given bytes, discarding the bytes received at the same time. Returns once the
+    /// last byte has been shifted out completely.
+    fn write(&mut self, data: &[u8]);
+
+    /// Sends the given bytes, replacing each of them with the byte received at the same time.
+    /// Returns once the last byte has been shifted out completely.
+    fn transfer(&mut self, data: &mut [u8]);
+}
+
+
+/// An SPI bus that can also send data in the background while the CPU does something else, e.g.
+/// using DMA.
+pub trait BackgroundSpiBus: SpiBus {
+    /// Starts sending the first `length` bytes of the buffer and returns immediately. Any previous
+    /// background transfer must have been finished; the blocking methods of [`SpiBus`] must not be
+    /// called until this one has been finished too.
+    fn start_write(&mut self, buffer: &'static mut [u8], length: usize);
+
+    /// Waits until the background transfer (if any) has been shifted out completely and returns
+    /// its buffer.
+    fn finish_write(&mut self) -> Option<&'static mut [u8]>;
+}
+
+
+/// The clock polarity and phase of an SPI bus.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum SpiMode {
+    /// The clock idles low; data is sampled at the leading edge.
+    Mode0,
+
+    /// The clock idles low; data is sampled at the trailing edge.
+    Mode1,
+
+    /// The clock idles high; data is sampled at the leading edge.
+    Mode2,
+
+    /// The clock idles high; data is sampled at the trailing edge.
+    Mode3,
+}
+impl SpiMode {
+    /// Whether the clock is high while idle (CPOL=1).
+    #[inline]
+    pub const fn clock_idles_high(&self) -> bool {
+        matches!(self, Self::Mode2 | Self::Mode3)
+    }
+
+    /// Whether data is sampled at the second clock edge of each bit (CPHA=1).
+    #[inline]
+    pub const fn samples_at_trailing_edge(&self) -> bool {
+        matches!(self, Self::Mode1 | Self::Mode3)
+    }
+}
+
+
+/// The settings of an SPI bus that a device needs while it is selected.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct SpiConfig {
+    pub mode: SpiMode,
+
+    /// The highest clock frequency the device can keep up with, in Hz.
+    pub max_frequency: u32,
+}
+
+
+/// Whether a chip-select line selects its device when it is low or when it is high.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum ChipSelectPolarity {
+    ActiveLow,
+    ActiveHigh,
+}
+
+
+/// An SPI bus whose mode and clock frequency can be changed between transfers.
+pub trait ConfigurableSpiBus: SpiBus {
+    /// Switches the bus to the given settings. Must not be called during a transfer.
+    fn configure(&mut self, config: &SpiConfig);
+
+    /// The lowest clock frequency the bus can generate, in Hz.
+    fn min_frequency(&self) -> u32;
+}
+
+
+/// A single device on an SPI bus. Its transfers take place while it is selected.
+pub trait SpiDevice: SpiBus {
+    /// Sets up the bus for this device and asserts its chip-select line.
+    fn select(&mut self);
+
+    /// Deasserts the chip-select line of this device.
+    fn deselect(&mut self);
+}
+
+
+/// A digital output pin.
+pub trait OutputPin {
+    fn set_high(&mut self);
+    fn set_low(&mut self);
+
+    #[inline]
+    fn set_level(&mut self, high: bool) {
+        if high {
+            self.set_high();
+        } else {
+            self.set_low();
+        }
+    }
+}
+
+
+#[cfg(test)]
+pub(crate) mod mock {
+    extern crate std;
+
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use std::vec::Vec;
+
+    use crate::display::SpiDisplayInterface;
+    use crate::spi_device::ExclusiveSpiDevice;
+
+    use super::{
+        BackgroundSpiBus, ChipSelectPolarity, ConfigurableSpiBus, OutputPin, SpiBus, SpiConfig,
+    };
+
+    /// Something that happened on the mocked hardware.
+    #[derive(Clone, Debug, Eq, PartialEq)]
+    pub(crate) enum Event {
+        PinHigh(&'static str),
+        PinLow(&'static str),
+        SpiWrite(Vec<u8>),
+        SpiTransfer(Vec<u8>),
+        SpiWriteStarted(Vec<u8>),
+        SpiWriteFinished,
+        SpiConfigured(SpiConfig),
+    }
+
+    /// The shared record of events on all mocked hardware.
+    pub(crate) type EventLog = Rc<RefCell<Vec<Event>>>;
+
+    pub(crate) fn new_log() -> EventLog { Rc::new(RefCell::new(Vec::new())) }
+
+    /// A display connected via a mocked SPI bus, with the pins named `"cs"` and `"dc"`.
+    pub(crate) type MockDisplayInterface<SPI = MockSpi> =
+        SpiDisplayInterface<ExclusiveSpiDevice<SPI, MockPin>, MockPin>;
+
+    /// Attaches a device to a mocked SPI bus, with the chip select pin named `"cs"`.
+    pub(crate) fn spi_device<SPI: SpiBus>(
+        log: &EventLog, spi: SPI, polarity: ChipSelectPolarity,
+    ) -> ExclusiveSpiDevice<SPI, MockPin> {
+        ExclusiveSpiDevice::new(spi, MockPin { name: "cs", log: log.clone() }, polarity)
+    }
+
+    /// Connects a display to a mocked SPI bus; the log is cleared once the pins are set up.
+    pub(crate) fn display_interface_on<SPI: SpiBus>(
+        log: &EventLog, spi: SPI,
+    ) -> MockDisplayInterface<SPI> {
+        let display = SpiDisplayInterface::new(
+            spi_device(log, spi, ChipSelectPolarity::ActiveLow),
+            MockPin { name: "dc", log: log.clone() },
+        );
+        log.borrow_mut().clear();
+        display
+    }
+
+    /// Connects a display to a [`MockSpi`] without any queued responses.
+    pub(crate) fn display_interface(log: &EventLog) -> MockDisplayInterface {
+        display_interface_on(log, MockSpi { log: log.clone(), responses: Vec::new() })
+    }
+
+    /// Runs the closure on a display connected via SPI and returns the events on the bus.
+    pub(crate) fn display_events<F: FnOnce(&mut MockDisplayInterface)>(f: F) -> Vec<Event> {
+        let log = new_log();
+        f(&mut display_interface(&log));
+        let events = log.borrow().clone();
+        events
+    }
+
+    pub(crate) struct MockPin {
+        pub name: &'static str,
+        pub log: EventLog,
+    }
+    impl OutputPin for MockPin {
+        fn set_high(&mut self) { self.log.borrow_mut().push(Event::PinHigh(self.name)); }
+        fn set_low(&mut self) { self.log.borrow_mut().push(Event::PinLow(self.name)); }
+    }
+
+    /// The lowest clock frequency of the mocked SPI buses.
+    pub(crate) const MOCK_MIN_FREQUENCY: u32 = 100_000;
+
+    /// A mocked SPI bus; transfers return the queued responses, then zeroes.
+    pub(crate) struct MockSpi {
+        pub log: EventLog,
+        pub responses: Vec<u8>,
+    }
+    impl SpiBus for MockSpi {
+        fn write(&mut self, data: &[u8]) {
+            self.log.borrow_mut().push(Event::SpiWrite(data.to_vec()));
+        }
+
+        fn transfer(&mut self, data: &mut [u8]) {
+            self.log.borrow_mut().push(Event::SpiTransfer(data.to_vec()));
+            for b in data {
+                *b = if self.responses.is_empty() { 0x00 } else { self.responses.remove(0) };
+            }
+        }
+    }
+    impl ConfigurableSpiBus for MockSpi {
+        fn configure(&mut self, config: &SpiConfig) {
+            self.log.borrow_mut().push(Event::SpiConfigured(*config));
+        }
+
+        fn min_frequency(&self) -> u32 { MOCK_MIN_FREQUENCY }
+    }
+
+    /// A mocked SPI bus that sends in the background; background transfers finish only when
+    /// asked to.
+    pub(crate) struct MockBackgroundSpi {
+        pub log: EventLog,
+        pub in_flight: Option<&'static mut [u8]>,
+    }
+    impl SpiBus for MockBackgroundSpi {
+        fn write(&mut self, data: &[u8]) {
+            assert!(self.in_flight.is_none());
+            self.log.borrow_mut().push(Event::SpiWrite(data.to_vec()));
+        }
+
+        fn transfer(&mut self, data: &mut [u8]) {
+            assert!(self.in_flight.is_none());
+            self.log.borrow_mut().push(Event::SpiTransfer(data.to_vec()));
+            data.fill(0x00);
+        }
+    }
+    impl BackgroundSpiBus for MockBackgroundSpi {
+        fn start_write(&mut self, buffer: &'static mut [u8], length: usize) {
+            assert!(self.in_flight.is_none());
+            self.log.borrow_mut().push(Event::SpiWriteStarted(buffer[..length].to_vec()));
+            self.in_flight = Some(buffer);
+        }
+
+        fn finish_write(&mut self) -> Option<&'static mut [u8]> {
+            let buffer = self.in_flight.take()?;
+            self.log.borrow_mut().push(Event::SpiWriteFinished);
+            Some(buffer)
+        }
+    }
+    impl ConfigurableSpiBus for MockBackgroundSpi {
+        fn configure(&mut self, config: &SpiConfig) {
+            assert!(self.in_flight.is_none());
+            self.log.borrow_mut().push(Event::SpiConfigured(*config));
+        }
+
+        fn min_frequency(&self) -> u32 { MOCK_MIN_FREQUENCY }
+    }
+}
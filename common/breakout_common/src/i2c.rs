@@ -0,0 +1,575 @@
+//! Transactions on an I2C bus as the only host, driven by the SAM D21's I2C host (I2CM) registers.
+//!
+//! The controller only talks to the registers through [`I2cRegisters`], so that its state machine
+//! can be tested against a simulated bus on the host. Smart mode is not used; every acknowledgement
+//! is sent by an explicit command.
+
+
+use core::fmt;
+use core::time::Duration;
+
+use crate::instant::Instant;
+
+
+/// The largest 7-bit target address.
+pub const MAX_ADDRESS: u8 = 0x7F;
+
+
+/// The flags that tell the controller what happened on the bus.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct I2cStatus {
+    /// INTFLAG.MB: a byte has been sent, or an error has ended the transfer.
+    pub host_on_bus: bool,
+
+    /// INTFLAG.SB: a byte has been received.
+    pub target_on_bus: bool,
+
+    /// STATUS.RXNACK: the last byte sent was not acknowledged.
+    pub received_nack: bool,
+
+    /// STATUS.ARBLOST: another host took over the bus.
+    pub arbitration_lost: bool,
+
+    /// STATUS.BUSERR: a START or STOP condition turned up where it must not.
+    pub bus_error: bool,
+}
+
+
+/// What the host does after a byte has been received or sent.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum I2cCommand {
+    /// Acknowledge the byte just received and receive another one.
+    AckAndRead,
+
+    /// Refuse the byte just received (if any) and send a STOP condition.
+    NackAndStop,
+}
+impl I2cCommand {
+    /// The value of CTRLB.ACKACT, i.e. whether a NACK is sent.
+    pub const fn ackact(&self) -> bool {
+        match self {
+            Self::AckAndRead => false,
+            Self::NackAndStop => true,
+        }
+    }
+
+    /// The value of CTRLB.CMD.
+    pub const fn cmd(&self) -> u8 {
+        match self {
+            Self::AckAndRead => 0x2,
+            Self::NackAndStop => 0x3,
+        }
+    }
+}
+
+
+/// Access to the registers of an I2C host.
+pub trait I2cRegisters {
+    /// Reads the interrupt and status flags.
+    fn status(&self) -> I2cStatus;
+
+    /// Writes ADDR, which sends a (repeated) START condition and the address byte.
+    fn write_address(&mut self, address_byte: u8);
+
+    /// Writes DATA, which sends the byte.
+    fn write_data(&mut self, byte: u8);
+
+    /// Reads the byte just received from DATA.
+    fn read_data(&mut self) -> u8;
+
+    /// Writes CTRLB.ACKACT and CTRLB.CMD.
+    fn command(&mut self, command: I2cCommand);
+
+    /// Clears the error flags in STATUS and the interrupt flags.
+    fn clear_errors(&mut self);
+
+    /// Forces the bus state to idle, e.g. after a target has stopped responding.
+    fn force_idle(&mut self);
+}
+
+
+/// Whether it was the address or a data byte that the target did not acknowledge.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum NackKind {
+    Address,
+    Data,
+}
+
+
+/// The reason why an I2C transaction failed.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum I2cError {
+    /// The target did not acknowledge a byte.
+    Nack(NackKind),
+
+    /// Another host took over the bus.
+    ArbitrationLost,
+
+    /// A START or STOP condition turned up where it must not.
+    BusError,
+
+    /// The transaction did not finish in time.
+    Timeout,
+
+    /// The target address does not fit into 7 bits.
+    InvalidAddress(u8),
+}
+impl fmt::Display for I2cError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Nack(NackKind::Address) => write!(f, "address not acknowledged"),
+            Self::Nack(NackKind::Data) => write!(f, "data not acknowledged"),
+            Self::ArbitrationLost => write!(f, "arbitration lost"),
+            Self::BusError => write!(f, "bus error"),
+            Self::Timeout => write!(f, "timeout"),
+            Self::InvalidAddress(address) => write!(f, "invalid 7-bit address 0x{:02X}", address),
+        }
+    }
+}
+
+
+/// Rejects addresses that do not fit into 7 bits, before anything is sent.
+const fn check_address(address: u8) -> Result<(), I2cError> {
+    if address > MAX_ADDRESS {
+        return Err(I2cError::InvalidAddress(address));
+    }
+    Ok(())
+}
+
+
+/// An I2C host that performs one transaction at a time and waits for it to finish.
+///
+/// Each transaction must finish within `timeout`, as measured by `clock`. After a failed
+/// transaction, the bus is left idle (if it still belongs to this host at all).
+pub struct I2cController<R, C> {
+    registers: R,
+    clock: C,
+    timeout: Duration,
+}
+impl<R: I2cRegisters, C: Fn() -> Instant> I2cController<R, C> {
+    pub const fn new(registers: R, clock: C, timeout: Duration) -> Self {
+        Self {
+            registers,
+            clock,
+            timeout,
+        }
+    }
+
+    /// Releases the registers.
+    pub fn release(self) -> R {
+        self.registers
+    }
+
+    /// Sends the data to the target with the given 7-bit address. Without data, this only checks
+    /// whether the target acknowledges its address.
+    pub fn write(&mut self, address: u8, data: &[u8]) -> Result<(), I2cError> {
+        check_address(address)?;
+        let started = (self.clock)();
+        let result = self.send(address, data, started);
+        if result.is_ok() {
+            self.registers.command(I2cCommand::NackAndStop);
+        }
+        self.recover_from(result)
+    }
+
+    /// Fills the buffer with data from the target with the given 7-bit address. An empty buffer
+    /// does not touch the bus, since I2C cannot read zero bytes.
+    pub fn read(&mut self, address: u8, buffer: &mut [u8]) -> Result<(), I2cError> {
+        check_address(address)?;
+        let started = (self.clock)();
+        let result = self.receive(address, buffer, started);
+        self.recover_from(result)
+    }
+
+    /// Sends the data to the target with the given 7-bit address, then fills the buffer with data
+    /// from it after a repeated START condition. With an empty buffer, this is a plain write.
+    pub fn write_read(&mut self, address: u8, data: &[u8], buffer: &mut [u8])
+            -> Result<(), I2cError> {
+        check_address(address)?;
+        if buffer.is_empty() {
+            return self.write(address, data);
+        }
+        let started = (self.clock)();
+        let result = self.send(address, data, started)
+            .and_then(|()| self.receive(address, buffer, started));
+        self.recover_from(result)
+    }
+
+    /// Sends a START condition and the address, and waits for the target to respond.
+    fn start(&mut self, address: u8, read: bool, started: Instant) -> Result<(), I2cError> {
+        debug_assert!(address <= MAX_ADDRESS);
+        self.registers.write_address((address << 1) | u8::from(read));
+        let status = self.wait(started)?;
+        if status.received_nack {
+            return Err(I2cError::Nack(NackKind::Address));
+        }
+        Ok(())
+    }
+
+    /// Addresses the target for writing and sends the data, leaving the bus to this host.
+    fn send(&mut self, address: u8, data: &[u8], started: Instant) -> Result<(), I2cError> {
+        self.start(address, false, started)?;
+        for &byte in data {
+            self.registers.write_data(byte);
+            let status = self.wait(started)?;
+            if status.received_nack {
+                return Err(I2cError::Nack(NackKind::Data));
+            }
+        }
+        Ok(())
+    }
+
+    /// Addresses the target for reading and fills the buffer, refusing the last byte and ending
+    /// the transaction.
+    fn receive(&mut self, address: u8, buffer: &mut [u8], started: Instant)
+            -> Result<(), I2cError> {
+        let Some(last) = buffer.len().checked_sub(1) else {
+            return Ok(());
+        };
+        self.start(address, true, started)?;
+        for (i, byte) in buffer.iter_mut().enumerate() {
+            if i > 0 {
+                self.wait(started)?;
+            }
+            *byte = self.registers.read_data();
+            let command = if i == last { I2cCommand::NackAndStop } else { I2cCommand::AckAndRead };
+            self.registers.command(command);
+        }
+        Ok(())
+    }
+
+    /// Waits until the current byte has been sent or received, or something went wrong.
+    fn wait(&mut self, started: Instant) -> Result<I2cStatus, I2cError> {
+        loop {
+            let status = self.registers.status();
+            if status.bus_error {
+                return Err(I2cError::BusError);
+            }
+            if status.arbitration_lost {
+                return Err(I2cError::ArbitrationLost);
+            }
+            if status.host_on_bus || status.target_on_bus {
+                return Ok(status);
+            }
+            if (self.clock)().duration_since(started) >= self.timeout {
+                return Err(I2cError::Timeout);
+            }
+        }
+    }
+
+    /// Leaves the bus idle after a failed transaction.
+    fn recover_from(&mut self, result: Result<(), I2cError>) -> Result<(), I2cError> {
+        match result {
+            Ok(()) | Err(I2cError::InvalidAddress(_)) => {},
+            Err(I2cError::Nack(_)) => {
+                // the bus still belongs to us
+                self.registers.command(I2cCommand::NackAndStop);
+            },
+            Err(I2cError::ArbitrationLost) | Err(I2cError::BusError) => {
+                // the hardware has already let go of the bus
+                self.registers.clear_errors();
+            },
+            Err(I2cError::Timeout) => {
+                // the target may be holding SCL low; try to end the transaction anyway and stop
+                // waiting for the bus
+                self.registers.command(I2cCommand::NackAndStop);
+                self.registers.clear_errors();
+                self.registers.force_idle();
+            },
+        }
+        result
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::cell::Cell;
+    use std::collections::VecDeque;
+    use std::format;
+    use std::vec;
+    use std::vec::Vec;
+
+    use super::*;
+
+    const TIMEOUT: Duration = Duration::from_millis(10);
+
+    /// What the simulated bus saw.
+    #[derive(Clone, Copy, Debug, Eq, PartialEq)]
+    enum Trace {
+        Start(u8, bool),
+        Sent(u8),
+        Received(u8),
+        Ack,
+        Stop,
+        ClearErrors,
+        ForceIdle,
+    }
+
+    /// How the simulated bus misbehaves.
+    #[derive(Clone, Copy, Debug, Eq, PartialEq)]
+    enum Fault {
+        None,
+        ArbitrationLost,
+        BusError,
+        Hang,
+    }
+
+    /// The I2CM registers with a single target on the bus.
+    struct SimulatedRegisters {
+        target_address: u8,
+        target_rx: Vec<u8>,
+        target_tx: VecDeque<u8>,
+        nack_after_bytes: Option<usize>,
+        fault: Fault,
+
+        status: I2cStatus,
+        data: u8,
+        trace: Vec<Trace>,
+    }
+    impl SimulatedRegisters {
+        fn new(target_address: u8) -> Self {
+            Self {
+                target_address,
+                target_rx: Vec::new(),
+                target_tx: VecDeque::new(),
+                nack_after_bytes: None,
+                fault: Fault::None,
+                status: I2cStatus::default(),
+                data: 0,
+                trace: Vec::new(),
+            }
+        }
+
+        fn load_next_byte(&mut self) {
+            self.data = self.target_tx.pop_front()
+                .expect("target has no more bytes");
+            self.status = I2cStatus { target_on_bus: true, ..I2cStatus::default() };
+        }
+    }
+    impl I2cRegisters for SimulatedRegisters {
+        fn status(&self) -> I2cStatus { self.status }
+
+        fn write_address(&mut self, address_byte: u8) {
+            let (address, read) = (address_byte >> 1, address_byte & 1 != 0);
+            self.trace.push(Trace::Start(address, read));
+            self.status = match self.fault {
+                Fault::None if address != self.target_address => I2cStatus {
+                    host_on_bus: true, received_nack: true, ..I2cStatus::default()
+                },
+                Fault::None if read => {
+                    self.load_next_byte();
+                    return;
+                },
+                Fault::None => I2cStatus { host_on_bus: true, ..I2cStatus::default() },
+                Fault::ArbitrationLost => I2cStatus {
+                    host_on_bus: true, arbitration_lost: true, ..I2cStatus::default()
+                },
+                Fault::BusError => I2cStatus {
+                    host_on_bus: true, bus_error: true, ..I2cStatus::default()
+                },
+                Fault::Hang => I2cStatus::default(),
+            };
+        }
+
+        fn write_data(&mut self, byte: u8) {
+            assert!(self.status.host_on_bus && !self.status.received_nack);
+            self.trace.push(Trace::Sent(byte));
+            self.target_rx.push(byte);
+            let nack = self.nack_after_bytes == Some(self.target_rx.len());
+            self.status = I2cStatus {
+                host_on_bus: true, received_nack: nack, ..I2cStatus::default()
+            };
+        }
+
+        fn read_data(&mut self) -> u8 {
+            assert!(self.status.target_on_bus);
+            self.trace.push(Trace::Received(self.data));
+            self.data
+        }
+
+        fn command(&mut self, command: I2cCommand) {
+            match command {
+                I2cCommand::AckAndRead => {
+                    assert!(self.status.target_on_bus);
+                    self.trace.push(Trace::Ack);
+                    self.load_next_byte();
+                },
+                I2cCommand::NackAndStop => {
+                    self.trace.push(Trace::Stop);
+                    self.status = I2cStatus::default();
+                },
+            }
+        }
+
+        fn clear_errors(&mut self) {
+            self.trace.push(Trace::ClearErrors);
+            self.status = I2cStatus::default();
+        }
+
+        fn force_idle(&mut self) {
+            self.trace.push(Trace::ForceIdle);
+        }
+    }
+
+    /// Runs the closure on a controller whose clock advances by 1 ms every time it is read, and
+    /// returns its result and the trace of the bus.
+    fn run<F>(registers: SimulatedRegisters, f: F) -> (Result<(), I2cError>, SimulatedRegisters)
+    where
+        F: FnOnce(&mut I2cController<SimulatedRegisters, &dyn Fn() -> Instant>)
+            -> Result<(), I2cError>,
+    {
+        let millis = Cell::new(0u32);
+        let clock = || {
+            millis.set(millis.get() + 1);
+            Instant::from_micros(millis.get() * 1000)
+        };
+        let mut controller = I2cController::new(
+            registers, &clock as &dyn Fn() -> Instant, TIMEOUT,
+        );
+        let result = f(&mut controller);
+        (result, controller.release())
+    }
+
+    #[test]
+    fn test_commands() {
+        assert_eq!((I2cCommand::AckAndRead.ackact(), I2cCommand::AckAndRead.cmd()), (false, 0x2));
+        assert_eq!((I2cCommand::NackAndStop.ackact(), I2cCommand::NackAndStop.cmd()), (true, 0x3));
+    }
+
+    #[test]
+    fn test_write() {
+        let (result, registers) = run(
+            SimulatedRegisters::new(0x3C),
+            |i2c| i2c.write(0x3C, &[0x00, 0xAF]),
+        );
+        assert_eq!(result, Ok(()));
+        assert_eq!(registers.target_rx, vec![0x00, 0xAF]);
+        assert_eq!(
+            registers.trace,
+            vec![Trace::Start(0x3C, false), Trace::Sent(0x00), Trace::Sent(0xAF), Trace::Stop],
+        );
+    }
+
+    #[test]
+    fn test_read() {
+        let mut registers = SimulatedRegisters::new(0x50);
+        registers.target_tx.extend([1, 2, 3]);
+        let mut buffer = [0u8; 3];
+        let (result, registers) = run(registers, |i2c| i2c.read(0x50, &mut buffer));
+        assert_eq!(result, Ok(()));
+        assert_eq!(buffer, [1, 2, 3]);
+        assert_eq!(
+            registers.trace,
+            vec![
+                Trace::Start(0x50, true),
+                Trace::Received(1),
+                Trace::Ack,
+                Trace::Received(2),
+                Trace::Ack,
+                Trace::Received(3),
+                Trace::Stop,
+            ],
+        );
+
+        // nothing to read, nothing to do
+        let (result, registers) = run(SimulatedRegisters::new(0x50), |i2c| i2c.read(0x50, &mut []));
+        assert_eq!(result, Ok(()));
+        assert!(registers.trace.is_empty());
+    }
+
+    #[test]
+    fn test_write_read() {
+        let mut registers = SimulatedRegisters::new(0x50);
+        registers.target_tx.extend([0xAB, 0xCD]);
+        let mut buffer = [0u8; 2];
+        let (result, registers) = run(registers, |i2c| i2c.write_read(0x50, &[0x10], &mut buffer));
+        assert_eq!(result, Ok(()));
+        assert_eq!(buffer, [0xAB, 0xCD]);
+        assert_eq!(
+            registers.trace,
+            vec![
+                Trace::Start(0x50, false),
+                Trace::Sent(0x10),
+                Trace::Start(0x50, true),
+                Trace::Received(0xAB),
+                Trace::Ack,
+                Trace::Received(0xCD),
+                Trace::Stop,
+            ],
+        );
+    }
+
+    #[test]
+    fn test_nack() {
+        let (result, registers) = run(SimulatedRegisters::new(0x50), |i2c| i2c.write(0x51, &[1]));
+        assert_eq!(result, Err(I2cError::Nack(NackKind::Address)));
+        assert_eq!(registers.trace, vec![Trace::Start(0x51, false), Trace::Stop]);
+
+        let mut registers = SimulatedRegisters::new(0x50);
+        registers.nack_after_bytes = Some(1);
+        let (result, registers) = run(registers, |i2c| i2c.write(0x50, &[1, 2]));
+        assert_eq!(result, Err(I2cError::Nack(NackKind::Data)));
+        assert_eq!(
+            registers.trace,
+            vec![Trace::Start(0x50, false), Trace::Sent(1), Trace::Stop],
+        );
+
+        let mut buffer = [0u8; 1];
+        let (result, _) = run(SimulatedRegisters::new(0x50), |i2c| i2c.read(0x51, &mut buffer));
+        assert_eq!(result, Err(I2cError::Nack(NackKind::Address)));
+        assert_eq!(format!("{}", result.unwrap_err()), "address not acknowledged");
+    }
+
+    #[test]
+    fn test_bus_errors() {
+        for (fault, error) in [
+            (Fault::ArbitrationLost, I2cError::ArbitrationLost),
+            (Fault::BusError, I2cError::BusError),
+        ] {
+            let mut registers = SimulatedRegisters::new(0x50);
+            registers.fault = fault;
+            let (result, registers) = run(registers, |i2c| i2c.write(0x50, &[1]));
+            assert_eq!(result, Err(error));
+            // the bus does not belong to us anymore, so there is no STOP condition
+            assert_eq!(registers.trace, vec![Trace::Start(0x50, false), Trace::ClearErrors]);
+        }
+    }
+
+    #[test]
+    fn test_timeout() {
+        let mut registers = SimulatedRegisters::new(0x50);
+        registers.fault = Fault::Hang;
+        let (result, registers) = run(registers, |i2c| i2c.write(0x50, &[1]));
+        assert_eq!(result, Err(I2cError::Timeout));
+        assert_eq!(
+            registers.trace,
+            vec![Trace::Start(0x50, false), Trace::Stop, Trace::ClearErrors, Trace::ForceIdle],
+        );
+    }
+
+    #[test]
+    fn test_invalid_address() {
+        let (result, registers) = run(SimulatedRegisters::new(0x50), |i2c| i2c.write(0x80, &[1]));
+        assert_eq!(result, Err(I2cError::InvalidAddress(0x80)));
+        assert!(registers.trace.is_empty());
+
+        let mut buffer = [0u8; 1];
+        let (result, registers) = run(
+            SimulatedRegisters::new(0x50),
+            |i2c| i2c.read(0xFF, &mut buffer),
+        );
+        assert_eq!(result, Err(I2cError::InvalidAddress(0xFF)));
+        assert!(registers.trace.is_empty());
+
+        let (result, registers) = run(
+            SimulatedRegisters::new(0x50),
+            |i2c| i2c.write_read(0x80, &[1], &mut buffer),
+        );
+        assert_eq!(result, Err(I2cError::InvalidAddress(0x80)));
+        assert!(registers.trace.is_empty());
+        assert_eq!(format!("{}", result.unwrap_err()), "invalid 7-bit address 0x80");
+    }
+}
@@ -0,0 +1,253 @@
+//! Frame buffers that store a palette index instead of a color for each pixel.
+//!
+//! A 96x96 frame takes up 18 KiB in R5:G6:B5 encoding, but only 4.5 KiB with 4-bit indices. The
+//! indices are expanded into pixel bytes on the fly while the frame is sent to the display.
+
+
+use crate::color::Color;
+use crate::layout::{BYTES_PER_PIXEL, Rect};
+
+
+/// The number of bits that store the palette index of a pixel.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum IndexDepth {
+    /// 16 colors; two pixels per byte, the left one in the upper nibble.
+    Bits4,
+
+    /// 256 colors; one pixel per byte.
+    Bits8,
+}
+impl IndexDepth {
+    #[inline]
+    pub const fn bits(&self) -> usize {
+        match self {
+            Self::Bits4 => 4,
+            Self::Bits8 => 8,
+        }
+    }
+
+    /// The number of colors that can be distinguished.
+    #[inline]
+    pub const fn color_count(&self) -> usize { 1 << self.bits() }
+
+    /// The number of bytes in a row of the given width; rows always start at a byte boundary.
+    #[inline]
+    pub const fn row_bytes(&self, width: usize) -> usize { (width * self.bits()).div_ceil(8) }
+
+    /// The number of bytes in a frame buffer of the given dimensions.
+    #[inline]
+    pub const fn frame_bytes(&self, width: usize, height: usize) -> usize {
+        self.row_bytes(width) * height
+    }
+}
+
+
+/// The colors that palette indices refer to.
+///
+/// The colors are kept in their encoded form so that expanding a pixel is a mere lookup.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct Palette {
+    pixels: [[u8; BYTES_PER_PIXEL]; 256],
+}
+impl Palette {
+    /// Creates a palette starting with the given colors; all other indices are black.
+    ///
+    /// Panics if more than 256 colors are given.
+    pub fn new(colors: &[Color]) -> Self {
+        assert!(colors.len() <= 256);
+        let mut palette = Self { pixels: [Color::BLACK.to_pixel_bytes(); 256] };
+        for (index, color) in colors.iter().enumerate() {
+            palette.pixels[index] = color.to_pixel_bytes();
+        }
+        palette
+    }
+
+    /// The color at the given index, as far as it survived encoding.
+    #[inline]
+    pub fn color(&self, index: u8) -> Color {
+        Color::from_pixel_bytes(self.pixels[usize::from(index)])
+    }
+
+    #[inline]
+    pub fn set_color(&mut self, index: u8, color: Color) {
+        self.pixels[usize::from(index)] = color.to_pixel_bytes();
+    }
+
+    /// The bytes of a pixel with the color at the given index.
+    #[inline]
+    pub fn pixel_bytes(&self, index: u8) -> [u8; BYTES_PER_PIXEL] {
+        self.pixels[usize::from(index)]
+    }
+}
+
+
+/// A frame buffer that stores a palette index for each pixel, backed by a buffer of bytes such as
+/// an array or a `Vec`.
+pub struct IndexedFrameBuffer<B> {
+    buffer: B,
+    width: usize,
+    height: usize,
+    depth: IndexDepth,
+}
+impl<B: AsRef<[u8]> + AsMut<[u8]>> IndexedFrameBuffer<B> {
+    /// Wraps the buffer, which must be exactly [`IndexDepth::frame_bytes`] long.
+    pub fn new(buffer: B, width: usize, height: usize, depth: IndexDepth) -> Self {
+        assert_eq!(buffer.as_ref().len(), depth.frame_bytes(width, height));
+        Self { buffer, width, height, depth }
+    }
+
+    #[inline]
+    pub fn width(&self) -> usize { self.width }
+
+    #[inline]
+    pub fn height(&self) -> usize { self.height }
+
+    #[inline]
+    pub fn depth(&self) -> IndexDepth { self.depth }
+
+    /// The whole area of the frame.
+    #[inline]
+    pub fn area(&self) -> Rect { Rect::new(0, 0, self.width, self.height) }
+
+    /// The raw bytes of the frame.
+    #[inline]
+    pub fn as_bytes(&self) -> &[u8] { self.buffer.as_ref() }
+
+    /// Returns the underlying buffer.
+    pub fn into_inner(self) -> B { self.buffer }
+
+    /// The palette index of the pixel at the given coordinates.
+    pub fn get(&self, x: usize, y: usize) -> u8 {
+        debug_assert!(self.area().contains(x, y));
+        let row = &self.buffer.as_ref()[y * self.depth.row_bytes(self.width)..];
+        match self.depth {
+            IndexDepth::Bits4 => {
+                let byte = row[x / 2];
+                if x.is_multiple_of(2) { byte >> 4 } else { byte & 0x0F }
+            },
+            IndexDepth::Bits8 => row[x],
+        }
+    }
+
+    /// Sets the palette index of the pixel at the given coordinates.
+    #[inline]
+    pub fn set(&mut self, x: usize, y: usize, index: u8) {
+        self.fill_span(x, y, 1, index);
+    }
+
+    /// Sets the palette index of `length` pixels in row `y`, starting at column `x`.
+    pub fn fill_span(&mut self, x: usize, y: usize, length: usize, index: u8) {
+        debug_assert!(x + length <= self.width && y < self.height);
+        debug_assert!(usize::from(index) < self.depth.color_count());
+        let row_bytes = self.depth.row_bytes(self.width);
+        let row = &mut self.buffer.as_mut()[y * row_bytes..(y + 1) * row_bytes];
+        match self.depth {
+            IndexDepth::Bits4 => {
+                for column in x..x + length {
+                    let byte = &mut row[column / 2];
+                    *byte = if column.is_multiple_of(2) {
+                        (*byte & 0x0F) | (index << 4)
+                    } else {
+                        (*byte & 0xF0) | index
+                    };
+                }
+            },
+            IndexDepth::Bits8 => row[x..x + length].fill(index),
+        }
+    }
+
+    /// Sets the palette index of all pixels.
+    pub fn fill(&mut self, index: u8) {
+        debug_assert!(usize::from(index) < self.depth.color_count());
+        let byte = match self.depth {
+            IndexDepth::Bits4 => (index << 4) | index,
+            IndexDepth::Bits8 => index,
+        };
+        self.buffer.as_mut().fill(byte);
+    }
+
+    /// Expands an area of the frame into pixel bytes in row-major order, looking up the colors in
+    /// the palette.
+    ///
+    /// The output must be exactly large enough to hold the area. This has the same shape as
+    /// [`Playfield::draw_area`], so it can be used to stream the frame to the display with
+    /// [`update_area`].
+    ///
+    /// [`Playfield::draw_area`]: crate::playfield::Playfield::draw_area
+    /// [`update_area`]: crate::display::update_area
+    pub fn expand_area(&self, area: Rect, palette: &Palette, output: &mut [u8]) {
+        debug_assert_eq!(output.len(), area.area() * BYTES_PER_PIXEL);
+        debug_assert!(area.right() <= self.width && area.bottom() <= self.height);
+        for (y, output_row) in (area.top..area.bottom())
+                .zip(output.chunks_exact_mut(area.width * BYTES_PER_PIXEL)) {
+            for (x, pixel) in (area.left..area.right())
+                    .zip(output_row.chunks_exact_mut(BYTES_PER_PIXEL)) {
+                pixel.copy_from_slice(&palette.pixel_bytes(self.get(x, y)));
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::vec;
+
+    use super::*;
+
+    #[test]
+    fn test_sizes() {
+        assert_eq!(IndexDepth::Bits4.frame_bytes(96, 96), 4608);
+        assert_eq!(IndexDepth::Bits8.frame_bytes(96, 96), 9216);
+        assert_eq!(IndexDepth::Bits4.frame_bytes(128, 128), 8192);
+        assert_eq!(IndexDepth::Bits4.row_bytes(5), 3);
+        assert_eq!(IndexDepth::Bits4.color_count(), 16);
+        assert_eq!(IndexDepth::Bits8.color_count(), 256);
+    }
+
+    #[test]
+    fn test_4_bit_packing() {
+        let mut frame = IndexedFrameBuffer::new([0u8; 4], 3, 2, IndexDepth::Bits4);
+        frame.set(0, 0, 0xA);
+        frame.set(2, 0, 0x3);
+        frame.fill_span(0, 1, 3, 0x7);
+        frame.set(1, 1, 0xE);
+        assert_eq!(frame.as_bytes(), &[0xA0, 0x30, 0x7E, 0x70]);
+        assert_eq!(frame.get(0, 0), 0xA);
+        assert_eq!(frame.get(1, 0), 0x0);
+        assert_eq!(frame.get(1, 1), 0xE);
+
+        frame.fill(0x5);
+        assert_eq!(frame.into_inner(), [0x55; 4]);
+    }
+
+    #[test]
+    fn test_expand_area() {
+        let palette = Palette::new(&[Color::BLACK, Color::WHITE, Color::new(0xFF, 0x00, 0x00)]);
+        for depth in [IndexDepth::Bits4, IndexDepth::Bits8] {
+            let mut frame = IndexedFrameBuffer::new(
+                vec![0u8; depth.frame_bytes(5, 4)], 5, 4, depth,
+            );
+            frame.fill_span(1, 1, 3, 1);
+            frame.set(2, 2, 2);
+
+            let mut output = [0xAAu8; 3 * 2 * BYTES_PER_PIXEL];
+            frame.expand_area(Rect::new(1, 1, 3, 2), &palette, &mut output);
+            assert_eq!(
+                output,
+                [0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0x00, 0x00, 0xF8, 0x00, 0x00, 0x00],
+            );
+        }
+    }
+
+    #[test]
+    fn test_palette() {
+        let mut palette = Palette::new(&[Color::WHITE]);
+        assert_eq!(palette.color(0), Color::WHITE);
+        assert_eq!(palette.color(255), Color::BLACK);
+        palette.set_color(7, Color::new(0x00, 0xFF, 0x00));
+        assert_eq!(palette.pixel_bytes(7), [0x07, 0xE0]);
+    }
+}
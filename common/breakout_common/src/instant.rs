@@ -0,0 +1,235 @@
+//! Points in time measured by a wrapping microsecond counter.
+
+
+use core::cmp::Ordering;
+use core::ops::{Add, AddAssign, Sub, SubAssign};
+use core::time::Duration;
+
+
+/// The longest duration that can be represented as the difference between two instants
+/// (approx. 71.6 minutes).
+pub const MAX_DURATION: Duration = Duration::from_micros(u32::MAX as u64);
+
+
+/// A point in time as measured by a monotonic clock.
+///
+/// Instants are stored as a 32-bit microsecond counter that wraps around every 2**32 µs
+/// (approx. 71.6 minutes). All arithmetic is performed modulo 2**32, which means that:
+///
+/// * [`Instant::duration_since`] is correct as long as the two instants are less than
+///   [`MAX_DURATION`] apart.
+///
+/// * Comparisons are correct as long as the two instants are less than half of [`MAX_DURATION`]
+///   (approx. 35.8 minutes) apart. Since comparisons wrap around, they are not transitive over
+///   longer spans of time, which is why `Instant` is not [`Ord`].
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct Instant {
+    micros: u32,
+}
+impl Instant {
+    #[inline]
+    pub const fn from_micros(micros: u32) -> Self {
+        Self { micros }
+    }
+
+    /// Assembles an instant from a counter of coarse ticks and the progress within the current
+    /// tick.
+    ///
+    /// `ticks` is the number of whole ticks that have passed, each of which lasts `tick_micros`
+    /// microseconds. Of the current tick, `elapsed_cycles` out of `cycles_per_tick` have passed.
+    /// The tick counter may wrap around; the resulting instant wraps consistently.
+    #[inline]
+    pub const fn from_ticks(
+        ticks: u32,
+        tick_micros: u32,
+        elapsed_cycles: u32,
+        cycles_per_tick: u32,
+    ) -> Self {
+        let sub_tick_micros =
+            (elapsed_cycles as u64 * tick_micros as u64 / cycles_per_tick as u64) as u32;
+        Self {
+            micros: ticks.wrapping_mul(tick_micros).wrapping_add(sub_tick_micros),
+        }
+    }
+
+    /// The raw value of the microsecond counter.
+    #[inline]
+    pub const fn as_micros(&self) -> u32 { self.micros }
+
+    /// The signed difference between this instant and an earlier one, in microseconds.
+    #[inline]
+    const fn signed_micros_since(&self, earlier: Instant) -> i32 {
+        self.micros.wrapping_sub(earlier.micros) as i32
+    }
+
+    /// The amount of time that has passed from `earlier` to this instant.
+    ///
+    /// Since the counter wraps around, an `earlier` that is actually later than this instant is
+    /// indistinguishable from one that is almost [`MAX_DURATION`] earlier; use
+    /// [`Instant::checked_duration_since`] if this is a concern.
+    #[inline]
+    pub const fn duration_since(&self, earlier: Instant) -> Duration {
+        Duration::from_micros(self.micros.wrapping_sub(earlier.micros) as u64)
+    }
+
+    /// The amount of time that has passed from `earlier` to this instant, or `None` if `earlier` is
+    /// (by wrapping comparison) later than this instant.
+    #[inline]
+    pub const fn checked_duration_since(&self, earlier: Instant) -> Option<Duration> {
+        if self.signed_micros_since(earlier) < 0 {
+            None
+        } else {
+            Some(self.duration_since(earlier))
+        }
+    }
+
+    /// The amount of time that has passed since this instant, according to the given clock.
+    #[inline]
+    pub fn elapsed<C: Clock + ?Sized>(&self, clock: &C) -> Duration {
+        clock.now().duration_since(*self)
+    }
+
+    /// Returns the instant the given duration after this one, or `None` if the duration is longer
+    /// than [`MAX_DURATION`].
+    pub fn checked_add(&self, duration: Duration) -> Option<Self> {
+        let micros: u32 = duration.as_micros().try_into().ok()?;
+        Some(Self { micros: self.micros.wrapping_add(micros) })
+    }
+
+    /// Returns the instant the given duration before this one, or `None` if the duration is longer
+    /// than [`MAX_DURATION`].
+    pub fn checked_sub(&self, duration: Duration) -> Option<Self> {
+        let micros: u32 = duration.as_micros().try_into().ok()?;
+        Some(Self { micros: self.micros.wrapping_sub(micros) })
+    }
+}
+impl PartialOrd for Instant {
+    /// Compares two instants, taking wraparound into account. See the type documentation for
+    /// restrictions.
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.signed_micros_since(*other).cmp(&0))
+    }
+}
+impl Add<Duration> for Instant {
+    type Output = Self;
+
+    /// Panics if the duration is longer than [`MAX_DURATION`].
+    fn add(self, rhs: Duration) -> Self::Output {
+        self.checked_add(rhs)
+            .expect("duration too long to add to instant")
+    }
+}
+impl AddAssign<Duration> for Instant {
+    fn add_assign(&mut self, rhs: Duration) { *self = *self + rhs; }
+}
+impl Sub<Duration> for Instant {
+    type Output = Self;
+
+    /// Panics if the duration is longer than [`MAX_DURATION`].
+    fn sub(self, rhs: Duration) -> Self::Output {
+        self.checked_sub(rhs)
+            .expect("duration too long to subtract from instant")
+    }
+}
+impl SubAssign<Duration> for Instant {
+    fn sub_assign(&mut self, rhs: Duration) { *self = *self - rhs; }
+}
+impl Sub<Instant> for Instant {
+    type Output = Duration;
+
+    fn sub(self, rhs: Instant) -> Self::Output {
+        self.duration_since(rhs)
+    }
+}
+
+
+/// A source of monotonic time.
+pub trait Clock {
+    /// Returns the current instant.
+    fn now(&self) -> Instant;
+}
+
+
+#[cfg(test)]
+mod tests {
+    use core::cell::Cell;
+
+    use super::*;
+
+    struct MockClock {
+        micros: Cell<u32>,
+    }
+    impl Clock for MockClock {
+        fn now(&self) -> Instant { Instant::from_micros(self.micros.get()) }
+    }
+
+    fn us(micros: u64) -> Duration { Duration::from_micros(micros) }
+
+    #[test]
+    fn test_duration_since() {
+        let a = Instant::from_micros(1_000);
+        let b = Instant::from_micros(3_500);
+        assert_eq!(b.duration_since(a), us(2_500));
+        assert_eq!(b - a, us(2_500));
+        assert_eq!(b.checked_duration_since(a), Some(us(2_500)));
+        assert_eq!(a.checked_duration_since(b), None);
+        assert_eq!(a.checked_duration_since(a), Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn test_wraparound() {
+        let before = Instant::from_micros(u32::MAX - 99);
+        let after = Instant::from_micros(150);
+        assert_eq!(after.duration_since(before), us(250));
+        assert_eq!(after.checked_duration_since(before), Some(us(250)));
+        assert_eq!(before.checked_duration_since(after), None);
+        assert!(before < after);
+        assert!(after > before);
+        assert_eq!(before + us(250), after);
+        assert_eq!(after - us(250), before);
+    }
+
+    #[test]
+    fn test_comparison() {
+        let a = Instant::from_micros(0x7FFF_0000);
+        let b = Instant::from_micros(0x8000_0000);
+        assert!(a < b);
+        assert!(a <= a);
+        assert_eq!(a.partial_cmp(&a), Some(Ordering::Equal));
+
+        // more than half the range apart: appears to be in the past
+        let c = Instant::from_micros(0);
+        let d = Instant::from_micros(0x8000_0001);
+        assert!(d < c);
+    }
+
+    #[test]
+    fn test_add_limits() {
+        let a = Instant::from_micros(5);
+        assert_eq!(a.checked_add(MAX_DURATION), Some(Instant::from_micros(4)));
+        assert_eq!(a.checked_add(MAX_DURATION + us(1)), None);
+        assert_eq!(a.checked_sub(us(10)), Some(Instant::from_micros(u32::MAX - 4)));
+    }
+
+    #[test]
+    fn test_from_ticks() {
+        // 10 ms ticks, 480_000 cycles per tick (48 MHz)
+        assert_eq!(Instant::from_ticks(0, 10_000, 0, 480_000).as_micros(), 0);
+        assert_eq!(Instant::from_ticks(3, 10_000, 240_000, 480_000).as_micros(), 35_000);
+        assert_eq!(Instant::from_ticks(3, 10_000, 48, 480_000).as_micros(), 30_001);
+
+        // the tick counter wrapping around does not disturb durations
+        let before = Instant::from_ticks(u32::MAX, 10_000, 0, 480_000);
+        let after = Instant::from_ticks(0, 10_000, 240_000, 480_000);
+        assert_eq!(after.duration_since(before), us(15_000));
+        assert!(before < after);
+    }
+
+    #[test]
+    fn test_elapsed() {
+        let clock = MockClock { micros: Cell::new(u32::MAX - 10) };
+        let start = clock.now();
+        clock.micros.set(20);
+        assert_eq!(start.elapsed(&clock), us(31));
+    }
+}
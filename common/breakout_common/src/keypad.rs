@@ -0,0 +1,172 @@
+//! Driver for the 4x4 keypad that is read out via SPI.
+
+
+use core::fmt;
+
+use crate::hal::SpiDevice;
+
+
+/// The state of all keys on the keypad.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct KeypadState {
+    state: u16,
+}
+macro_rules! impl_is_pressed {
+    ($name:ident, $mask:expr) => {
+        pub const fn $name(&self) -> bool {
+            (self.state & $mask) == 0
+        }
+    };
+}
+impl KeypadState {
+    /// The state in which no key is pressed.
+    pub const NONE_PRESSED: Self = Self::from_bits(0xFFFF);
+
+    /// Interprets the 16 bits read from the keypad; a key is pressed if its bit is cleared.
+    #[inline]
+    pub const fn from_bits(state: u16) -> Self {
+        Self { state }
+    }
+
+    /// The keys that are pressed in this state but were not pressed in the previous one.
+    #[inline]
+    pub const fn newly_pressed(&self, previous: &Self) -> Self {
+        Self { state: self.state | !previous.state }
+    }
+
+    impl_is_pressed!(is_1_pressed, 0b0000_0000_0000_0001);
+    impl_is_pressed!(is_2_pressed, 0b0000_0000_0000_0010);
+    impl_is_pressed!(is_3_pressed, 0b0000_0000_0000_0100);
+    impl_is_pressed!(is_a_pressed, 0b0000_0000_0000_1000);
+    impl_is_pressed!(is_4_pressed, 0b0000_0000_0001_0000);
+    impl_is_pressed!(is_5_pressed, 0b0000_0000_0010_0000);
+    impl_is_pressed!(is_6_pressed, 0b0000_0000_0100_0000);
+    impl_is_pressed!(is_b_pressed, 0b0000_0000_1000_0000);
+    impl_is_pressed!(is_7_pressed, 0b0000_0001_0000_0000);
+    impl_is_pressed!(is_8_pressed, 0b0000_0010_0000_0000);
+    impl_is_pressed!(is_9_pressed, 0b0000_0100_0000_0000);
+    impl_is_pressed!(is_c_pressed, 0b0000_1000_0000_0000);
+    // bottom row is different
+    impl_is_pressed!(is_0_pressed, 0b0001_0000_0000_0000);
+    impl_is_pressed!(is_hash_pressed, 0b0010_0000_0000_0000);
+    impl_is_pressed!(is_d_pressed, 0b0100_0000_0000_0000);
+    impl_is_pressed!(is_asterisk_pressed, 0b1000_0000_0000_0000);
+}
+impl fmt::Display for KeypadState {
+    /// Lists the labels of all pressed keys.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        macro_rules! write_pressed {
+            ($test_func:ident, $label:expr) => {
+                if self.$test_func() {
+                    write!(f, "{}", $label)?;
+                }
+            };
+        }
+
+        write_pressed!(is_0_pressed, '0');
+        write_pressed!(is_1_pressed, '1');
+        write_pressed!(is_2_pressed, '2');
+        write_pressed!(is_3_pressed, '3');
+        write_pressed!(is_4_pressed, '4');
+        write_pressed!(is_5_pressed, '5');
+        write_pressed!(is_6_pressed, '6');
+        write_pressed!(is_7_pressed, '7');
+        write_pressed!(is_8_pressed, '8');
+        write_pressed!(is_9_pressed, '9');
+        write_pressed!(is_asterisk_pressed, '*');
+        write_pressed!(is_hash_pressed, '#');
+        write_pressed!(is_a_pressed, 'A');
+        write_pressed!(is_b_pressed, 'B');
+        write_pressed!(is_c_pressed, 'C');
+        write_pressed!(is_d_pressed, 'D');
+        Ok(())
+    }
+}
+
+
+/// A keypad connected via SPI. Its chip-select line is active-high.
+pub struct Keypad<SPI> {
+    spi: SPI,
+}
+impl<SPI: SpiDevice> Keypad<SPI> {
+    /// Takes over the device.
+    pub fn new(spi: SPI) -> Self {
+        Self {
+            spi,
+        }
+    }
+
+    pub fn read(&mut self) -> KeypadState {
+        let mut buf = [0u8; 2];
+
+        self.spi.select();
+
+        // read 16 bits
+        self.spi.transfer(&mut buf);
+
+        self.spi.deselect();
+
+        KeypadState::from_bits(u16::from_be_bytes(buf))
+    }
+
+    /// Releases the device.
+    pub fn release(self) -> SPI {
+        self.spi
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::{format, vec};
+
+    use crate::hal::ChipSelectPolarity;
+    use crate::hal::mock::{Event, MockSpi, new_log, spi_device};
+
+    use super::*;
+
+    #[test]
+    fn test_read() {
+        let log = new_log();
+        let mut keypad = Keypad::new(spi_device(
+            &log,
+            MockSpi { log: log.clone(), responses: vec![0b1110_1111, 0b1111_1110] },
+            ChipSelectPolarity::ActiveHigh,
+        ));
+        let state = keypad.read();
+        assert!(state.is_0_pressed());
+        assert!(state.is_1_pressed());
+        assert!(!state.is_2_pressed());
+        assert!(!state.is_d_pressed());
+        assert_eq!(
+            *log.borrow(),
+            vec![
+                Event::PinLow("cs"),
+                Event::PinHigh("cs"),
+                Event::SpiTransfer(vec![0x00, 0x00]),
+                Event::PinLow("cs"),
+            ],
+        );
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(format!("{}", KeypadState::from_bits(0xFFFF)), "");
+        assert_eq!(format!("{}", KeypadState::from_bits(0b0111_1111_0111_1110)), "1*B");
+    }
+
+    #[test]
+    fn test_newly_pressed() {
+        let previous = KeypadState::from_bits(0b1111_1111_1111_1110);
+        let current = KeypadState::from_bits(0b0111_1111_1111_1100);
+        let pressed = current.newly_pressed(&previous);
+        assert!(!pressed.is_1_pressed());
+        assert!(pressed.is_2_pressed());
+        assert!(pressed.is_asterisk_pressed());
+        assert_eq!(format!("{}", pressed), "2*");
+        assert_eq!(previous.newly_pressed(&current), KeypadState::NONE_PRESSED);
+        assert_eq!(current.newly_pressed(&KeypadState::NONE_PRESSED), current);
+    }
+}
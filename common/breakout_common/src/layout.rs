@@ -0,0 +1,430 @@
+//! Description of the display geometry and how the screen is divided into areas.
+
+
+use crate::fixedpoint::{FixedPoint, FixedPointIntegerValue};
+use crate::orientation::Orientation;
+
+
+/// The number of bytes per pixel in the frame buffer; see [`Color::to_pixel_bytes`] for the
+/// encoding.
+///
+/// [`Color::to_pixel_bytes`]: crate::color::Color::to_pixel_bytes
+pub const BYTES_PER_PIXEL: usize = 2; // R5:G6:B5 encoding
+
+// by height:
+// HUD (score digits etc.)
+// 1px buffer
+// 1px playfield border
+// playfield
+// 1px playfield border
+const HUD_BUFFER_HEIGHT: usize = 1;
+const BORDER_THICKNESS: usize = 1;
+
+/// The number of columns and rows in the RAM of the SSD1351 display controller, which layouts
+/// assume unless told otherwise.
+pub const CONTROLLER_RAM_EXTENT: usize = 128;
+
+
+/// A rectangular area of the screen, in pixels.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Rect {
+    pub left: usize,
+    pub top: usize,
+    pub width: usize,
+    pub height: usize,
+}
+impl Rect {
+    #[inline]
+    pub const fn new(left: usize, top: usize, width: usize, height: usize) -> Self {
+        Self { left, top, width, height }
+    }
+
+    /// The column just right of this rectangle.
+    #[inline]
+    pub const fn right(&self) -> usize { self.left + self.width }
+
+    /// The row just below this rectangle.
+    #[inline]
+    pub const fn bottom(&self) -> usize { self.top + self.height }
+
+    #[inline]
+    pub const fn is_empty(&self) -> bool { self.width == 0 || self.height == 0 }
+
+    #[inline]
+    pub const fn contains(&self, x: usize, y: usize) -> bool {
+        x >= self.left && x < self.right() && y >= self.top && y < self.bottom()
+    }
+
+    /// Whether this rectangle and the other one have at least one pixel in common.
+    #[inline]
+    pub const fn intersects(&self, other: &Rect) -> bool {
+        !self.is_empty() && !other.is_empty()
+            && self.left < other.right() && other.left < self.right()
+            && self.top < other.bottom() && other.top < self.bottom()
+    }
+
+    /// The smallest rectangle that contains both this rectangle and the other one.
+    pub const fn union(&self, other: &Rect) -> Rect {
+        if self.is_empty() {
+            return *other;
+        }
+        if other.is_empty() {
+            return *self;
+        }
+        let left = if self.left < other.left { self.left } else { other.left };
+        let top = if self.top < other.top { self.top } else { other.top };
+        let right = if self.right() > other.right() { self.right() } else { other.right() };
+        let bottom = if self.bottom() > other.bottom() { self.bottom() } else { other.bottom() };
+        Rect::new(left, top, right - left, bottom - top)
+    }
+
+    /// The number of pixels in this rectangle.
+    #[inline]
+    pub const fn area(&self) -> usize { self.width * self.height }
+}
+
+
+/// The geometry of the display and the layout of the game on it.
+///
+/// The HUD occupies the topmost rows of the display; the playfield, surrounded by a 1px border,
+/// takes up the rest.
+///
+/// The display dimensions are those of the image as drawn by the game; if the orientation swaps
+/// axes, they are swapped with respect to the panel's native dimensions.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct DisplayLayout {
+    /// Width of the visible area of the display, in pixels.
+    pub display_width: usize,
+
+    /// Height of the visible area of the display, in pixels.
+    pub display_height: usize,
+
+    /// Height of the HUD at the top of the display, in pixels.
+    pub hud_height: usize,
+
+    /// Index of the first column of the display controller's RAM that is connected to the panel,
+    /// in the panel's native orientation.
+    pub ram_column_offset: u8,
+
+    /// Index of the first row of the display controller's RAM that is connected to the panel, in
+    /// the panel's native orientation.
+    pub ram_row_offset: u8,
+
+    /// The number of columns in the display controller's RAM, in the panel's native orientation.
+    pub ram_columns: usize,
+
+    /// The number of rows in the display controller's RAM, in the panel's native orientation.
+    pub ram_rows: usize,
+
+    /// How the image is rotated and mirrored on the panel.
+    pub orientation: Orientation,
+}
+impl DisplayLayout {
+    /// The 96x96 PSP27801 panel as found on the OLED C click. It is connected to columns 16
+    /// through 111 of the SSD1351.
+    pub const PSP27801: Self = Self::new(96, 96, 8, 16, 0);
+
+    /// A 128x128 panel using the whole RAM of the SSD1351.
+    pub const SSD1351_128X128: Self = Self::new(128, 128, 8, 0, 0);
+
+    /// A 96x64 panel using the whole RAM of the SSD1331.
+    pub const SSD1331_96X64: Self = Self::new(96, 64, 8, 0, 0)
+        .with_ram_size(96, 64);
+
+    /// Creates a new layout for a panel in its native orientation.
+    ///
+    /// Since positions on the playfield are stored as [`FixedPoint`] values, the playfield must be
+    /// at most 127 pixels wide and high; this method panics if the display is too large (or too
+    /// small to fit a playfield at all).
+    pub const fn new(
+        display_width: usize,
+        display_height: usize,
+        hud_height: usize,
+        ram_column_offset: u8,
+        ram_row_offset: u8,
+    ) -> Self {
+        let layout = Self {
+            display_width,
+            display_height,
+            hud_height,
+            ram_column_offset,
+            ram_row_offset,
+            ram_columns: CONTROLLER_RAM_EXTENT,
+            ram_rows: CONTROLLER_RAM_EXTENT,
+            orientation: Orientation::NORMAL,
+        };
+        layout.validated()
+    }
+
+    /// Returns this layout for a display controller whose RAM has the given number of columns and
+    /// rows (in the panel's native orientation) instead of [`CONTROLLER_RAM_EXTENT`] each.
+    ///
+    /// Panics if the panel does not fit into the RAM at its offsets.
+    pub const fn with_ram_size(self, ram_columns: usize, ram_rows: usize) -> Self {
+        let layout = Self {
+            ram_columns,
+            ram_rows,
+            ..self
+        };
+        layout.validated()
+    }
+
+    /// Returns this layout with the image shown in the given orientation on the panel.
+    ///
+    /// If the orientation swaps axes, the display width and height are swapped as well, turning a
+    /// landscape layout into a portrait one and vice versa. Panics under the same conditions as
+    /// [`DisplayLayout::new`].
+    pub const fn with_orientation(self, orientation: Orientation) -> Self {
+        let (panel_width, panel_height) = (self.panel_width(), self.panel_height());
+        let (display_width, display_height) = if orientation.swaps_axes() {
+            (panel_height, panel_width)
+        } else {
+            (panel_width, panel_height)
+        };
+        let layout = Self {
+            display_width,
+            display_height,
+            orientation,
+            ..self
+        };
+        layout.validated()
+    }
+
+    const fn validated(self) -> Self {
+        assert!(self.display_width > 2 * BORDER_THICKNESS);
+        assert!(self.display_height > self.hud_height + HUD_BUFFER_HEIGHT + 2 * BORDER_THICKNESS);
+        let playfield = self.playfield_area();
+        assert!(playfield.width <= FixedPointIntegerValue::MAX as usize);
+        assert!(playfield.height <= FixedPointIntegerValue::MAX as usize);
+        assert!(self.ram_column_offset as usize + self.panel_width() <= self.ram_columns);
+        assert!(self.ram_row_offset as usize + self.panel_height() <= self.ram_rows);
+        self
+    }
+
+    /// The width of the panel in its native orientation.
+    #[inline]
+    pub const fn panel_width(&self) -> usize {
+        if self.orientation.swaps_axes() { self.display_height } else { self.display_width }
+    }
+
+    /// The height of the panel in its native orientation.
+    #[inline]
+    pub const fn panel_height(&self) -> usize {
+        if self.orientation.swaps_axes() { self.display_width } else { self.display_height }
+    }
+
+    /// The window of the display controller's RAM that is visible on the panel, taking into account
+    /// that reversing the columns or the scan direction mirrors the RAM.
+    pub const fn ram_window(&self) -> Rect {
+        let mapping = self.orientation.scan_mapping();
+        let column_start = if mapping.reverse_columns {
+            self.ram_columns - self.ram_column_offset as usize - self.panel_width()
+        } else {
+            self.ram_column_offset as usize
+        };
+        let row_start = if mapping.backward_scan {
+            self.ram_rows - self.ram_row_offset as usize - self.panel_height()
+        } else {
+            self.ram_row_offset as usize
+        };
+        Rect::new(column_start, row_start, self.panel_width(), self.panel_height())
+    }
+
+    /// The window of the display controller's RAM into which the given area of the display is
+    /// written when its pixels are sent in row-major order.
+    ///
+    /// If the orientation swaps axes, the controller fills the window column by column, so the
+    /// window is transposed with respect to the area.
+    pub const fn ram_rect(&self, area: Rect) -> Rect {
+        let window = self.ram_window();
+        if self.orientation.scan_mapping().vertical_increment {
+            Rect::new(window.left + area.top, window.top + area.left, area.height, area.width)
+        } else {
+            Rect::new(window.left + area.left, window.top + area.top, area.width, area.height)
+        }
+    }
+
+    /// Whether the display is wider than it is high.
+    #[inline]
+    pub const fn is_landscape(&self) -> bool { self.display_width > self.display_height }
+
+    /// The number of bytes in a single row of the display.
+    #[inline]
+    pub const fn row_bytes(&self) -> usize { self.display_width * BYTES_PER_PIXEL }
+
+    /// The number of bytes in the whole display.
+    #[inline]
+    pub const fn display_bytes(&self) -> usize { self.display_height * self.row_bytes() }
+
+    /// The whole visible area of the display.
+    #[inline]
+    pub const fn display_area(&self) -> Rect {
+        Rect::new(0, 0, self.display_width, self.display_height)
+    }
+
+    /// The area reserved for the HUD.
+    #[inline]
+    pub const fn hud_area(&self) -> Rect {
+        Rect::new(0, 0, self.display_width, self.hud_height)
+    }
+
+    /// The area in which the game takes place, excluding its border.
+    #[inline]
+    pub const fn playfield_area(&self) -> Rect {
+        let top = self.hud_height + HUD_BUFFER_HEIGHT + BORDER_THICKNESS;
+        Rect::new(
+            BORDER_THICKNESS,
+            top,
+            self.display_width - 2 * BORDER_THICKNESS,
+            self.display_height - top - BORDER_THICKNESS,
+        )
+    }
+
+    /// The area of the playfield including its border.
+    #[inline]
+    pub const fn playfield_border_area(&self) -> Rect {
+        let playfield = self.playfield_area();
+        Rect::new(
+            playfield.left - BORDER_THICKNESS,
+            playfield.top - BORDER_THICKNESS,
+            playfield.width + 2 * BORDER_THICKNESS,
+            playfield.height + 2 * BORDER_THICKNESS,
+        )
+    }
+
+    /// The width of the playfield in game coordinates.
+    #[inline]
+    pub const fn playfield_width(&self) -> FixedPoint {
+        FixedPoint::new_integer(self.playfield_area().width as FixedPointIntegerValue)
+    }
+
+    /// The height of the playfield in game coordinates.
+    #[inline]
+    pub const fn playfield_height(&self) -> FixedPoint {
+        FixedPoint::new_integer(self.playfield_area().height as FixedPointIntegerValue)
+    }
+
+    /// The row of the playfield (relative to its top) at which the paddle is located.
+    #[inline]
+    pub const fn paddle_top(&self) -> usize { self.playfield_area().height - 2 }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::orientation::Rotation;
+
+    #[test]
+    fn test_psp27801() {
+        let layout = DisplayLayout::PSP27801;
+        assert_eq!(layout.display_bytes(), 96 * 96 * 2);
+        assert_eq!(layout.hud_area(), Rect::new(0, 0, 96, 8));
+        assert_eq!(layout.playfield_area(), Rect::new(1, 10, 94, 85));
+        assert_eq!(layout.playfield_border_area(), Rect::new(0, 9, 96, 87));
+        assert_eq!(layout.playfield_width(), FixedPoint::new_integer(94));
+        assert_eq!(layout.playfield_height(), FixedPoint::new_integer(85));
+        assert_eq!(layout.paddle_top(), 83);
+        assert!(!layout.is_landscape());
+    }
+
+    #[test]
+    fn test_128x128() {
+        let layout = DisplayLayout::SSD1351_128X128;
+        assert_eq!(layout.display_bytes(), 128 * 128 * 2);
+        assert_eq!(layout.playfield_area(), Rect::new(1, 10, 126, 117));
+        assert_eq!(layout.playfield_border_area().bottom(), 128);
+        assert_eq!(layout.playfield_border_area().right(), 128);
+    }
+
+    #[test]
+    fn test_ssd1331() {
+        let layout = DisplayLayout::SSD1331_96X64;
+        assert!(layout.is_landscape());
+        assert_eq!(layout.playfield_area(), Rect::new(1, 10, 94, 53));
+        assert_eq!(layout.ram_window(), Rect::new(0, 0, 96, 64));
+
+        // the panel fills the whole RAM, so mirroring it does not move the window
+        let upside_down = layout.with_orientation(Orientation::new(Rotation::Deg180, false));
+        assert_eq!(upside_down.ram_window(), Rect::new(0, 0, 96, 64));
+        let portrait = layout.with_orientation(Orientation::new(Rotation::Deg90, false));
+        assert_eq!(portrait.ram_window(), Rect::new(0, 0, 96, 64));
+    }
+
+    #[test]
+    fn test_landscape() {
+        let layout = DisplayLayout::new(128, 96, 6, 0, 16);
+        assert!(layout.is_landscape());
+        assert_eq!(layout.row_bytes(), 256);
+        assert_eq!(layout.hud_area(), Rect::new(0, 0, 128, 6));
+        assert_eq!(layout.playfield_area(), Rect::new(1, 8, 126, 87));
+        assert_eq!(layout.playfield_border_area().bottom(), 96);
+    }
+
+    #[test]
+    fn test_rotated_landscape() {
+        let panel = DisplayLayout::new(128, 96, 8, 0, 0);
+        assert_eq!(panel.ram_window(), Rect::new(0, 0, 128, 96));
+
+        let portrait = panel.with_orientation(Orientation::new(Rotation::Deg90, false));
+        assert!(!portrait.is_landscape());
+        assert_eq!((portrait.display_width, portrait.display_height), (96, 128));
+        assert_eq!((portrait.panel_width(), portrait.panel_height()), (128, 96));
+        assert_eq!(portrait.playfield_area(), Rect::new(1, 10, 94, 117));
+        assert_eq!(portrait.ram_window(), Rect::new(0, 0, 128, 96));
+
+        let back = portrait.with_orientation(Orientation::new(Rotation::Deg180, false));
+        assert_eq!((back.display_width, back.display_height), (128, 96));
+        assert_eq!(back.ram_window(), Rect::new(0, 32, 128, 96));
+    }
+
+    #[test]
+    fn test_rotated_psp27801() {
+        let layout = DisplayLayout::PSP27801;
+        assert_eq!(layout.ram_window(), Rect::new(16, 0, 96, 96));
+
+        let upside_down = layout.with_orientation(Orientation::new(Rotation::Deg180, false));
+        assert_eq!(upside_down.ram_window(), Rect::new(16, 32, 96, 96));
+
+        let mirrored = layout.with_orientation(Orientation::new(Rotation::Deg0, true));
+        assert_eq!(mirrored.ram_window(), Rect::new(16, 0, 96, 96));
+    }
+
+    #[test]
+    fn test_rect_union_and_intersection() {
+        let a = Rect::new(2, 3, 4, 5);
+        let b = Rect::new(5, 7, 2, 2);
+        let c = Rect::new(6, 3, 1, 1);
+        assert!(a.intersects(&b));
+        assert!(!a.intersects(&c));
+        assert!(!a.intersects(&Rect::new(3, 4, 0, 1)));
+        assert_eq!(a.union(&b), Rect::new(2, 3, 5, 6));
+        assert_eq!(a.union(&c), Rect::new(2, 3, 5, 5));
+        assert_eq!(a.union(&Rect::default()), a);
+        assert_eq!(Rect::default().union(&c), c);
+        assert_eq!(a.area(), 20);
+    }
+
+    #[test]
+    fn test_ram_rect() {
+        let layout = DisplayLayout::PSP27801;
+        assert_eq!(layout.ram_rect(layout.display_area()), layout.ram_window());
+        assert_eq!(layout.ram_rect(Rect::new(3, 40, 8, 1)), Rect::new(19, 40, 8, 1));
+
+        let rotated = layout.with_orientation(Orientation::new(Rotation::Deg270, false));
+        assert_eq!(rotated.ram_rect(rotated.display_area()), rotated.ram_window());
+        assert_eq!(rotated.ram_rect(Rect::new(3, 40, 8, 1)), Rect::new(56, 35, 1, 8));
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_too_wide() {
+        DisplayLayout::new(160, 128, 8, 0, 0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_outside_ram() {
+        DisplayLayout::PSP27801.with_ram_size(96, 96);
+    }
+}
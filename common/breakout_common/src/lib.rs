@@ -1,5 +1,28 @@
 #![no_std]
 
 
+pub mod brightness;
+pub mod burn_in;
+pub mod color;
+pub mod command_queue;
+pub mod controller;
+pub mod display;
+pub mod display_config;
+pub mod dmac;
+pub mod effects;
 pub mod fixedpoint;
+pub mod grayscale;
+pub mod hal;
+pub mod i2c;
+pub mod indexed;
+pub mod instant;
+pub mod keypad;
+pub mod layout;
+pub mod orientation;
 pub mod playfield;
+pub mod sercom;
+pub mod settings;
+pub mod spi_device;
+pub mod ssd1331;
+pub mod ssd1351;
+pub mod timing;
@@ -0,0 +1,171 @@
+//! Rotation and mirroring of the image shown on the display.
+
+
+/// Clockwise rotation of the image on the panel.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Rotation {
+    #[default]
+    Deg0,
+    Deg90,
+    Deg180,
+    Deg270,
+}
+impl Rotation {
+    /// The rotation angle in degrees.
+    #[inline]
+    pub const fn as_degrees(&self) -> u16 {
+        match self {
+            Self::Deg0 => 0,
+            Self::Deg90 => 90,
+            Self::Deg180 => 180,
+            Self::Deg270 => 270,
+        }
+    }
+
+    /// Returns the rotation corresponding to the given angle in degrees, if it is a multiple of 90.
+    pub const fn from_degrees(degrees: u16) -> Option<Self> {
+        match degrees % 360 {
+            0 => Some(Self::Deg0),
+            90 => Some(Self::Deg90),
+            180 => Some(Self::Deg180),
+            270 => Some(Self::Deg270),
+            _ => None,
+        }
+    }
+}
+
+
+/// How the SSD1351 has to scan its RAM to show an image in a specific orientation.
+///
+/// These correspond to the homonymous fields of the controller's `SetMapping` command.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct ScanMapping {
+    /// Whether consecutive pixels are written top-to-bottom instead of left-to-right.
+    pub vertical_increment: bool,
+
+    /// Whether column 0 of the RAM is output on the rightmost segment instead of the leftmost.
+    pub reverse_columns: bool,
+
+    /// Whether row 0 of the RAM is output on the bottommost common instead of the topmost.
+    pub backward_scan: bool,
+}
+
+
+/// The orientation of the image on the panel: first mirrored (if requested), then rotated.
+///
+/// The panel shows the image rotated clockwise by the given angle; an enclosure in which the panel
+/// is mounted rotated counterclockwise by the same angle therefore shows an upright image.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Orientation {
+    pub rotation: Rotation,
+
+    /// Whether the image is mirrored horizontally (before rotation).
+    pub mirror: bool,
+}
+impl Orientation {
+    /// The upright, unmirrored orientation.
+    pub const NORMAL: Self = Self::new(Rotation::Deg0, false);
+
+    #[inline]
+    pub const fn new(rotation: Rotation, mirror: bool) -> Self {
+        Self { rotation, mirror }
+    }
+
+    /// Whether the width and height of the image are swapped with respect to the panel.
+    #[inline]
+    pub const fn swaps_axes(&self) -> bool {
+        matches!(self.rotation, Rotation::Deg90 | Rotation::Deg270)
+    }
+
+    /// The scan mapping with which an SSD1351 shows a row-major image in this orientation.
+    pub const fn scan_mapping(&self) -> ScanMapping {
+        // (x, y) is a pixel of the image, (c, r) is column and row on the panel
+        //   0°: c = x,         r = y          => horizontal
+        //  90°: c = h - 1 - y, r = x          => vertical, reverse columns
+        // 180°: c = w - 1 - x, r = h - 1 - y  => horizontal, reverse columns, backward scan
+        // 270°: c = y,         r = w - 1 - x  => vertical, backward scan
+        // mirroring replaces x with (w - 1 - x) beforehand
+        let (vertical_increment, reverse_columns, backward_scan) = match self.rotation {
+            Rotation::Deg0 => (false, false, false),
+            Rotation::Deg90 => (true, true, false),
+            Rotation::Deg180 => (false, true, true),
+            Rotation::Deg270 => (true, false, true),
+        };
+        if !self.mirror {
+            ScanMapping { vertical_increment, reverse_columns, backward_scan }
+        } else if vertical_increment {
+            // x is output along the rows
+            ScanMapping { vertical_increment, reverse_columns, backward_scan: !backward_scan }
+        } else {
+            // x is output along the columns
+            ScanMapping { vertical_increment, reverse_columns: !reverse_columns, backward_scan }
+        }
+    }
+
+    /// Transforms the coordinates of a pixel of the image (of the given size) into the coordinates
+    /// on the panel.
+    pub const fn transform(&self, x: usize, y: usize, image_width: usize, image_height: usize) -> (usize, usize) {
+        let x = if self.mirror { image_width - 1 - x } else { x };
+        match self.rotation {
+            Rotation::Deg0 => (x, y),
+            Rotation::Deg90 => (image_height - 1 - y, x),
+            Rotation::Deg180 => (image_width - 1 - x, image_height - 1 - y),
+            Rotation::Deg270 => (y, image_width - 1 - x),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Simulates how the SSD1351 places the pixels of a row-major image given a scan mapping.
+    fn scan(mapping: ScanMapping, index: usize, panel_width: usize, panel_height: usize) -> (usize, usize) {
+        let (column, row) = if mapping.vertical_increment {
+            (index / panel_height, index % panel_height)
+        } else {
+            (index % panel_width, index / panel_width)
+        };
+        let column = if mapping.reverse_columns { panel_width - 1 - column } else { column };
+        let row = if mapping.backward_scan { panel_height - 1 - row } else { row };
+        (column, row)
+    }
+
+    #[test]
+    fn test_scan_mapping_matches_transform() {
+        const IMAGE_WIDTH: usize = 5;
+        const IMAGE_HEIGHT: usize = 3;
+
+        for rotation in [Rotation::Deg0, Rotation::Deg90, Rotation::Deg180, Rotation::Deg270] {
+            for mirror in [false, true] {
+                let orientation = Orientation::new(rotation, mirror);
+                let mapping = orientation.scan_mapping();
+                let (panel_width, panel_height) = if orientation.swaps_axes() {
+                    (IMAGE_HEIGHT, IMAGE_WIDTH)
+                } else {
+                    (IMAGE_WIDTH, IMAGE_HEIGHT)
+                };
+
+                for y in 0..IMAGE_HEIGHT {
+                    for x in 0..IMAGE_WIDTH {
+                        let index = y * IMAGE_WIDTH + x;
+                        assert_eq!(
+                            scan(mapping, index, panel_width, panel_height),
+                            orientation.transform(x, y, IMAGE_WIDTH, IMAGE_HEIGHT),
+                            "{:?} at ({}, {})", orientation, x, y,
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_from_degrees() {
+        assert_eq!(Rotation::from_degrees(0), Some(Rotation::Deg0));
+        assert_eq!(Rotation::from_degrees(270), Some(Rotation::Deg270));
+        assert_eq!(Rotation::from_degrees(450), Some(Rotation::Deg90));
+        assert_eq!(Rotation::from_degrees(45), None);
+    }
+}
@@ -1,4 +1,9 @@
+use arrayvec::ArrayVec;
+
+use crate::color::Color;
 use crate::fixedpoint::FixedPoint;
+use crate::indexed::{IndexedFrameBuffer, Palette};
+use crate::layout::{BYTES_PER_PIXEL, DisplayLayout, Rect};
 
 
 #[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
@@ -33,38 +38,91 @@ pub struct Paddle {
 }
 
 
-// the display is 96x96
-//
-// by height:
-// 8px score digits
-// 1px buffer
-// 1px playfield border
-// 85px playfield (with 1px border on each side)
-// 1px playfield border
+pub const DEFAULT_PADDLE_WIDTH: FixedPoint = FixedPoint::new_integer(8);
 
+/// The palette index of the background in indexed frame buffers.
+pub const BACKGROUND_INDEX: u8 = 0;
 
-pub const DISPLAY_WIDTH: usize = 96;
-pub const DISPLAY_HEIGHT: usize = 96;
-pub const PLAYFIELD_WIDTH: FixedPoint = FixedPoint::new_integer(94);
-pub const PLAYFIELD_HEIGHT: FixedPoint = FixedPoint::new_integer(85);
-pub const PLAYFIELD_LEFT: usize = 1;
-pub const PLAYFIELD_TOP: usize = 10;
+/// The palette index of the border, the ball and the paddle in indexed frame buffers.
+pub const FOREGROUND_INDEX: u8 = 1;
 
-pub const BYTES_PER_PIXEL: usize = 2; // R5:G6:B5 encoding
-pub const DISPLAY_ROW_BYTES: usize = DISPLAY_WIDTH * BYTES_PER_PIXEL;
-pub const DISPLAY_BYTES: usize = DISPLAY_HEIGHT * DISPLAY_ROW_BYTES;
+/// The palette with which indexed frame buffers look like the frames drawn by [`Playfield::draw`].
+pub fn default_palette() -> Palette {
+    Palette::new(&[Color::BLACK, Color::WHITE])
+}
 
-pub const PADDLE_TOP: usize = (PLAYFIELD_HEIGHT.as_integer() as usize) - 2;
-pub const DEFAULT_PADDLE_WIDTH: FixedPoint = FixedPoint::new_integer(8);
+/// The maximum number of areas returned by [`Playfield::take_dirty_areas`].
+pub const MAX_DIRTY_AREAS: usize = 4;
+
+/// The areas of the display that need to be redrawn.
+pub type DirtyAreas = ArrayVec<Rect, MAX_DIRTY_AREAS>;
+
+
+/// The areas covered by the moving objects when the display was last brought up to date.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+struct ShownObjects {
+    ball: Rect,
+    paddle: Rect,
+}
+
+
+/// Adds the areas that must be redrawn for an object that moved from `previous` to `current`.
+fn push_moved(dirty: &mut DirtyAreas, previous: Rect, current: Rect) {
+    if previous == current {
+        return;
+    }
+    if previous.intersects(&current) {
+        dirty.push(previous.union(&current));
+    } else {
+        dirty.push(previous);
+        dirty.push(current);
+    }
+}
+
+
+/// Something that the playfield can be drawn onto.
+trait Canvas {
+    /// The part of the display covered by the canvas.
+    fn area(&self) -> Rect;
+
+    /// Lights up `length` pixels in row `y`, starting at column `x`; all of them are within the
+    /// area of the canvas.
+    fn light_span(&mut self, x: usize, y: usize, length: usize);
+}
+
+
+/// Pixel bytes in row-major order covering an area of the display.
+struct PixelArea<'a> {
+    buffer: &'a mut [u8],
+    area: Rect,
+}
+impl Canvas for PixelArea<'_> {
+    fn area(&self) -> Rect { self.area }
+
+    fn light_span(&mut self, x: usize, y: usize, length: usize) {
+        let start = (y - self.area.top) * self.area.width + (x - self.area.left);
+        self.buffer[start*BYTES_PER_PIXEL..(start + length)*BYTES_PER_PIXEL].fill(0xFF);
+    }
+}
+impl<B: AsRef<[u8]> + AsMut<[u8]>> Canvas for IndexedFrameBuffer<B> {
+    fn area(&self) -> Rect { IndexedFrameBuffer::area(self) }
+
+    fn light_span(&mut self, x: usize, y: usize, length: usize) {
+        self.fill_span(x, y, length, FOREGROUND_INDEX);
+    }
+}
 
 
 pub struct Playfield {
+    pub layout: DisplayLayout,
     pub ball: Ball,
     pub paddle: Paddle,
+    shown: Option<ShownObjects>,
 }
 impl Playfield {
-    pub fn new() -> Self {
+    pub fn new(layout: DisplayLayout) -> Self {
         Self {
+            layout,
             ball: Ball {
                 position: Vec2 { x: FixedPoint::zero(), y: FixedPoint::zero() },
                 velocity: Vec2 {
@@ -77,130 +135,193 @@ impl Playfield {
                 left_offset: FixedPoint::new_integer(0),
                 width: DEFAULT_PADDLE_WIDTH,
             },
+            shown: None,
         }
     }
 
-    fn advance_ball(&mut self) {
-        self.ball.position.x += self.ball.velocity.x;
-        self.ball.position.y += self.ball.velocity.y;
-
-        if self.ball.position.x < FixedPoint::zero() {
-            self.ball.position.x = FixedPoint::zero();
-            self.ball.velocity.flip_x();
-        }
-        if self.ball.position.x >= PLAYFIELD_WIDTH {
-            self.ball.position.x = PLAYFIELD_WIDTH - FixedPoint::one();
-            self.ball.velocity.flip_x();
-        }
-        if self.ball.position.y < FixedPoint::zero() {
-            self.ball.position.y = FixedPoint::zero();
-            self.ball.velocity.flip_y();
-        }
-        if self.ball.position.y >= PLAYFIELD_HEIGHT {
-            self.ball.position.y = PLAYFIELD_HEIGHT - FixedPoint::one();
-            self.ball.velocity.flip_y();
+    /// Moves a single coordinate of the ball by its velocity, bouncing off the walls at 0 and at
+    /// `extent`.
+    ///
+    /// The wall checks are performed before the addition so that the coordinate cannot overflow on
+    /// playfields that extend close to the largest representable [`FixedPoint`] value.
+    fn advance_coordinate(position: &mut FixedPoint, velocity: &mut FixedPoint, extent: FixedPoint) {
+        if *velocity < FixedPoint::zero() && *position < -*velocity {
+            *position = FixedPoint::zero();
+            *velocity = -*velocity;
+        } else if *velocity > FixedPoint::zero() && *position >= extent - *velocity {
+            *position = extent - FixedPoint::one();
+            *velocity = -*velocity;
+        } else {
+            *position += *velocity;
         }
     }
 
+    fn advance_ball(&mut self) {
+        Self::advance_coordinate(
+            &mut self.ball.position.x,
+            &mut self.ball.velocity.x,
+            self.layout.playfield_width(),
+        );
+        Self::advance_coordinate(
+            &mut self.ball.position.y,
+            &mut self.ball.velocity.y,
+            self.layout.playfield_height(),
+        );
+    }
+
     /// Advance the playfield simulation by one frame.
     pub fn advance(&mut self) {
         self.advance_ball();
     }
 
-    fn draw_horizontal_line(&self, buffer: &mut [u8], x: usize, y: usize, length: usize) {
-        let y_offset = y * DISPLAY_ROW_BYTES;
-        for my_x in x..(x+length) {
-            for i in 0..BYTES_PER_PIXEL {
-                buffer[y_offset + my_x*BYTES_PER_PIXEL + i] = 0xFF;
-            }
-        }
+    fn draw_pixel<C: Canvas>(canvas: &mut C, x: usize, y: usize) {
+        Self::draw_horizontal_line(canvas, x, y, 1);
     }
 
-    fn draw_vertical_line(&self, buffer: &mut [u8], x: usize, y: usize, length: usize) {
-        for my_y in y..(y+length) {
-            let y_offset = my_y * DISPLAY_ROW_BYTES;
-            for i in 0..BYTES_PER_PIXEL {
-                buffer[y_offset + x*BYTES_PER_PIXEL + i] = 0xFF;
-            }
+    fn draw_horizontal_line<C: Canvas>(canvas: &mut C, x: usize, y: usize, length: usize) {
+        // only the columns that fall into the canvas
+        let area = canvas.area();
+        let start = x.max(area.left);
+        let end = (x + length).min(area.right());
+        if y < area.top || y >= area.bottom() || start >= end {
+            return;
         }
+        canvas.light_span(start, y, end - start);
     }
 
-    /// Draw the border around the playfield into the buffer.
-    fn draw_playfield_border(&self, buffer: &mut [u8]) {
-        debug_assert_eq!(buffer.len(), DISPLAY_BYTES);
+    fn draw_vertical_line<C: Canvas>(canvas: &mut C, x: usize, y: usize, length: usize) {
+        // only the rows that fall into the canvas
+        let area = canvas.area();
+        for my_y in y.max(area.top)..(y + length).min(area.bottom()) {
+            Self::draw_pixel(canvas, x, my_y);
+        }
+    }
 
-        const BORDER_LEFT: usize = PLAYFIELD_LEFT - 1;
-        const BORDER_HORIZONTAL_LENGTH: usize = PLAYFIELD_WIDTH.as_integer() as usize + 2;
+    /// Draw the border around the playfield onto the canvas.
+    fn draw_playfield_border<C: Canvas>(&self, canvas: &mut C) {
+        let border = self.layout.playfield_border_area();
 
         // top border
-        self.draw_horizontal_line(
-            buffer,
-            BORDER_LEFT,
-            PLAYFIELD_TOP - 1,
-            BORDER_HORIZONTAL_LENGTH,
-        );
+        Self::draw_horizontal_line(canvas, border.left, border.top, border.width);
 
         // bottom border
-        self.draw_horizontal_line(
-            buffer,
-            BORDER_LEFT,
-            PLAYFIELD_TOP + (PLAYFIELD_HEIGHT.as_integer() as usize),
-            BORDER_HORIZONTAL_LENGTH,
-        );
+        Self::draw_horizontal_line(canvas, border.left, border.bottom() - 1, border.width);
 
         // left border
-        self.draw_vertical_line(
-            buffer,
-            BORDER_LEFT,
-            PLAYFIELD_TOP - 1,
-            (PLAYFIELD_HEIGHT.as_integer() as usize) + 1,
-        );
+        Self::draw_vertical_line(canvas, border.left, border.top, border.height);
 
         // right border
-        self.draw_vertical_line(
-            buffer,
-            BORDER_LEFT + (PLAYFIELD_WIDTH.as_integer() as usize) + 1,
-            PLAYFIELD_TOP - 1,
-            (PLAYFIELD_HEIGHT.as_integer() as usize) + 1,
-        );
+        Self::draw_vertical_line(canvas, border.right() - 1, border.top, border.height);
     }
 
-    fn draw_ball(&self, buffer: &mut [u8]) {
-        // draw ball
-        let ball_x = PLAYFIELD_LEFT + (self.ball.position.x.as_integer() as usize);
-        let ball_y = PLAYFIELD_TOP + (self.ball.position.y.as_integer() as usize);
-        let ball_offset = ball_y * DISPLAY_ROW_BYTES + ball_x * BYTES_PER_PIXEL;
-        buffer[ball_offset+0] = 0xFF;
-        buffer[ball_offset+1] = 0xFF;
+    /// The area of the display covered by the ball.
+    fn ball_area(&self) -> Rect {
+        let playfield = self.layout.playfield_area();
+        let ball_x = playfield.left + (self.ball.position.x.as_integer() as usize);
+        let ball_y = playfield.top + (self.ball.position.y.as_integer() as usize);
+        Rect::new(ball_x, ball_y, 1, 1)
     }
 
-    fn draw_paddle(&self, buffer: &mut [u8]) {
-        const PADDLE_Y_OFFSET: usize = (PLAYFIELD_TOP + PADDLE_TOP) * DISPLAY_ROW_BYTES;
-        for x in 0..self.paddle.width.as_integer() {
-            let x_in_playfield =
-                PLAYFIELD_LEFT
-                + (self.paddle.left_offset.as_integer() as usize)
-                + (x as usize)
-            ;
-            let paddle_offset = PADDLE_Y_OFFSET + x_in_playfield * BYTES_PER_PIXEL;
-            buffer[paddle_offset+0] = 0xFF;
-            buffer[paddle_offset+1] = 0xFF;
-        }
+    /// The area of the display covered by the paddle.
+    fn paddle_area(&self) -> Rect {
+        let playfield = self.layout.playfield_area();
+        let paddle_x = playfield.left + (self.paddle.left_offset.as_integer() as usize);
+        let paddle_y = playfield.top + self.layout.paddle_top();
+        let paddle_width = self.paddle.width.as_integer() as usize;
+        Rect::new(paddle_x, paddle_y, paddle_width, 1)
+    }
+
+    fn draw_ball<C: Canvas>(&self, canvas: &mut C) {
+        let ball = self.ball_area();
+        Self::draw_pixel(canvas, ball.left, ball.top);
+    }
+
+    fn draw_paddle<C: Canvas>(&self, canvas: &mut C) {
+        let paddle = self.paddle_area();
+        Self::draw_horizontal_line(canvas, paddle.left, paddle.top, paddle.width);
+    }
+
+    fn draw_objects<C: Canvas>(&self, canvas: &mut C) {
+        // draw playfield border
+        self.draw_playfield_border(canvas);
+
+        self.draw_ball(canvas);
+        self.draw_paddle(canvas);
+    }
+
+    /// Draw a rectangular area of the current state of the playfield.
+    ///
+    /// The buffer receives the pixels of the area in row-major order and must be exactly large
+    /// enough to hold them. It is cleared before drawing, so it contains the same pixels as the
+    /// corresponding part of the whole display drawn by [`Playfield::draw`] into a cleared buffer.
+    pub fn draw_area(&self, area: Rect, buffer: &mut [u8]) {
+        debug_assert_eq!(buffer.len(), area.area() * BYTES_PER_PIXEL);
+        debug_assert!(area.right() <= self.layout.display_width);
+        debug_assert!(area.bottom() <= self.layout.display_height);
+
+        buffer.fill(0x00);
+        self.draw_objects(&mut PixelArea { buffer, area });
+    }
+
+    /// Draw the current state of the playfield into an indexed frame buffer of the same size as the
+    /// display, using [`BACKGROUND_INDEX`] and [`FOREGROUND_INDEX`].
+    pub fn draw_indexed<B: AsRef<[u8]> + AsMut<[u8]>>(&self, frame: &mut IndexedFrameBuffer<B>) {
+        debug_assert_eq!(frame.area(), self.layout.display_area());
+        frame.fill(BACKGROUND_INDEX);
+        self.draw_objects(frame);
+    }
+
+    /// Draw a horizontal band of the current state of the playfield.
+    ///
+    /// The band consists of whole rows of the display, starting at `first_row`; its length must be
+    /// a multiple of [`DisplayLayout::row_bytes`] and it must not extend beyond the bottom of the
+    /// display. The band is cleared before drawing. Drawing all bands of the display one after the
+    /// other yields the same image as [`Playfield::draw`] into a cleared buffer, without having to
+    /// keep all of it in memory at once.
+    pub fn draw_band(&self, band: &mut [u8], first_row: usize) {
+        debug_assert_eq!(band.len() % self.layout.row_bytes(), 0);
+        let rows = band.len() / self.layout.row_bytes();
+        self.draw_area(Rect::new(0, first_row, self.layout.display_width, rows), band);
     }
 
     /// Draw the current state of the playfield onto the display.
+    ///
+    /// The buffer must be exactly [`DisplayLayout::display_bytes`] long. Only the pixels of the
+    /// border, the ball and the paddle are written; the rest of the buffer is left as it is.
     pub fn draw(&self, screen: &mut [u8]) {
-        debug_assert_eq!(screen.len(), DISPLAY_BYTES);
+        debug_assert_eq!(screen.len(), self.layout.display_bytes());
+        self.draw_objects(&mut PixelArea { buffer: screen, area: self.layout.display_area() });
+    }
 
-        // draw playfield border
-        self.draw_playfield_border(screen);
+    /// Returns the areas of the display that have changed since the last call and marks the
+    /// current state as shown.
+    ///
+    /// The first call (and the first call after [`Playfield::invalidate`]) returns the whole
+    /// display. Redrawing the returned areas with [`Playfield::draw_area`] brings a display that
+    /// showed the previous state up to date.
+    pub fn take_dirty_areas(&mut self) -> DirtyAreas {
+        let mut dirty = DirtyAreas::new();
+        let current = ShownObjects {
+            ball: self.ball_area(),
+            paddle: self.paddle_area(),
+        };
+        match self.shown.replace(current) {
+            None => dirty.push(self.layout.display_area()),
+            Some(shown) => {
+                push_moved(&mut dirty, shown.ball, current.ball);
+                push_moved(&mut dirty, shown.paddle, current.paddle);
+            },
+        }
+        dirty
+    }
 
-        self.draw_ball(screen);
-        self.draw_paddle(screen);
+    /// Forgets what is shown on the display, so that it is redrawn completely next time.
+    pub fn invalidate(&mut self) {
+        self.shown = None;
     }
 
     pub fn move_paddle_right(&mut self) {
-        if self.paddle.left_offset + self.paddle.width >= PLAYFIELD_WIDTH {
+        if self.paddle.left_offset + self.paddle.width >= self.layout.playfield_width() {
             return;
         }
         self.paddle.left_offset += FixedPoint::new_integer(1);
@@ -213,3 +334,268 @@ impl Playfield {
         self.paddle.left_offset -= FixedPoint::new_integer(1);
     }
 }
+
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use std::vec;
+    use std::vec::Vec;
+
+    use crate::indexed::IndexDepth;
+
+    use super::*;
+
+    fn is_lit(layout: &DisplayLayout, screen: &[u8], x: usize, y: usize) -> bool {
+        let offset = y * layout.row_bytes() + x * BYTES_PER_PIXEL;
+        screen[offset..offset+BYTES_PER_PIXEL].iter().any(|b| *b != 0x00)
+    }
+
+    fn check_border(layout: DisplayLayout) {
+        let playfield = Playfield::new(layout);
+        let mut screen = vec![0u8; layout.display_bytes()];
+        playfield.draw(&mut screen);
+
+        let border = layout.playfield_border_area();
+        for x in border.left..border.right() {
+            assert!(is_lit(&layout, &screen, x, border.top));
+            assert!(is_lit(&layout, &screen, x, border.bottom() - 1));
+        }
+        for y in border.top..border.bottom() {
+            assert!(is_lit(&layout, &screen, border.left, y));
+            assert!(is_lit(&layout, &screen, border.right() - 1, y));
+        }
+
+        // the HUD stays empty
+        let hud = layout.hud_area();
+        for y in hud.top..hud.bottom() {
+            for x in hud.left..hud.right() {
+                assert!(!is_lit(&layout, &screen, x, y));
+            }
+        }
+    }
+
+    #[test]
+    fn test_border_psp27801() {
+        check_border(DisplayLayout::PSP27801);
+    }
+
+    #[test]
+    fn test_border_128x128() {
+        check_border(DisplayLayout::SSD1351_128X128);
+    }
+
+    #[test]
+    fn test_border_landscape() {
+        check_border(DisplayLayout::new(128, 96, 8, 0, 16));
+    }
+
+    /// Draws the playfield pixel by pixel, independently of the drawing code under test.
+    fn reference_frame(playfield: &Playfield) -> Vec<u8> {
+        let layout = playfield.layout;
+        let mut screen = vec![0u8; layout.display_bytes()];
+        let mut light = |x: usize, y: usize| {
+            let offset = y * layout.row_bytes() + x * BYTES_PER_PIXEL;
+            screen[offset..offset+BYTES_PER_PIXEL].fill(0xFF);
+        };
+
+        let border = layout.playfield_border_area();
+        for x in border.left..border.right() {
+            light(x, border.top);
+            light(x, border.bottom() - 1);
+        }
+        for y in border.top..border.bottom() {
+            light(border.left, y);
+            light(border.right() - 1, y);
+        }
+
+        let playfield_area = layout.playfield_area();
+        light(
+            playfield_area.left + playfield.ball.position.x.as_integer() as usize,
+            playfield_area.top + playfield.ball.position.y.as_integer() as usize,
+        );
+        let paddle_left = playfield_area.left + playfield.paddle.left_offset.as_integer() as usize;
+        for x in 0..playfield.paddle.width.as_integer() as usize {
+            light(paddle_left + x, playfield_area.top + layout.paddle_top());
+        }
+
+        screen
+    }
+
+    #[test]
+    fn test_draw_matches_reference() {
+        let layout = DisplayLayout::PSP27801;
+        let mut playfield = Playfield::new(layout);
+        playfield.paddle.left_offset = FixedPoint::new_integer(12);
+        for _ in 0..100 {
+            playfield.advance();
+            let mut screen = vec![0u8; layout.display_bytes()];
+            playfield.draw(&mut screen);
+            assert!(screen == reference_frame(&playfield));
+        }
+    }
+
+    #[test]
+    fn test_draw_keeps_background() {
+        let layout = DisplayLayout::PSP27801;
+        let playfield = Playfield::new(layout);
+        let mut screen = vec![0x55u8; layout.display_bytes()];
+        playfield.draw(&mut screen);
+
+        let reference = reference_frame(&playfield);
+        for (drawn, expected) in screen.iter().zip(reference.iter()) {
+            assert_eq!(*drawn, if *expected == 0x00 { 0x55 } else { 0xFF });
+        }
+    }
+
+    fn check_bands(playfield: &Playfield, band_rows: usize) {
+        let layout = playfield.layout;
+        let screen = reference_frame(playfield);
+
+        let mut band = vec![0xAAu8; band_rows * layout.row_bytes()];
+        for first_row in (0..layout.display_height).step_by(band_rows) {
+            let rows = band_rows.min(layout.display_height - first_row);
+            let band = &mut band[..rows * layout.row_bytes()];
+            playfield.draw_band(band, first_row);
+
+            let start = first_row * layout.row_bytes();
+            assert_eq!(band, &screen[start..start + band.len()], "band at row {}", first_row);
+        }
+    }
+
+    #[test]
+    fn test_bands_match_full_frame() {
+        let layouts = [
+            DisplayLayout::PSP27801,
+            DisplayLayout::SSD1351_128X128,
+            DisplayLayout::new(128, 96, 8, 0, 16),
+        ];
+        for layout in layouts {
+            let mut playfield = Playfield::new(layout);
+            playfield.paddle.left_offset = FixedPoint::new_integer(5);
+            for _ in 0..300 {
+                playfield.advance();
+                for band_rows in [1, 7, 8, layout.display_height] {
+                    check_bands(&playfield, band_rows);
+                }
+            }
+        }
+    }
+
+    /// Copies a rectangular area out of a whole screen.
+    fn crop(layout: &DisplayLayout, screen: &[u8], area: Rect) -> Vec<u8> {
+        let mut pixels = Vec::new();
+        for y in area.top..area.bottom() {
+            let offset = y * layout.row_bytes() + area.left * BYTES_PER_PIXEL;
+            pixels.extend_from_slice(&screen[offset..offset + area.width * BYTES_PER_PIXEL]);
+        }
+        pixels
+    }
+
+    #[test]
+    fn test_area_matches_full_frame() {
+        let layout = DisplayLayout::PSP27801;
+        let mut playfield = Playfield::new(layout);
+        playfield.ball.position = Vec2 {
+            x: FixedPoint::new_integer(3),
+            y: FixedPoint::new_integer(7),
+        };
+        let screen = reference_frame(&playfield);
+
+        let areas = [
+            Rect::new(0, 0, 96, 96),
+            Rect::new(0, 9, 1, 1),
+            Rect::new(2, 15, 5, 4),
+            Rect::new(90, 80, 6, 16),
+            Rect::new(3, 93, 12, 1),
+        ];
+        for area in areas {
+            let mut buffer = vec![0xAAu8; area.area() * BYTES_PER_PIXEL];
+            playfield.draw_area(area, &mut buffer);
+            assert_eq!(buffer, crop(&layout, &screen, area), "{:?}", area);
+        }
+    }
+
+    #[test]
+    fn test_dirty_areas_bring_screen_up_to_date() {
+        let layout = DisplayLayout::SSD1351_128X128;
+        let mut playfield = Playfield::new(layout);
+        assert_eq!(playfield.take_dirty_areas().as_slice(), &[layout.display_area()]);
+        assert!(playfield.take_dirty_areas().is_empty());
+
+        let mut screen = vec![0u8; layout.display_bytes()];
+        playfield.draw(&mut screen);
+        for frame in 0..200 {
+            playfield.advance();
+            if frame % 3 == 0 {
+                playfield.move_paddle_right();
+            }
+
+            // redraw only the dirty areas of the screen
+            for area in playfield.take_dirty_areas() {
+                let mut buffer = vec![0u8; area.area() * BYTES_PER_PIXEL];
+                playfield.draw_area(area, &mut buffer);
+                for (row, pixels) in buffer.chunks(area.width * BYTES_PER_PIXEL).enumerate() {
+                    let offset =
+                        (area.top + row) * layout.row_bytes() + area.left * BYTES_PER_PIXEL;
+                    screen[offset..offset + pixels.len()].copy_from_slice(pixels);
+                }
+            }
+
+            let mut expected = vec![0u8; layout.display_bytes()];
+            playfield.draw(&mut expected);
+            assert!(screen == expected, "frame {}", frame);
+        }
+
+        playfield.invalidate();
+        assert_eq!(playfield.take_dirty_areas().as_slice(), &[layout.display_area()]);
+    }
+
+    #[test]
+    fn test_indexed_matches_full_frame() {
+        let palette = default_palette();
+        for layout in [DisplayLayout::PSP27801, DisplayLayout::new(128, 96, 8, 0, 16)] {
+            let mut playfield = Playfield::new(layout);
+            playfield.paddle.left_offset = FixedPoint::new_integer(30);
+            for depth in [IndexDepth::Bits4, IndexDepth::Bits8] {
+                let mut frame = IndexedFrameBuffer::new(
+                    vec![0xFFu8; depth.frame_bytes(layout.display_width, layout.display_height)],
+                    layout.display_width,
+                    layout.display_height,
+                    depth,
+                );
+                for _ in 0..50 {
+                    playfield.advance();
+                    playfield.draw_indexed(&mut frame);
+
+                    let mut expanded = vec![0u8; layout.display_bytes()];
+                    frame.expand_area(layout.display_area(), &palette, &mut expanded);
+                    let mut screen = vec![0u8; layout.display_bytes()];
+                    playfield.draw(&mut screen);
+                    assert!(expanded == screen);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_ball_bounces_off_right_wall() {
+        let layout = DisplayLayout::SSD1351_128X128;
+        let mut playfield = Playfield::new(layout);
+        playfield.ball.position.x = layout.playfield_width() - FixedPoint::new_raw(1);
+        playfield.ball.position.y = FixedPoint::new_integer(20);
+        playfield.advance();
+        assert_eq!(playfield.ball.position.x, layout.playfield_width() - FixedPoint::one());
+        assert!(playfield.ball.velocity.x < FixedPoint::zero());
+    }
+
+    #[test]
+    fn test_paddle_stops_at_right_wall() {
+        let layout = DisplayLayout::new(128, 96, 8, 0, 16);
+        let mut playfield = Playfield::new(layout);
+        for _ in 0..200 {
+            playfield.move_paddle_right();
+        }
+        assert_eq!(playfield.paddle.left_offset + playfield.paddle.width, layout.playfield_width());
+    }
+}
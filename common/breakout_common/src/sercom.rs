@@ -0,0 +1,653 @@
+//! Pins, pads, clocks and baud rates of the SAM D21's serial communication modules (SERCOMs).
+//!
+//! Each of the six SERCOMs has four pads, which the port multiplexer connects to a few specific
+//! pins, and each mode (SPI, UART, I2C) only supports some assignments of pads to signals. These
+//! calculations do not touch any registers, which keeps them testable on the host.
+
+
+use core::fmt;
+
+
+/// The frequency of the clock feeding the SERCOMs (GCLK0, the main CPU clock).
+pub const SERCOM_CLOCK_FREQUENCY: u32 = 48_000_000;
+
+const CLKCTRL_ID_MASK: u16 = 0b11_1111;
+const CLKCTRL_GEN_SHIFT: u16 = 8;
+const CLKCTRL_GEN_MASK: u16 = 0b1111;
+const CLKCTRL_CLKEN: u16 = 1 << 14;
+
+
+/// A bank of I/O pins.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum PinBank {
+    PA,
+    PB,
+}
+
+
+/// A single I/O pin of the SAM D21.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct PinId {
+    pub bank: PinBank,
+    pub index: u8,
+}
+impl PinId {
+    #[inline]
+    pub const fn new(bank: PinBank, index: u8) -> Self {
+        assert!(index < 32);
+        Self { bank, index }
+    }
+}
+impl fmt::Display for PinId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}{:02}", self.bank, self.index)
+    }
+}
+
+
+/// One of the SERCOM instances.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Sercom {
+    Sercom0,
+    Sercom1,
+    Sercom2,
+    Sercom3,
+    Sercom4,
+    Sercom5,
+}
+impl Sercom {
+    pub const fn index(&self) -> u8 {
+        match self {
+            Self::Sercom0 => 0,
+            Self::Sercom1 => 1,
+            Self::Sercom2 => 2,
+            Self::Sercom3 => 3,
+            Self::Sercom4 => 4,
+            Self::Sercom5 => 5,
+        }
+    }
+
+    /// The bit in the power manager's APBCMASK register that gives power to this SERCOM.
+    #[inline]
+    pub const fn apbc_mask(&self) -> u32 { 1 << (2 + self.index()) }
+
+    /// The ID of this SERCOM's core clock in the generic clock controller.
+    #[inline]
+    pub const fn core_clock_id(&self) -> u8 { 0x14 + self.index() }
+}
+
+
+/// The value of the generic clock controller's CLKCTRL register that connects the clock with the
+/// given ID to the given generator and enables it.
+pub const fn clock_control(id: u8, generator: u8) -> u16 {
+    CLKCTRL_CLKEN
+        | ((generator as u16 & CLKCTRL_GEN_MASK) << CLKCTRL_GEN_SHIFT)
+        | (id as u16 & CLKCTRL_ID_MASK)
+}
+
+
+/// One of the four pads of a SERCOM.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Pad {
+    Pad0,
+    Pad1,
+    Pad2,
+    Pad3,
+}
+impl Pad {
+    pub const fn index(&self) -> u8 {
+        match self {
+            Self::Pad0 => 0,
+            Self::Pad1 => 1,
+            Self::Pad2 => 2,
+            Self::Pad3 => 3,
+        }
+    }
+
+    #[inline]
+    const fn same_as(&self, other: Pad) -> bool { self.index() == other.index() }
+}
+
+
+/// How the port multiplexer connects a pin to a SERCOM pad.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum SercomFunction {
+    /// Peripheral function C.
+    Sercom,
+
+    /// Peripheral function D.
+    SercomAlt,
+}
+
+
+/// A connection between a pin and a SERCOM pad.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct SercomPad {
+    pub pin: PinId,
+    pub sercom: Sercom,
+    pub pad: Pad,
+    pub function: SercomFunction,
+}
+impl SercomPad {
+    const fn new(bank: PinBank, index: u8, function: SercomFunction, sercom: Sercom, pad: Pad)
+            -> Self {
+        Self { pin: PinId::new(bank, index), sercom, pad, function }
+    }
+
+    /// Finds out how the pin can be connected to the SERCOM.
+    pub const fn find(sercom: Sercom, pin: PinId) -> Result<Self, PadError> {
+        let mut i = 0;
+        while i < PAD_TABLE.len() {
+            let entry = PAD_TABLE[i];
+            if entry.sercom.index() == sercom.index()
+                    && entry.pin.bank as u8 == pin.bank as u8
+                    && entry.pin.index == pin.index {
+                return Ok(entry);
+            }
+            i += 1;
+        }
+        Err(PadError::NotOnSercom(pin))
+    }
+}
+
+
+/// All connections between pins and SERCOM pads on the SAM D21G (48 pins).
+pub const PAD_TABLE: [SercomPad; 52] = {
+    use PinBank::{PA, PB};
+    use Sercom::*;
+    use SercomFunction::{Sercom as C, SercomAlt as D};
+    use Pad::*;
+    [
+        SercomPad::new(PA, 0, D, Sercom1, Pad0),
+        SercomPad::new(PA, 1, D, Sercom1, Pad1),
+        SercomPad::new(PA, 4, D, Sercom0, Pad0),
+        SercomPad::new(PA, 5, D, Sercom0, Pad1),
+        SercomPad::new(PA, 6, D, Sercom0, Pad2),
+        SercomPad::new(PA, 7, D, Sercom0, Pad3),
+        SercomPad::new(PA, 8, C, Sercom0, Pad0),
+        SercomPad::new(PA, 8, D, Sercom2, Pad0),
+        SercomPad::new(PA, 9, C, Sercom0, Pad1),
+        SercomPad::new(PA, 9, D, Sercom2, Pad1),
+        SercomPad::new(PA, 10, C, Sercom0, Pad2),
+        SercomPad::new(PA, 10, D, Sercom2, Pad2),
+        SercomPad::new(PA, 11, C, Sercom0, Pad3),
+        SercomPad::new(PA, 11, D, Sercom2, Pad3),
+        SercomPad::new(PA, 12, C, Sercom2, Pad0),
+        SercomPad::new(PA, 12, D, Sercom4, Pad0),
+        SercomPad::new(PA, 13, C, Sercom2, Pad1),
+        SercomPad::new(PA, 13, D, Sercom4, Pad1),
+        SercomPad::new(PA, 14, C, Sercom2, Pad2),
+        SercomPad::new(PA, 14, D, Sercom4, Pad2),
+        SercomPad::new(PA, 15, C, Sercom2, Pad3),
+        SercomPad::new(PA, 15, D, Sercom4, Pad3),
+        SercomPad::new(PA, 16, C, Sercom1, Pad0),
+        SercomPad::new(PA, 16, D, Sercom3, Pad0),
+        SercomPad::new(PA, 17, C, Sercom1, Pad1),
+        SercomPad::new(PA, 17, D, Sercom3, Pad1),
+        SercomPad::new(PA, 18, C, Sercom1, Pad2),
+        SercomPad::new(PA, 18, D, Sercom3, Pad2),
+        SercomPad::new(PA, 19, C, Sercom1, Pad3),
+        SercomPad::new(PA, 19, D, Sercom3, Pad3),
+        SercomPad::new(PA, 20, C, Sercom5, Pad2),
+        SercomPad::new(PA, 20, D, Sercom3, Pad2),
+        SercomPad::new(PA, 21, C, Sercom5, Pad3),
+        SercomPad::new(PA, 21, D, Sercom3, Pad3),
+        SercomPad::new(PA, 22, C, Sercom3, Pad0),
+        SercomPad::new(PA, 22, D, Sercom5, Pad0),
+        SercomPad::new(PA, 23, C, Sercom3, Pad1),
+        SercomPad::new(PA, 23, D, Sercom5, Pad1),
+        SercomPad::new(PA, 24, C, Sercom3, Pad2),
+        SercomPad::new(PA, 24, D, Sercom5, Pad2),
+        SercomPad::new(PA, 25, C, Sercom3, Pad3),
+        SercomPad::new(PA, 25, D, Sercom5, Pad3),
+        SercomPad::new(PA, 30, D, Sercom1, Pad2),
+        SercomPad::new(PA, 31, D, Sercom1, Pad3),
+        SercomPad::new(PB, 2, D, Sercom5, Pad0),
+        SercomPad::new(PB, 3, D, Sercom5, Pad1),
+        SercomPad::new(PB, 8, D, Sercom4, Pad0),
+        SercomPad::new(PB, 9, D, Sercom4, Pad1),
+        SercomPad::new(PB, 10, D, Sercom4, Pad2),
+        SercomPad::new(PB, 11, D, Sercom4, Pad3),
+        SercomPad::new(PB, 22, D, Sercom5, Pad2),
+        SercomPad::new(PB, 23, D, Sercom5, Pad3),
+    ]
+};
+
+
+/// The reason why pins cannot be used for a SERCOM mode.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum PadError {
+    /// The pin cannot be connected to the SERCOM.
+    NotOnSercom(PinId),
+
+    /// The same pad would carry two signals.
+    PadInUse(Pad),
+
+    /// The SERCOM cannot put the signals on these pads.
+    UnsupportedPinout,
+}
+impl fmt::Display for PadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotOnSercom(pin) => write!(f, "{} cannot be connected to this SERCOM", pin),
+            Self::PadInUse(pad) => write!(f, "PAD[{}] would carry two signals", pad.index()),
+            Self::UnsupportedPinout => write!(f, "unsupported pinout"),
+        }
+    }
+}
+
+
+/// Unwraps a `Result` in a const fn, returning the error from the surrounding function.
+macro_rules! const_try {
+    ($result:expr) => {
+        match $result {
+            Ok(value) => value,
+            Err(error) => return Err(error),
+        }
+    };
+}
+
+
+/// The pins of an SPI controller.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct SpiPins {
+    pub data_out: PinId,
+    pub clock: PinId,
+    pub data_in: PinId,
+}
+impl SpiPins {
+    /// Works out the pads of the SERCOM that these pins are connected to.
+    pub const fn resolve(&self, sercom: Sercom) -> Result<SpiPinout, PadError> {
+        let data_out = const_try!(SercomPad::find(sercom, self.data_out));
+        let clock = const_try!(SercomPad::find(sercom, self.clock));
+        let data_in = const_try!(SercomPad::find(sercom, self.data_in));
+        SpiPinout::new(data_out, clock, data_in)
+    }
+}
+
+
+/// The pads of an SPI controller, in a combination supported by the SERCOM.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct SpiPinout {
+    data_out: SercomPad,
+    clock: SercomPad,
+    data_in: SercomPad,
+}
+impl SpiPinout {
+    pub const fn new(data_out: SercomPad, clock: SercomPad, data_in: SercomPad)
+            -> Result<Self, PadError> {
+        if data_in.pad.same_as(data_out.pad) || data_in.pad.same_as(clock.pad) {
+            return Err(PadError::PadInUse(data_in.pad));
+        }
+        let pinout = Self { data_out, clock, data_in };
+        if pinout.data_out_pinout_bits().is_none() {
+            return Err(PadError::UnsupportedPinout);
+        }
+        Ok(pinout)
+    }
+
+    const fn data_out_pinout_bits(&self) -> Option<u8> {
+        match (self.data_out.pad, self.clock.pad) {
+            (Pad::Pad0, Pad::Pad1) => Some(0x0),
+            (Pad::Pad2, Pad::Pad3) => Some(0x1),
+            (Pad::Pad3, Pad::Pad1) => Some(0x2),
+            (Pad::Pad0, Pad::Pad3) => Some(0x3),
+            _ => None,
+        }
+    }
+
+    /// The value of CTRLA.DOPO, which places the outgoing data and the clock.
+    pub const fn dopo(&self) -> u8 {
+        match self.data_out_pinout_bits() {
+            Some(bits) => bits,
+            None => unreachable!(),
+        }
+    }
+
+    /// The value of CTRLA.DIPO, which places the incoming data.
+    #[inline]
+    pub const fn dipo(&self) -> u8 { self.data_in.pad.index() }
+
+    /// The pads in the order data out, clock, data in.
+    #[inline]
+    pub const fn pads(&self) -> [SercomPad; 3] { [self.data_out, self.clock, self.data_in] }
+}
+
+
+/// The pins of an asynchronous UART.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct UartPins {
+    pub transmit: PinId,
+    pub receive: PinId,
+}
+impl UartPins {
+    /// Works out the pads of the SERCOM that these pins are connected to.
+    pub const fn resolve(&self, sercom: Sercom) -> Result<UartPinout, PadError> {
+        let transmit = const_try!(SercomPad::find(sercom, self.transmit));
+        let receive = const_try!(SercomPad::find(sercom, self.receive));
+        UartPinout::new(transmit, receive)
+    }
+}
+
+
+/// The pads of an asynchronous UART without flow control, in a combination supported by the
+/// SERCOM.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct UartPinout {
+    transmit: SercomPad,
+    receive: SercomPad,
+}
+impl UartPinout {
+    pub const fn new(transmit: SercomPad, receive: SercomPad) -> Result<Self, PadError> {
+        if !matches!(transmit.pad, Pad::Pad0 | Pad::Pad2) {
+            return Err(PadError::UnsupportedPinout);
+        }
+        if receive.pad.same_as(transmit.pad) {
+            return Err(PadError::PadInUse(receive.pad));
+        }
+        Ok(Self { transmit, receive })
+    }
+
+    /// The value of CTRLA.TXPO, which places the outgoing data (and the unused external clock on
+    /// the pad after it).
+    pub const fn txpo(&self) -> u8 {
+        match self.transmit.pad {
+            Pad::Pad0 => 0x0,
+            _ => 0x1,
+        }
+    }
+
+    /// The value of CTRLA.RXPO, which places the incoming data.
+    #[inline]
+    pub const fn rxpo(&self) -> u8 { self.receive.pad.index() }
+
+    /// The pads in the order transmit, receive.
+    #[inline]
+    pub const fn pads(&self) -> [SercomPad; 2] { [self.transmit, self.receive] }
+}
+
+
+/// The pins of an I2C host.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct I2cPins {
+    pub data: PinId,
+    pub clock: PinId,
+}
+impl I2cPins {
+    /// Works out the pads of the SERCOM that these pins are connected to; SDA must be on PAD0 and
+    /// SCL on PAD1.
+    pub const fn resolve(&self, sercom: Sercom) -> Result<[SercomPad; 2], PadError> {
+        let data = const_try!(SercomPad::find(sercom, self.data));
+        let clock = const_try!(SercomPad::find(sercom, self.clock));
+        if !data.pad.same_as(Pad::Pad0) || !clock.pad.same_as(Pad::Pad1) {
+            return Err(PadError::UnsupportedPinout);
+        }
+        Ok([data, clock])
+    }
+}
+
+
+/// The reason why a SERCOM cannot generate a baud rate.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum BaudError {
+    /// The rate is too high for the reference clock.
+    TooFast,
+
+    /// The rate is too low for the reference clock.
+    TooSlow,
+}
+impl fmt::Display for BaudError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TooFast => write!(f, "baud rate too high for the reference clock"),
+            Self::TooSlow => write!(f, "baud rate too low for the reference clock"),
+        }
+    }
+}
+
+
+/// The value of the BAUD register that gives the fastest SPI clock not above `max_frequency`.
+///
+/// Clock generation is in synchronous mode, so f_{BAUD} = f_{ref} / (2 * (BAUD + 1)); BAUD is
+/// rounded up to be on the slow side.
+pub const fn spi_baud(reference: u32, max_frequency: u32) -> Result<u8, BaudError> {
+    if max_frequency == 0 {
+        return Err(BaudError::TooSlow);
+    }
+    let divider = (reference as u64).div_ceil(2 * max_frequency as u64);
+    if divider <= 1 {
+        Ok(0)
+    } else if divider > 256 {
+        Err(BaudError::TooSlow)
+    } else {
+        Ok((divider - 1) as u8)
+    }
+}
+
+
+/// The lowest SPI clock frequency that can be generated from the reference clock, i.e. the lowest
+/// `max_frequency` accepted by [`spi_baud`].
+pub const fn spi_min_frequency(reference: u32) -> u32 {
+    reference.div_ceil(2 * 256)
+}
+
+
+/// The value of the BAUD register for a UART with 16x oversampling and arithmetic baud rate
+/// generation.
+///
+/// BAUD = 65_536 * (1 - 16 * (f_{BAUD} / f_{ref})), rounded to the nearest integer.
+pub const fn uart_baud(reference: u32, baud_rate: u32) -> Result<u16, BaudError> {
+    if baud_rate == 0 {
+        return Err(BaudError::TooSlow);
+    }
+    if 16 * baud_rate as u64 >= reference as u64 {
+        return Err(BaudError::TooFast);
+    }
+    let reference = reference as u64;
+    let subtrahend = (65_536 * 16 * baud_rate as u64 + reference / 2) / reference;
+    if subtrahend == 0 {
+        return Err(BaudError::TooSlow);
+    }
+    Ok((65_536 - subtrahend) as u16)
+}
+
+
+/// The value of the BAUD register that gives the fastest I2C clock not above `max_frequency`, when
+/// the bus takes `rise_time_ns` nanoseconds for SCL to rise.
+///
+/// With BAUDLOW set to zero, f_{SCL} = f_{ref} / (10 + 2 * BAUD + f_{ref} * T_{rise}); BAUD is
+/// rounded up to be on the slow side.
+pub const fn i2c_baud(reference: u32, max_frequency: u32, rise_time_ns: u32)
+        -> Result<u8, BaudError> {
+    if max_frequency == 0 {
+        return Err(BaudError::TooSlow);
+    }
+    let period = (reference as u64).div_ceil(max_frequency as u64);
+    let rise = reference as u64 * rise_time_ns as u64 / 1_000_000_000;
+    if period < 10 + rise {
+        return Err(BaudError::TooFast);
+    }
+    let baud = (period - 10 - rise).div_ceil(2);
+    if baud > 255 {
+        Err(BaudError::TooSlow)
+    } else {
+        Ok(baud as u8)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::format;
+
+    use super::*;
+
+    const fn pin(bank: PinBank, index: u8) -> PinId { PinId::new(bank, index) }
+
+    #[test]
+    fn test_table_consistent() {
+        // no pin reaches the same SERCOM twice, and no pin has more than one SERCOM per function
+        for (i, a) in PAD_TABLE.iter().enumerate() {
+            for b in &PAD_TABLE[i + 1..] {
+                assert!(!(a.pin == b.pin && a.sercom == b.sercom), "{:?} {:?}", a, b);
+                assert!(!(a.pin == b.pin && a.function == b.function), "{:?} {:?}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn test_find() {
+        let pad = SercomPad::find(Sercom::Sercom1, pin(PinBank::PA, 19))
+            .expect("PA19 is on SERCOM1");
+        assert_eq!(pad.pad, Pad::Pad3);
+        assert_eq!(pad.function, SercomFunction::Sercom);
+
+        let pad = SercomPad::find(Sercom::Sercom3, pin(PinBank::PA, 19))
+            .expect("PA19 is on SERCOM3");
+        assert_eq!(pad.pad, Pad::Pad3);
+        assert_eq!(pad.function, SercomFunction::SercomAlt);
+
+        assert_eq!(
+            SercomPad::find(Sercom::Sercom0, pin(PinBank::PA, 19)),
+            Err(PadError::NotOnSercom(pin(PinBank::PA, 19))),
+        );
+        assert_eq!(
+            format!("{}", PadError::NotOnSercom(pin(PinBank::PB, 3))),
+            "PB03 cannot be connected to this SERCOM",
+        );
+    }
+
+    #[test]
+    fn test_spi_pinout() {
+        // the Arduino Zero's SPI header
+        let pins = SpiPins {
+            data_out: pin(PinBank::PA, 16),
+            clock: pin(PinBank::PA, 17),
+            data_in: pin(PinBank::PA, 19),
+        };
+        let pinout = pins.resolve(Sercom::Sercom1)
+            .expect("supported pinout");
+        assert_eq!(pinout.dopo(), 0x0);
+        assert_eq!(pinout.dipo(), 3);
+        assert_eq!(pinout.pads()[1].pin, pin(PinBank::PA, 17));
+
+        let pinout = pins.resolve(Sercom::Sercom3)
+            .expect("supported pinout");
+        assert_eq!(pinout.dopo(), 0x0);
+        assert!(pinout.pads().iter().all(|pad| pad.function == SercomFunction::SercomAlt));
+
+        // data out on PAD[3], clock on PAD[1]
+        let pins = SpiPins {
+            data_out: pin(PinBank::PA, 19),
+            clock: pin(PinBank::PA, 17),
+            data_in: pin(PinBank::PA, 16),
+        };
+        let pinout = pins.resolve(Sercom::Sercom1)
+            .expect("supported pinout");
+        assert_eq!(pinout.dopo(), 0x2);
+        assert_eq!(pinout.dipo(), 0);
+
+        // clock on PAD[0] is not possible
+        let pins = SpiPins {
+            data_out: pin(PinBank::PA, 17),
+            clock: pin(PinBank::PA, 16),
+            data_in: pin(PinBank::PA, 19),
+        };
+        assert_eq!(pins.resolve(Sercom::Sercom1), Err(PadError::UnsupportedPinout));
+
+        let pins = SpiPins {
+            data_out: pin(PinBank::PA, 16),
+            clock: pin(PinBank::PA, 17),
+            data_in: pin(PinBank::PA, 16),
+        };
+        assert_eq!(pins.resolve(Sercom::Sercom1), Err(PadError::PadInUse(Pad::Pad0)));
+    }
+
+    #[test]
+    fn test_uart_pinout() {
+        // the EDBG virtual COM port
+        let pins = UartPins { transmit: pin(PinBank::PB, 22), receive: pin(PinBank::PB, 23) };
+        let pinout = pins.resolve(Sercom::Sercom5)
+            .expect("supported pinout");
+        assert_eq!(pinout.txpo(), 0x1);
+        assert_eq!(pinout.rxpo(), 3);
+
+        // the Arduino's D0/D1
+        let pins = UartPins { transmit: pin(PinBank::PA, 10), receive: pin(PinBank::PA, 11) };
+        assert!(pins.resolve(Sercom::Sercom0).is_ok());
+        assert!(pins.resolve(Sercom::Sercom2).is_ok());
+        assert_eq!(
+            pins.resolve(Sercom::Sercom1),
+            Err(PadError::NotOnSercom(pin(PinBank::PA, 10))),
+        );
+
+        // transmitting on PAD[1] is not possible
+        let pins = UartPins { transmit: pin(PinBank::PA, 9), receive: pin(PinBank::PA, 11) };
+        assert_eq!(pins.resolve(Sercom::Sercom0), Err(PadError::UnsupportedPinout));
+    }
+
+    #[test]
+    fn test_i2c_pinout() {
+        let pins = I2cPins { data: pin(PinBank::PA, 22), clock: pin(PinBank::PA, 23) };
+        let pads = pins.resolve(Sercom::Sercom3)
+            .expect("supported pinout");
+        assert_eq!(pads[0].function, SercomFunction::Sercom);
+        let pads = pins.resolve(Sercom::Sercom5)
+            .expect("supported pinout");
+        assert_eq!(pads[0].function, SercomFunction::SercomAlt);
+
+        let swapped = I2cPins { data: pins.clock, clock: pins.data };
+        assert_eq!(swapped.resolve(Sercom::Sercom3), Err(PadError::UnsupportedPinout));
+    }
+
+    #[test]
+    fn test_clocks() {
+        assert_eq!(Sercom::Sercom0.apbc_mask(), 1 << 2);
+        assert_eq!(Sercom::Sercom5.apbc_mask(), 1 << 7);
+        assert_eq!(Sercom::Sercom1.core_clock_id(), 0x15);
+        assert_eq!(clock_control(Sercom::Sercom1.core_clock_id(), 0), 0x4015);
+        assert_eq!(clock_control(Sercom::Sercom3.core_clock_id(), 2), 0x4217);
+    }
+
+    #[test]
+    fn test_spi_baud() {
+        assert_eq!(spi_baud(SERCOM_CLOCK_FREQUENCY, 10_000_000), Ok(2));
+        assert_eq!(spi_baud(SERCOM_CLOCK_FREQUENCY, 4_800_000), Ok(4));
+        assert_eq!(spi_baud(SERCOM_CLOCK_FREQUENCY, 1_000_000), Ok(23));
+        assert_eq!(spi_baud(SERCOM_CLOCK_FREQUENCY, 24_000_000), Ok(0));
+        assert_eq!(spi_baud(SERCOM_CLOCK_FREQUENCY, 100_000_000), Ok(0));
+        assert_eq!(spi_baud(SERCOM_CLOCK_FREQUENCY, 93_750), Ok(255));
+        assert_eq!(spi_baud(SERCOM_CLOCK_FREQUENCY, 93_749), Err(BaudError::TooSlow));
+        assert_eq!(spi_baud(SERCOM_CLOCK_FREQUENCY, 0), Err(BaudError::TooSlow));
+
+        assert_eq!(spi_min_frequency(SERCOM_CLOCK_FREQUENCY), 93_750);
+        for reference in [SERCOM_CLOCK_FREQUENCY, 8_000_000, 1_000_001] {
+            let min_frequency = spi_min_frequency(reference);
+            assert!(spi_baud(reference, min_frequency).is_ok());
+            assert_eq!(spi_baud(reference, min_frequency - 1), Err(BaudError::TooSlow));
+        }
+    }
+
+    #[test]
+    fn test_uart_baud() {
+        assert_eq!(uart_baud(SERCOM_CLOCK_FREQUENCY, 115_200), Ok(63_019));
+        assert_eq!(uart_baud(SERCOM_CLOCK_FREQUENCY, 9_600), Ok(65_326));
+        assert_eq!(uart_baud(SERCOM_CLOCK_FREQUENCY, 3_000_000), Err(BaudError::TooFast));
+        assert_eq!(uart_baud(SERCOM_CLOCK_FREQUENCY, 1), Err(BaudError::TooSlow));
+        assert_eq!(
+            format!("{}", BaudError::TooFast),
+            "baud rate too high for the reference clock",
+        );
+    }
+
+    #[test]
+    fn test_i2c_baud() {
+        // standard and fast mode with the maximum rise times the I2C specification allows
+        assert_eq!(i2c_baud(SERCOM_CLOCK_FREQUENCY, 100_000, 1_000), Ok(211));
+        assert_eq!(i2c_baud(SERCOM_CLOCK_FREQUENCY, 400_000, 300), Ok(48));
+        assert_eq!(i2c_baud(SERCOM_CLOCK_FREQUENCY, 4_000_000, 300), Err(BaudError::TooFast));
+        assert_eq!(i2c_baud(SERCOM_CLOCK_FREQUENCY, 10_000, 300), Err(BaudError::TooSlow));
+    }
+}
@@ -0,0 +1,97 @@
+//! User settings that outlive a single game, and their encoding for storage.
+//!
+//! The encoding is a version byte followed by one byte per setting. It is the same on the board and
+//! on the host, so that a settings file can be moved between them.
+
+
+use core::fmt;
+
+use crate::brightness::Brightness;
+
+
+/// The version of the encoding produced by [`Settings::to_bytes`].
+pub const FORMAT_VERSION: u8 = 1;
+
+/// The number of bytes in encoded settings.
+pub const SETTINGS_BYTES: usize = 2;
+
+
+/// The reason why encoded settings could not be decoded.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum SettingsError {
+    /// The number of bytes does not match [`SETTINGS_BYTES`].
+    WrongLength(usize),
+
+    /// The settings were stored in a format this version does not know.
+    UnknownVersion(u8),
+
+    /// The stored brightness level is out of range.
+    InvalidBrightness(u8),
+}
+impl fmt::Display for SettingsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::WrongLength(length)
+                => write!(f, "expected {} bytes, got {}", SETTINGS_BYTES, length),
+            Self::UnknownVersion(version)
+                => write!(f, "unknown settings format version {}", version),
+            Self::InvalidBrightness(level)
+                => write!(f, "invalid brightness level {}", level),
+        }
+    }
+}
+
+
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct Settings {
+    pub brightness: Brightness,
+}
+impl Settings {
+    pub fn to_bytes(&self) -> [u8; SETTINGS_BYTES] {
+        [FORMAT_VERSION, self.brightness.level()]
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, SettingsError> {
+        let [version, brightness_level]: [u8; SETTINGS_BYTES] = bytes.try_into()
+            .map_err(|_| SettingsError::WrongLength(bytes.len()))?;
+        if version != FORMAT_VERSION {
+            return Err(SettingsError::UnknownVersion(version));
+        }
+        let brightness = Brightness::new(brightness_level)
+            .ok_or(SettingsError::InvalidBrightness(brightness_level))?;
+        Ok(Self { brightness })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::format;
+
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let settings = Settings { brightness: Brightness::new(2).unwrap() };
+        let bytes = settings.to_bytes();
+        assert_eq!(bytes, [FORMAT_VERSION, 2]);
+        assert_eq!(Settings::from_bytes(&bytes), Ok(settings));
+        assert_eq!(Settings::default().brightness, Brightness::MAX);
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        assert_eq!(Settings::from_bytes(&[FORMAT_VERSION]), Err(SettingsError::WrongLength(1)));
+        assert_eq!(Settings::from_bytes(&[0, 2]), Err(SettingsError::UnknownVersion(0)));
+        assert_eq!(
+            Settings::from_bytes(&[FORMAT_VERSION, 8]),
+            Err(SettingsError::InvalidBrightness(8)),
+        );
+        assert_eq!(
+            format!("{}", SettingsError::WrongLength(3)),
+            "expected 2 bytes, got 3",
+        );
+    }
+}
@@ -0,0 +1,303 @@
+//! Devices on SPI buses, each with its own chip-select line and bus settings.
+//!
+//! A device that has a bus to itself is an [`ExclusiveSpiDevice`]. Devices that share a bus get
+//! their handles from a [`SharedSpiBus`], which reconfigures the bus whenever a device with
+//! different settings is selected and makes sure that only one device is selected at a time.
+
+
+use core::cell::{Cell, RefCell};
+
+use crate::hal::{
+    BackgroundSpiBus, ChipSelectPolarity, ConfigurableSpiBus, OutputPin, SpiBus, SpiConfig,
+    SpiDevice,
+};
+use crate::sercom::BaudError;
+
+
+/// Drives a chip-select line so that the device is selected or not.
+#[inline]
+fn set_selected<CS: OutputPin>(chip_select: &mut CS, polarity: ChipSelectPolarity, selected: bool) {
+    chip_select.set_level(selected == (polarity == ChipSelectPolarity::ActiveHigh));
+}
+
+
+/// The only device on a bus, which is expected to be set up for it already.
+pub struct ExclusiveSpiDevice<SPI, CS> {
+    spi: SPI,
+    chip_select: CS,
+    polarity: ChipSelectPolarity,
+}
+impl<SPI: SpiBus, CS: OutputPin> ExclusiveSpiDevice<SPI, CS> {
+    /// Takes over the bus and the chip-select line, deselecting the device.
+    pub fn new(spi: SPI, mut chip_select: CS, polarity: ChipSelectPolarity) -> Self {
+        set_selected(&mut chip_select, polarity, false);
+        Self {
+            spi,
+            chip_select,
+            polarity,
+        }
+    }
+
+    /// Releases the bus and the chip-select line.
+    pub fn release(self) -> (SPI, CS) {
+        (self.spi, self.chip_select)
+    }
+}
+impl<SPI: SpiBus, CS: OutputPin> SpiBus for ExclusiveSpiDevice<SPI, CS> {
+    fn write(&mut self, data: &[u8]) { self.spi.write(data) }
+    fn transfer(&mut self, data: &mut [u8]) { self.spi.transfer(data) }
+}
+impl<SPI: BackgroundSpiBus, CS: OutputPin> BackgroundSpiBus for ExclusiveSpiDevice<SPI, CS> {
+    fn start_write(&mut self, buffer: &'static mut [u8], length: usize) {
+        self.spi.start_write(buffer, length)
+    }
+
+    fn finish_write(&mut self) -> Option<&'static mut [u8]> { self.spi.finish_write() }
+}
+impl<SPI: SpiBus, CS: OutputPin> SpiDevice for ExclusiveSpiDevice<SPI, CS> {
+    fn select(&mut self) { set_selected(&mut self.chip_select, self.polarity, true) }
+    fn deselect(&mut self) { set_selected(&mut self.chip_select, self.polarity, false) }
+}
+
+
+/// An SPI bus shared by several devices, which get their handles from [`SharedSpiBus::device`].
+pub struct SharedSpiBus<SPI> {
+    spi: RefCell<SPI>,
+
+    /// The settings the bus currently has, if they are known.
+    config: Cell<Option<SpiConfig>>,
+
+    /// The number of the selected device, if any.
+    selected: Cell<Option<u8>>,
+
+    /// The number of the next device to be handed out.
+    next_device: Cell<u8>,
+}
+impl<SPI: ConfigurableSpiBus> SharedSpiBus<SPI> {
+    /// Takes over the bus. It is configured when the first device is selected.
+    pub const fn new(spi: SPI) -> Self {
+        Self {
+            spi: RefCell::new(spi),
+            config: Cell::new(None),
+            selected: Cell::new(None),
+            next_device: Cell::new(0),
+        }
+    }
+
+    /// Hands out a handle to a device on this bus, deselecting the device.
+    ///
+    /// Fails if the bus cannot run as slowly as the settings of the device require, so that every
+    /// handle's settings can be applied when it is selected.
+    pub fn device<CS: OutputPin>(
+        &self,
+        config: SpiConfig,
+        mut chip_select: CS,
+        polarity: ChipSelectPolarity,
+    ) -> Result<SharedSpiDevice<'_, SPI, CS>, BaudError> {
+        if config.max_frequency < self.spi.borrow().min_frequency() {
+            return Err(BaudError::TooSlow);
+        }
+        let number = self.next_device.get();
+        self.next_device.set(number.checked_add(1).expect("too many devices"));
+        set_selected(&mut chip_select, polarity, false);
+        Ok(SharedSpiDevice {
+            bus: self,
+            number,
+            config,
+            chip_select,
+            polarity,
+        })
+    }
+
+    /// Whether one of the devices is selected.
+    pub fn is_busy(&self) -> bool { self.selected.get().is_some() }
+
+    /// Releases the bus; all device handles must have been dropped.
+    pub fn release(self) -> SPI {
+        self.spi.into_inner()
+    }
+}
+
+
+/// A device on a [`SharedSpiBus`].
+///
+/// Selecting it while another device of the same bus is selected panics.
+pub struct SharedSpiDevice<'a, SPI, CS> {
+    bus: &'a SharedSpiBus<SPI>,
+    number: u8,
+    config: SpiConfig,
+    chip_select: CS,
+    polarity: ChipSelectPolarity,
+}
+impl<SPI, CS> SharedSpiDevice<'_, SPI, CS> {
+    #[inline]
+    pub const fn config(&self) -> &SpiConfig { &self.config }
+
+    #[inline]
+    fn is_selected(&self) -> bool { self.bus.selected.get() == Some(self.number) }
+}
+impl<SPI: SpiBus, CS: OutputPin> SpiBus for SharedSpiDevice<'_, SPI, CS> {
+    fn write(&mut self, data: &[u8]) {
+        debug_assert!(self.is_selected());
+        self.bus.spi.borrow_mut().write(data);
+    }
+
+    fn transfer(&mut self, data: &mut [u8]) {
+        debug_assert!(self.is_selected());
+        self.bus.spi.borrow_mut().transfer(data);
+    }
+}
+impl<SPI: BackgroundSpiBus, CS: OutputPin> BackgroundSpiBus for SharedSpiDevice<'_, SPI, CS> {
+    fn start_write(&mut self, buffer: &'static mut [u8], length: usize) {
+        debug_assert!(self.is_selected());
+        self.bus.spi.borrow_mut().start_write(buffer, length);
+    }
+
+    fn finish_write(&mut self) -> Option<&'static mut [u8]> {
+        self.bus.spi.borrow_mut().finish_write()
+    }
+}
+impl<SPI: ConfigurableSpiBus, CS: OutputPin> SpiDevice for SharedSpiDevice<'_, SPI, CS> {
+    fn select(&mut self) {
+        match self.bus.selected.get() {
+            None => {},
+            Some(number) if number == self.number => return,
+            Some(number) => {
+                panic!("SPI device {} selected while device {} is", self.number, number);
+            },
+        }
+        if self.bus.config.get() != Some(self.config) {
+            self.bus.spi.borrow_mut().configure(&self.config);
+            self.bus.config.set(Some(self.config));
+        }
+        set_selected(&mut self.chip_select, self.polarity, true);
+        self.bus.selected.set(Some(self.number));
+    }
+
+    fn deselect(&mut self) {
+        if self.is_selected() {
+            set_selected(&mut self.chip_select, self.polarity, false);
+            self.bus.selected.set(None);
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::vec;
+
+    use crate::hal::SpiMode;
+    use crate::hal::mock::{Event, MOCK_MIN_FREQUENCY, MockPin, MockSpi, new_log, spi_device};
+
+    use super::*;
+
+    const FAST: SpiConfig = SpiConfig { mode: SpiMode::Mode3, max_frequency: 10_000_000 };
+    const SLOW: SpiConfig = SpiConfig { mode: SpiMode::Mode0, max_frequency: 1_000_000 };
+
+    #[test]
+    fn test_exclusive() {
+        let log = new_log();
+        let mut device = spi_device(
+            &log, MockSpi { log: log.clone(), responses: vec![] }, ChipSelectPolarity::ActiveHigh,
+        );
+        device.select();
+        device.write(&[1, 2]);
+        device.deselect();
+        assert_eq!(
+            *log.borrow(),
+            vec![
+                Event::PinLow("cs"),
+                Event::PinHigh("cs"),
+                Event::SpiWrite(vec![1, 2]),
+                Event::PinLow("cs"),
+            ],
+        );
+    }
+
+    #[test]
+    fn test_shared() {
+        let log = new_log();
+        let bus = SharedSpiBus::new(MockSpi { log: log.clone(), responses: vec![0xAB] });
+        let mut display = bus.device(
+            FAST, MockPin { name: "display", log: log.clone() }, ChipSelectPolarity::ActiveLow,
+        ).unwrap();
+        let mut keypad = bus.device(
+            SLOW, MockPin { name: "keypad", log: log.clone() }, ChipSelectPolarity::ActiveHigh,
+        ).unwrap();
+        assert_eq!(
+            *log.borrow(),
+            vec![Event::PinHigh("display"), Event::PinLow("keypad")],
+        );
+        log.borrow_mut().clear();
+
+        display.select();
+        assert!(bus.is_busy());
+        display.write(&[1]);
+        display.deselect();
+        assert!(!bus.is_busy());
+
+        // the bus is only reconfigured when the settings change
+        display.select();
+        display.deselect();
+
+        let mut buffer = [0u8; 1];
+        keypad.select();
+        keypad.transfer(&mut buffer);
+        keypad.deselect();
+        assert_eq!(buffer, [0xAB]);
+
+        assert_eq!(
+            *log.borrow(),
+            vec![
+                Event::SpiConfigured(FAST),
+                Event::PinLow("display"),
+                Event::SpiWrite(vec![1]),
+                Event::PinHigh("display"),
+                Event::PinLow("display"),
+                Event::PinHigh("display"),
+                Event::SpiConfigured(SLOW),
+                Event::PinHigh("keypad"),
+                Event::SpiTransfer(vec![0]),
+                Event::PinLow("keypad"),
+            ],
+        );
+
+        drop((display, keypad));
+        bus.release();
+    }
+
+    #[test]
+    fn test_too_slow() {
+        let log = new_log();
+        let bus = SharedSpiBus::new(MockSpi { log: log.clone(), responses: vec![] });
+        let slowest = SpiConfig { mode: SpiMode::Mode0, max_frequency: MOCK_MIN_FREQUENCY };
+        let too_slow = SpiConfig { max_frequency: MOCK_MIN_FREQUENCY - 1, ..slowest };
+        assert!(bus.device(
+            too_slow, MockPin { name: "slow", log: log.clone() }, ChipSelectPolarity::ActiveLow,
+        ).is_err());
+        assert!(bus.device(
+            slowest, MockPin { name: "slow", log: log.clone() }, ChipSelectPolarity::ActiveLow,
+        ).is_ok());
+
+        // the rejected device never touched its chip-select line
+        assert_eq!(*log.borrow(), vec![Event::PinHigh("slow")]);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_one_at_a_time() {
+        let log = new_log();
+        let bus = SharedSpiBus::new(MockSpi { log: log.clone(), responses: vec![] });
+        let mut display = bus.device(
+            FAST, MockPin { name: "display", log: log.clone() }, ChipSelectPolarity::ActiveLow,
+        ).unwrap();
+        let mut keypad = bus.device(
+            SLOW, MockPin { name: "keypad", log: log.clone() }, ChipSelectPolarity::ActiveHigh,
+        ).unwrap();
+        display.select();
+        keypad.select();
+    }
+}
@@ -0,0 +1,564 @@
+//! Driver for the SSD1331 OLED display controller, as used on 96x64 pixel panels.
+//!
+//! The SSD1331 is a smaller relative of the SSD1351 with a 96x64 RAM. Unlike the SSD1351, it
+//! expects the parameters of a command to be sent as command bytes too (with the data/~command pin
+//! low), and it writes every data byte into RAM without a separate command.
+
+
+use arrayvec::ArrayVec;
+
+use crate::command_queue::CommandQueue;
+use crate::controller::DisplayController;
+use crate::display::DisplayInterface;
+use crate::layout::{DisplayLayout, Rect};
+use crate::ssd1351::{AddressIncrement, DisplayCommand};
+
+
+/// The number of columns in the RAM of the SSD1331.
+pub const RAM_COLUMNS: usize = 96;
+
+/// The number of rows in the RAM of the SSD1331.
+pub const RAM_ROWS: usize = 64;
+
+/// The largest number of bytes in an encoded command, including the command code.
+pub const MAX_COMMAND_BYTES: usize = 3;
+
+/// The bytes of an encoded command.
+pub type CommandBytes = ArrayVec<u8, MAX_COMMAND_BYTES>;
+
+
+/// A command that can be sent to the SSD1331.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Ssd1331Command {
+    SetColumnAddress { start: u8, end: u8 },
+    SetRowAddress { start: u8, end: u8 },
+    SetContrastA { contrast: u8 },
+    SetContrastB { contrast: u8 },
+    SetContrastC { contrast: u8 },
+    SetMasterCurrent { current: u8 },
+    SetSecondPreChargeSpeedA { speed: u8 },
+    SetSecondPreChargeSpeedB { speed: u8 },
+    SetSecondPreChargeSpeedC { speed: u8 },
+    SetMapping {
+        address_increment: AddressIncrement,
+        reverse_columns: bool,
+        swap_color_sequence: bool,
+        backward_scan: bool,
+        com_split_odd_even: bool,
+    },
+    SetDisplayStartLine { line: u8 },
+    SetDisplayOffset { offset: u8 },
+    DisplayModeRegular,
+    DisplayModeAllOn,
+    DisplayModeAllOff,
+    DisplayModeInverted,
+    SetMuxRatio { ratio: u8 },
+    // SetMasterConfiguration: there is only one valid value (external VCC); it is always sent
+    SetMasterConfiguration,
+    DisplayOff,
+    DisplayOn,
+    SetPowerSaveMode { enabled: bool },
+    SetPhasePeriods {
+        phase1: u8,
+        phase2: u8,
+    },
+    SetClock {
+        divider: u8,
+        oscillator_frequency: u8,
+    },
+    SetPreChargeLevel { level: u8 },
+    SetVcomhLevel { level: u8 },
+}
+impl Ssd1331Command {
+    /// The code representing this command.
+    #[inline]
+    pub const fn as_command_code(&self) -> u8 {
+        match self {
+            Self::SetColumnAddress { .. } => 0x15,
+            Self::SetRowAddress { .. } => 0x75,
+            Self::SetContrastA { .. } => 0x81,
+            Self::SetContrastB { .. } => 0x82,
+            Self::SetContrastC { .. } => 0x83,
+            Self::SetMasterCurrent { .. } => 0x87,
+            Self::SetSecondPreChargeSpeedA { .. } => 0x8A,
+            Self::SetSecondPreChargeSpeedB { .. } => 0x8B,
+            Self::SetSecondPreChargeSpeedC { .. } => 0x8C,
+            Self::SetMapping { .. } => 0xA0,
+            Self::SetDisplayStartLine { .. } => 0xA1,
+            Self::SetDisplayOffset { .. } => 0xA2,
+            Self::DisplayModeRegular => 0xA4,
+            Self::DisplayModeAllOn => 0xA5,
+            Self::DisplayModeAllOff => 0xA6,
+            Self::DisplayModeInverted => 0xA7,
+            Self::SetMuxRatio { .. } => 0xA8,
+            Self::SetMasterConfiguration => 0xAD,
+            Self::DisplayOff => 0xAE,
+            Self::DisplayOn => 0xAF,
+            Self::SetPowerSaveMode { .. } => 0xB0,
+            Self::SetPhasePeriods { .. } => 0xB1,
+            Self::SetClock { .. } => 0xB3,
+            Self::SetPreChargeLevel { .. } => 0xBB,
+            Self::SetVcomhLevel { .. } => 0xBE,
+        }
+    }
+
+    /// Whether the values of the parameters are within the ranges accepted by the controller.
+    pub const fn is_valid(&self) -> bool {
+        match self {
+            Self::SetColumnAddress { start, end } => {
+                *start <= *end && (*end as usize) < RAM_COLUMNS
+            },
+            Self::SetRowAddress { start, end } => {
+                *start <= *end && (*end as usize) < RAM_ROWS
+            },
+            Self::SetMasterCurrent { current } => *current <= 0x0F,
+            Self::SetDisplayStartLine { line } => (*line as usize) < RAM_ROWS,
+            Self::SetDisplayOffset { offset } => (*offset as usize) < RAM_ROWS,
+            Self::SetMuxRatio { ratio } => *ratio >= 15 && (*ratio as usize) < RAM_ROWS,
+            Self::SetPhasePeriods { phase1, phase2 } => {
+                *phase1 >= 1 && *phase1 <= 0x0F && *phase2 >= 1 && *phase2 <= 0x0F
+            },
+            Self::SetClock { divider, oscillator_frequency } => {
+                *divider <= 0x0F && *oscillator_frequency <= 0x0F
+            },
+            Self::SetPreChargeLevel { level } => *level <= 0x1F,
+            Self::SetVcomhLevel { level } => *level <= 0x1F,
+            _ => true,
+        }
+    }
+
+    /// Encodes this command into its command code followed by its parameters. All of these bytes
+    /// are sent as command bytes.
+    ///
+    /// The command should be valid; invalid parameter values may be garbled.
+    pub fn encode(&self) -> CommandBytes {
+        let mut bytes = CommandBytes::new();
+        bytes.push(self.as_command_code());
+        match self {
+            Self::SetColumnAddress { start, end } => {
+                bytes.extend([*start, *end]);
+            },
+            Self::SetRowAddress { start, end } => {
+                bytes.extend([*start, *end]);
+            },
+            Self::SetContrastA { contrast }
+                    | Self::SetContrastB { contrast }
+                    | Self::SetContrastC { contrast } => {
+                bytes.push(*contrast);
+            },
+            Self::SetMasterCurrent { current } => {
+                bytes.push(*current);
+            },
+            Self::SetSecondPreChargeSpeedA { speed }
+                    | Self::SetSecondPreChargeSpeedB { speed }
+                    | Self::SetSecondPreChargeSpeedC { speed } => {
+                bytes.push(*speed);
+            },
+            Self::SetMapping { address_increment, reverse_columns, swap_color_sequence, backward_scan, com_split_odd_even } => {
+                let data_byte =
+                    (0b01 << 6) // 65k colors
+                    | if *com_split_odd_even { 1 << 5 } else { 0 }
+                    | if *backward_scan { 1 << 4 } else { 0 }
+                    // 3 swaps left and right of the commons; not needed
+                    | if *swap_color_sequence { 1 << 2 } else { 0 }
+                    | if *reverse_columns { 1 << 1 } else { 0 }
+                    | address_increment.as_bits()
+                ;
+                bytes.push(data_byte);
+            },
+            Self::SetDisplayStartLine { line } => {
+                bytes.push(*line);
+            },
+            Self::SetDisplayOffset { offset } => {
+                bytes.push(*offset);
+            },
+            Self::SetMuxRatio { ratio } => {
+                bytes.push(*ratio);
+            },
+            Self::SetMasterConfiguration => {
+                bytes.push(0x8E);
+            },
+            Self::SetPowerSaveMode { enabled } => {
+                bytes.push(if *enabled { 0x1A } else { 0x0B });
+            },
+            Self::SetPhasePeriods { phase1, phase2 } => {
+                bytes.push((*phase2 << 4) | *phase1);
+            },
+            Self::SetClock { divider, oscillator_frequency } => {
+                bytes.push((*oscillator_frequency << 4) | *divider);
+            },
+            Self::SetPreChargeLevel { level } => {
+                bytes.push(*level << 1);
+            },
+            Self::SetVcomhLevel { level } => {
+                bytes.push(*level << 1);
+            },
+            Self::DisplayModeRegular
+                | Self::DisplayModeAllOn
+                | Self::DisplayModeAllOff
+                | Self::DisplayModeInverted
+                | Self::DisplayOff
+                | Self::DisplayOn
+                => {},
+        }
+        bytes
+    }
+
+    /// The equivalent of an SSD1351 command, if there is one.
+    ///
+    /// Display offsets and start lines are taken modulo the number of rows, which keeps moving the
+    /// image by the same amount since the SSD1351 has twice as many rows.
+    pub const fn from_ssd1351(command: &DisplayCommand<'_>) -> Option<Self> {
+        match command {
+            DisplayCommand::DisplayOff => Some(Self::DisplayOff),
+            DisplayCommand::DisplayOn => Some(Self::DisplayOn),
+            DisplayCommand::SetMasterContrast { contrast } => {
+                Some(Self::SetMasterCurrent { current: *contrast })
+            },
+            DisplayCommand::SetDisplayOffset { offset } => {
+                Some(Self::SetDisplayOffset { offset: (*offset as usize % RAM_ROWS) as u8 })
+            },
+            DisplayCommand::SetDisplayStartLine { line } => {
+                Some(Self::SetDisplayStartLine { line: (*line as usize % RAM_ROWS) as u8 })
+            },
+            DisplayCommand::DisplayModeRegular => Some(Self::DisplayModeRegular),
+            DisplayCommand::DisplayModeInverted => Some(Self::DisplayModeInverted),
+            DisplayCommand::DisplayModeAllOn => Some(Self::DisplayModeAllOn),
+            DisplayCommand::DisplayModeAllOff => Some(Self::DisplayModeAllOff),
+            _ => None,
+        }
+    }
+}
+
+
+/// Sends the commands to the display, in as few transactions as the queue allows.
+pub fn transmit_all<DI: DisplayInterface>(display_interface: &mut DI, commands: &[Ssd1331Command]) {
+    // every byte is a command byte without data
+    let mut queue = CommandQueue::<0>::new();
+    for command in commands {
+        debug_assert!(command.is_valid());
+        for &byte in &command.encode() {
+            if queue.push_raw(byte, &[]).is_err() {
+                queue.flush(display_interface);
+                queue.push_raw(byte, &[])
+                    .expect("empty queue is full");
+            }
+        }
+    }
+    queue.flush(display_interface);
+}
+
+
+/// The SSD1331 with the settings of the panel connected to it.
+///
+/// The values are the raw parameters of the respective commands.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct Ssd1331Controller {
+    /// The contrast of color A, B and C.
+    pub color_contrast: [u8; 3],
+
+    /// The master current, scaling all colors from 1/16 to 16/16.
+    pub master_current: u8,
+
+    /// The speed of the second pre-charge of color A, B and C.
+    pub second_pre_charge_speed: [u8; 3],
+
+    /// The lengths of the first and second phase, in display clocks.
+    pub phase_periods: (u8, u8),
+
+    /// The divider of the display clock, minus 1, and the frequency of the internal oscillator.
+    pub clock: (u8, u8),
+
+    /// The pre-charge voltage, in steps from 0.1 to 0.5 times VCC.
+    pub pre_charge_level: u8,
+
+    /// The COMH voltage, in steps from 0.44 to 0.83 times VCC.
+    pub vcomh_level: u8,
+}
+impl Ssd1331Controller {
+    /// Settings for a typical 96x64 panel.
+    pub const PANEL_96X64: Self = Self {
+        color_contrast: [0x91, 0x50, 0x7D],
+        master_current: 0x0F,
+        second_pre_charge_speed: [0x64, 0x78, 0x64],
+        phase_periods: (1, 3),
+        clock: (0, 0x0F),
+        pre_charge_level: 0x1D,
+        vcomh_level: 0x1F,
+    };
+
+    pub const fn with_master_current(self, master_current: u8) -> Self {
+        Self { master_current, ..self }
+    }
+
+    /// The commands that apply the panel settings, without turning the display on.
+    pub const fn config_commands(&self) -> [Ssd1331Command; 15] {
+        [
+            Ssd1331Command::DisplayOff,
+            Ssd1331Command::SetDisplayStartLine { line: 0 },
+            Ssd1331Command::SetDisplayOffset { offset: 0 },
+            Ssd1331Command::DisplayModeRegular,
+            Ssd1331Command::SetMuxRatio { ratio: (RAM_ROWS - 1) as u8 },
+            Ssd1331Command::SetMasterConfiguration,
+            Ssd1331Command::SetPowerSaveMode { enabled: false },
+            Ssd1331Command::SetPhasePeriods {
+                phase1: self.phase_periods.0,
+                phase2: self.phase_periods.1,
+            },
+            Ssd1331Command::SetClock {
+                divider: self.clock.0,
+                oscillator_frequency: self.clock.1,
+            },
+            Ssd1331Command::SetSecondPreChargeSpeedA { speed: self.second_pre_charge_speed[0] },
+            Ssd1331Command::SetSecondPreChargeSpeedB { speed: self.second_pre_charge_speed[1] },
+            Ssd1331Command::SetSecondPreChargeSpeedC { speed: self.second_pre_charge_speed[2] },
+            Ssd1331Command::SetPreChargeLevel { level: self.pre_charge_level },
+            Ssd1331Command::SetVcomhLevel { level: self.vcomh_level },
+            Ssd1331Command::SetMasterCurrent { current: self.master_current },
+        ]
+    }
+
+    const fn contrast_commands(&self) -> [Ssd1331Command; 3] {
+        [
+            Ssd1331Command::SetContrastA { contrast: self.color_contrast[0] },
+            Ssd1331Command::SetContrastB { contrast: self.color_contrast[1] },
+            Ssd1331Command::SetContrastC { contrast: self.color_contrast[2] },
+        ]
+    }
+}
+impl Default for Ssd1331Controller {
+    fn default() -> Self { Self::PANEL_96X64 }
+}
+impl DisplayController for Ssd1331Controller {
+    /// Expects the layout to be based on [`DisplayLayout::SSD1331_96X64`].
+    fn initialize<DI: DisplayInterface>(&self, display_interface: &mut DI, layout: &DisplayLayout) {
+        debug_assert_eq!((layout.ram_columns, layout.ram_rows), (RAM_COLUMNS, RAM_ROWS));
+
+        // 0. apply the panel settings (this turns the display off)
+        transmit_all(display_interface, &self.config_commands());
+        transmit_all(display_interface, &self.contrast_commands());
+
+        // 1. scan the RAM according to the requested orientation
+        let mapping = layout.orientation.scan_mapping();
+        transmit_all(display_interface, &[Ssd1331Command::SetMapping {
+            address_increment: if mapping.vertical_increment {
+                AddressIncrement::Vertical
+            } else {
+                AddressIncrement::Horizontal
+            },
+            reverse_columns: mapping.reverse_columns,
+            swap_color_sequence: false,
+            backward_scan: mapping.backward_scan,
+            com_split_odd_even: true, // power-on default
+        }]);
+
+        // 2. clear out display RAM
+        transmit_all(display_interface, &[
+            Ssd1331Command::SetColumnAddress { start: 0, end: (RAM_COLUMNS - 1) as u8 },
+            Ssd1331Command::SetRowAddress { start: 0, end: (RAM_ROWS - 1) as u8 },
+        ]);
+        for _ in 0..(RAM_COLUMNS*RAM_ROWS)/32 {
+            let chunk = [0u8; 32*2];
+            display_interface.send(None, &chunk);
+        }
+
+        // 3. start at row and column as actually connected to the display, and stop sleeping
+        self.start_writing_area(display_interface, layout, layout.display_area());
+        transmit_all(display_interface, &[Ssd1331Command::DisplayOn]);
+    }
+
+    fn start_writing_area<DI: DisplayInterface>(
+        &self,
+        display_interface: &mut DI,
+        layout: &DisplayLayout,
+        area: Rect,
+    ) {
+        let window = layout.ram_rect(area);
+        transmit_all(display_interface, &[
+            Ssd1331Command::SetColumnAddress {
+                start: window.left as u8,
+                end: (window.right() - 1) as u8,
+            },
+            Ssd1331Command::SetRowAddress {
+                start: window.top as u8,
+                end: (window.bottom() - 1) as u8,
+            },
+        ]);
+    }
+
+    fn transmit<DI: DisplayInterface>(
+        &self,
+        display_interface: &mut DI,
+        command: &DisplayCommand<'_>,
+    ) -> bool {
+        match Ssd1331Command::from_ssd1351(command) {
+            Some(equivalent) => {
+                transmit_all(display_interface, &[equivalent]);
+                true
+            },
+            None => false,
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::vec;
+    use std::vec::Vec;
+
+    use crate::brightness::Brightness;
+    use crate::hal::mock::{Event, MockDisplayInterface, display_events};
+    use crate::orientation::{Orientation, Rotation};
+
+    use super::*;
+
+    /// The bytes sent while the display was selected, each with whether it was a command byte.
+    #[derive(Debug, Default, Eq, PartialEq)]
+    struct Transcript {
+        commands: Vec<u8>,
+        data: Vec<u8>,
+        transactions: usize,
+    }
+
+    /// Runs the closure on a display connected via SPI and summarizes what was sent.
+    fn transcript<F: FnOnce(&mut MockDisplayInterface)>(f: F) -> Transcript {
+        let mut transcript = Transcript::default();
+        let mut command_mode = false;
+        for event in display_events(f) {
+            match event {
+                Event::PinLow("cs") => transcript.transactions += 1,
+                Event::PinLow("dc") => command_mode = true,
+                Event::PinHigh("dc") => command_mode = false,
+                Event::SpiWrite(bytes) if command_mode => transcript.commands.extend(bytes),
+                Event::SpiWrite(bytes) => transcript.data.extend(bytes),
+                _ => {},
+            }
+        }
+        transcript
+    }
+
+    #[test]
+    fn test_encoding() {
+        assert_eq!(
+            Ssd1331Command::SetColumnAddress { start: 0, end: 95 }.encode().as_slice(),
+            &[0x15, 0, 95],
+        );
+        assert_eq!(Ssd1331Command::SetMasterCurrent { current: 6 }.encode().as_slice(), &[0x87, 6]);
+        assert_eq!(Ssd1331Command::DisplayOn.encode().as_slice(), &[0xAF]);
+        assert_eq!(
+            Ssd1331Command::SetPhasePeriods { phase1: 1, phase2: 3 }.encode().as_slice(),
+            &[0xB1, 0x31],
+        );
+        assert_eq!(
+            Ssd1331Command::SetClock { divider: 0, oscillator_frequency: 0x0F }.encode().as_slice(),
+            &[0xB3, 0xF0],
+        );
+        assert_eq!(
+            Ssd1331Command::SetPreChargeLevel { level: 0x1D }.encode().as_slice(),
+            &[0xBB, 0x3A],
+        );
+        assert_eq!(
+            Ssd1331Command::SetVcomhLevel { level: 0x1F }.encode().as_slice(),
+            &[0xBE, 0x3E],
+        );
+        assert_eq!(Ssd1331Command::SetMasterConfiguration.encode().as_slice(), &[0xAD, 0x8E]);
+        assert_eq!(
+            Ssd1331Command::SetMapping {
+                address_increment: AddressIncrement::Horizontal,
+                reverse_columns: true,
+                swap_color_sequence: false,
+                backward_scan: true,
+                com_split_odd_even: true,
+            }.encode().as_slice(),
+            &[0xA0, 0x72],
+        );
+    }
+
+    #[test]
+    fn test_validity() {
+        assert!(Ssd1331Command::SetColumnAddress { start: 0, end: 95 }.is_valid());
+        assert!(!Ssd1331Command::SetColumnAddress { start: 0, end: 96 }.is_valid());
+        assert!(!Ssd1331Command::SetRowAddress { start: 10, end: 9 }.is_valid());
+        assert!(!Ssd1331Command::SetMasterCurrent { current: 16 }.is_valid());
+        assert!(!Ssd1331Command::SetMuxRatio { ratio: 64 }.is_valid());
+        assert!(!Ssd1331Command::SetPhasePeriods { phase1: 0, phase2: 3 }.is_valid());
+        for command in Ssd1331Controller::PANEL_96X64.config_commands() {
+            assert!(command.is_valid(), "{:?}", command);
+        }
+    }
+
+    #[test]
+    fn test_from_ssd1351() {
+        assert_eq!(
+            Ssd1331Command::from_ssd1351(&DisplayCommand::SetDisplayOffset { offset: 127 }),
+            Some(Ssd1331Command::SetDisplayOffset { offset: 63 }),
+        );
+        assert_eq!(
+            Ssd1331Command::from_ssd1351(&DisplayCommand::DisplayModeAllOff),
+            Some(Ssd1331Command::DisplayModeAllOff),
+        );
+        assert_eq!(Ssd1331Command::from_ssd1351(&DisplayCommand::StartMoving), None);
+        assert_eq!(Ssd1331Command::from_ssd1351(&DisplayCommand::WriteRam), None);
+    }
+
+    #[test]
+    fn test_initialize() {
+        let controller = Ssd1331Controller::PANEL_96X64;
+        let layout = DisplayLayout::SSD1331_96X64
+            .with_orientation(Orientation::new(Rotation::Deg180, false));
+        let transcript = transcript(|display| controller.initialize(display, &layout));
+
+        assert_eq!(
+            transcript.commands,
+            vec![
+                0xAE, 0xA1, 0x00, 0xA2, 0x00, 0xA4, 0xA8, 0x3F, 0xAD, 0x8E, 0xB0, 0x0B,
+                0xB1, 0x31, 0xB3, 0xF0, 0x8A, 0x64, 0x8B, 0x78, 0x8C, 0x64, 0xBB, 0x3A,
+                0xBE, 0x3E, 0x87, 0x0F,
+                0x81, 0x91, 0x82, 0x50, 0x83, 0x7D,
+                0xA0, 0x72,
+                0x15, 0, 95, 0x75, 0, 63,
+                0x15, 0, 95, 0x75, 0, 63,
+                0xAF,
+            ],
+        );
+        assert_eq!(transcript.data.len(), 96 * 64 * 2);
+        assert!(transcript.data.iter().all(|&byte| byte == 0));
+    }
+
+    #[test]
+    fn test_window() {
+        let controller = Ssd1331Controller::PANEL_96X64;
+        let layout = DisplayLayout::SSD1331_96X64;
+        let area = Rect::new(4, 10, 2, 3);
+        assert_eq!(
+            transcript(|display| controller.start_writing_area(display, &layout, area)),
+            Transcript {
+                commands: vec![0x15, 4, 5, 0x75, 10, 12],
+                data: vec![],
+                transactions: 1,
+            },
+        );
+    }
+
+    #[test]
+    fn test_sleep_and_brightness() {
+        let controller = Ssd1331Controller::PANEL_96X64;
+        assert_eq!(
+            transcript(|display| controller.set_sleeping(display, true)).commands,
+            vec![0xAE],
+        );
+        assert_eq!(
+            transcript(|display| controller.set_brightness(display, Brightness::MIN)).commands,
+            vec![0x87, 0x01],
+        );
+        assert_eq!(
+            transcript(|display| {
+                assert!(!controller.transmit(display, &DisplayCommand::StopMoving));
+            }),
+            Transcript::default(),
+        );
+    }
+}
@@ -0,0 +1,777 @@
+//! Commands understood by the SSD1351 OLED display controller and their byte encoding.
+
+
+use core::fmt;
+
+use arrayvec::ArrayVec;
+
+
+/// The largest number of parameter bytes that any command takes (the grayscale lookup table).
+pub const MAX_PARAMETER_COUNT: usize = 63;
+
+/// The parameter bytes of an encoded command.
+pub type Parameters = ArrayVec<u8, MAX_PARAMETER_COUNT>;
+
+/// The longest pulse width, in display clocks, that an entry of the grayscale lookup table may
+/// specify.
+pub const MAX_GRAYSCALE_PULSE_WIDTH: u8 = 180;
+
+
+/// Whether the given bytes are a valid grayscale lookup table: one pulse width for each of the
+/// levels 1 to 63, never decreasing and at most [`MAX_GRAYSCALE_PULSE_WIDTH`].
+pub const fn is_valid_grayscale_table(table: &[u8]) -> bool {
+    if table.len() != MAX_PARAMETER_COUNT {
+        return false;
+    }
+    let mut previous = 0;
+    let mut i = 0;
+    while i < table.len() {
+        if table[i] < previous || table[i] > MAX_GRAYSCALE_PULSE_WIDTH {
+            return false;
+        }
+        previous = table[i];
+        i += 1;
+    }
+    true
+}
+
+
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum AddressIncrement {
+    Horizontal,
+    Vertical,
+}
+impl AddressIncrement {
+    pub const fn as_bits(&self) -> u8 {
+        match self {
+            Self::Horizontal => 0b0,
+            Self::Vertical => 0b1,
+        }
+    }
+
+    pub const fn from_bits(bits: u8) -> Option<Self> {
+        match bits {
+            0b0 => Some(Self::Horizontal),
+            0b1 => Some(Self::Vertical),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum ColorDepth {
+    Colors65k,
+    Colors262k,
+    Colors262k16BitFormat2,
+}
+impl ColorDepth {
+    pub const fn as_bits(&self) -> u8 {
+        match self {
+            Self::Colors65k => 0b00,
+            Self::Colors262k => 0b10,
+            Self::Colors262k16BitFormat2 => 0b11,
+        }
+    }
+
+    pub const fn from_bits(bits: u8) -> Option<Self> {
+        match bits {
+            // 0b01 (the power-on value) also selects 65k colors
+            0b00 | 0b01 => Some(Self::Colors65k),
+            0b10 => Some(Self::Colors262k),
+            0b11 => Some(Self::Colors262k16BitFormat2),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum ParallelInterface {
+    EightBit,
+    SixteenBit,
+    EighteenBit,
+}
+impl ParallelInterface {
+    pub const fn as_bits(&self) -> u8 {
+        match self {
+            Self::EightBit => 0b00,
+            Self::SixteenBit => 0b01,
+            Self::EighteenBit => 0b11,
+        }
+    }
+
+    pub const fn from_bits(bits: u8) -> Option<Self> {
+        match bits {
+            0b00 => Some(Self::EightBit),
+            0b01 => Some(Self::SixteenBit),
+            0b11 => Some(Self::EighteenBit),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum GpioState {
+    HiZInputDisabled,
+    HiZInputEnabled,
+    OutputLow,
+    OutputHigh,
+}
+impl GpioState {
+    pub const fn as_bits(&self) -> u8 {
+        match self {
+            Self::HiZInputDisabled => 0b00,
+            Self::HiZInputEnabled => 0b01,
+            Self::OutputLow => 0b10,
+            Self::OutputHigh => 0b11,
+        }
+    }
+
+    pub const fn from_bits(bits: u8) -> Option<Self> {
+        match bits {
+            0b00 => Some(Self::HiZInputDisabled),
+            0b01 => Some(Self::HiZInputEnabled),
+            0b10 => Some(Self::OutputLow),
+            0b11 => Some(Self::OutputHigh),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum LockState {
+    CommandsUnlocked,
+    CommandsLocked,
+    AdvancedCommandsLocked,
+    AdvancedCommandsUnlocked,
+}
+impl LockState {
+    pub const fn as_bits(&self) -> u8 {
+        match self {
+            Self::CommandsUnlocked => 0x12,
+            Self::CommandsLocked => 0x16,
+            Self::AdvancedCommandsLocked => 0xB0,
+            Self::AdvancedCommandsUnlocked => 0xB1,
+        }
+    }
+
+    pub const fn from_bits(bits: u8) -> Option<Self> {
+        match bits {
+            0x12 => Some(Self::CommandsUnlocked),
+            0x16 => Some(Self::CommandsLocked),
+            0xB0 => Some(Self::AdvancedCommandsLocked),
+            0xB1 => Some(Self::AdvancedCommandsUnlocked),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum ScrollSpeed {
+    TestMode,
+    Normal,
+    Slow,
+    Slowest,
+}
+impl ScrollSpeed {
+    pub const fn as_bits(&self) -> u8 {
+        match self {
+            Self::TestMode => 0b00,
+            Self::Normal => 0b01,
+            Self::Slow => 0b10,
+            Self::Slowest => 0b11,
+        }
+    }
+
+    pub const fn from_bits(bits: u8) -> Option<Self> {
+        match bits {
+            0b00 => Some(Self::TestMode),
+            0b01 => Some(Self::Normal),
+            0b10 => Some(Self::Slow),
+            0b11 => Some(Self::Slowest),
+            _ => None,
+        }
+    }
+}
+
+
+/// The reason why bytes could not be decoded into a command.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum DecodeCommandError {
+    /// The command code is not known.
+    UnknownCommand(u8),
+
+    /// The command was followed by the wrong number of parameter bytes.
+    WrongParameterCount { expected: usize, actual: usize },
+
+    /// A parameter byte has a value that does not correspond to any setting.
+    InvalidParameter,
+}
+impl fmt::Display for DecodeCommandError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownCommand(code) => write!(f, "unknown command code 0x{:02X}", code),
+            Self::WrongParameterCount { expected, actual }
+                => write!(f, "expected {} parameter bytes, got {}", expected, actual),
+            Self::InvalidParameter => write!(f, "invalid parameter value"),
+        }
+    }
+}
+
+
+/// A command that can be sent to the display.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum DisplayCommand<'a> {
+    SetColumnAddress { start: u8, end: u8 },
+    SetRowAddress { start: u8, end: u8 },
+    WriteRam,
+    ReadRam,
+    SetMapping {
+        address_increment: AddressIncrement,
+        reverse_columns: bool,
+        swap_color_sequence: bool,
+        backward_scan: bool,
+        com_split_odd_even: bool,
+        color_depth: ColorDepth,
+    },
+    SetDisplayStartLine { line: u8 },
+    SetDisplayOffset { offset: u8 },
+    DisplayModeAllOff,
+    DisplayModeAllOn,
+    DisplayModeRegular,
+    DisplayModeInverted,
+    FunctionSelection {
+        internal_vdd_regulator: bool,
+        interface: ParallelInterface,
+    },
+    NopAD,
+    DisplayOff,
+    DisplayOn,
+    NopB0,
+    SetPhasePeriods {
+        phase1: u8,
+        phase2: u8,
+    },
+    DisplayEnhancement { enhance: bool },
+    SetFrequency {
+        front_clock_divider_2pow: u8,
+        oscillator_frequency: u8,
+    },
+    // SetSegmentLowVoltage: there is only one valid value; don't bother
+    SetGpio {
+        gpio0: GpioState,
+        gpio1: GpioState,
+    },
+    SetSecondPreChargePeriod { period: u8 },
+    SetGrayscaleLookUpTable { table: &'a [u8] },
+    ResetGrayscaleLookUpTable,
+    SetPreChargeVoltageLevel { voltage_level: u8 },
+    SetComDeselectVoltageLevel { voltage_level: u8 },
+    SetColorContrast {
+        color1_contrast: u8,
+        color2_contrast: u8,
+        color3_contrast: u8,
+    },
+    SetMasterContrast { contrast: u8 },
+    SetMuxRatio { ratio: u8 },
+    NopD1,
+    NopE3,
+    SetCommandLock { state: LockState },
+    HorizontalScroll {
+        scrolling: u8,
+        start_row: u8,
+        scroll_row_count: u8,
+        scroll_speed: ScrollSpeed,
+    },
+    StopMoving,
+    StartMoving,
+}
+impl<'a> DisplayCommand<'a> {
+    /// The code representing this command.
+    #[inline]
+    pub const fn as_command_code(&self) -> u8 {
+        match self {
+            Self::SetColumnAddress { .. } => 0x15,
+            Self::SetRowAddress { .. } => 0x75,
+            Self::WriteRam => 0x5C,
+            Self::ReadRam => 0x5D,
+            Self::SetMapping { .. } => 0xA0,
+            Self::SetDisplayStartLine { .. } => 0xA1,
+            Self::SetDisplayOffset { .. } => 0xA2,
+            Self::DisplayModeAllOff => 0xA4,
+            Self::DisplayModeAllOn => 0xA5,
+            Self::DisplayModeRegular => 0xA6,
+            Self::DisplayModeInverted => 0xA7,
+            Self::FunctionSelection { .. } => 0xAB,
+            Self::NopAD => 0xAD,
+            Self::DisplayOff => 0xAE,
+            Self::DisplayOn => 0xAF,
+            Self::NopB0 => 0xB0,
+            Self::SetPhasePeriods { .. } => 0xB1,
+            Self::DisplayEnhancement { .. } => 0xB2,
+            Self::SetFrequency { .. } => 0xB3,
+            Self::SetGpio { .. } => 0xB5,
+            Self::SetSecondPreChargePeriod { .. } => 0xB6,
+            Self::SetGrayscaleLookUpTable { .. } => 0xB8,
+            Self::ResetGrayscaleLookUpTable => 0xB9,
+            Self::SetPreChargeVoltageLevel { .. } => 0xBB,
+            Self::SetComDeselectVoltageLevel { .. } => 0xBE,
+            Self::SetColorContrast { .. } => 0xC1,
+            Self::SetMasterContrast { .. } => 0xC7,
+            Self::SetMuxRatio { .. } => 0xCA,
+            Self::NopD1 => 0xD1,
+            Self::NopE3 => 0xE3,
+            Self::SetCommandLock { .. } => 0xFD,
+            Self::HorizontalScroll { .. } => 0x96,
+            Self::StopMoving => 0x9E,
+            Self::StartMoving => 0x9F,
+        }
+    }
+
+    /// The number of parameter bytes that follow the command with the given code, or `None` if the
+    /// code does not represent a known command.
+    pub const fn parameter_count(command_code: u8) -> Option<usize> {
+        match command_code {
+            0x15 | 0x75 => Some(2),
+            0x5C | 0x5D => Some(0),
+            0xA0..=0xA2 => Some(1),
+            0xA4..=0xA7 => Some(0),
+            0xAB => Some(1),
+            0xAD..=0xB0 => Some(0),
+            0xB1 => Some(1),
+            0xB2 => Some(3),
+            0xB3 | 0xB5 | 0xB6 => Some(1),
+            0xB8 => Some(MAX_PARAMETER_COUNT),
+            0xB9 => Some(0),
+            0xBB | 0xBE => Some(1),
+            0xC1 => Some(3),
+            0xC7 | 0xCA => Some(1),
+            0xD1 | 0xE3 => Some(0),
+            0xFD => Some(1),
+            0x96 => Some(5),
+            0x9E | 0x9F => Some(0),
+            _ => None,
+        }
+    }
+
+    pub const fn is_valid(&self) -> bool {
+        match self {
+            Self::SetColumnAddress { start, end }
+                => *start <= 127 && *end <= 127 && *start <= *end,
+            Self::SetRowAddress { start, end }
+            => *start <= 127 && *end <= 127 && *start <= *end,
+            Self::WriteRam => true,
+            Self::ReadRam => true,
+            Self::SetMapping { .. } => true,
+            Self::SetDisplayStartLine { line } => *line <= 127,
+            Self::SetDisplayOffset { offset } => *offset <= 127,
+            Self::DisplayModeAllOff => true,
+            Self::DisplayModeAllOn => true,
+            Self::DisplayModeRegular => true,
+            Self::DisplayModeInverted => true,
+            Self::FunctionSelection { .. } => true,
+            Self::NopAD => true,
+            Self::DisplayOff => true,
+            Self::DisplayOn => true,
+            Self::NopB0 => true,
+            Self::SetPhasePeriods { phase1, phase2 }
+                => *phase1 >= 2 && *phase1 <= 15 && *phase2 >= 3 && *phase2 <= 15,
+            Self::DisplayEnhancement { .. } => true,
+            Self::SetFrequency { front_clock_divider_2pow, oscillator_frequency }
+                => *front_clock_divider_2pow <= 0b1010 && *oscillator_frequency <= 0b1111,
+            Self::SetGpio { .. } => true,
+            Self::SetSecondPreChargePeriod { period }
+                => *period >= 0b0001 && *period <= 0b1111,
+            Self::SetGrayscaleLookUpTable { table }
+                => is_valid_grayscale_table(table),
+            Self::ResetGrayscaleLookUpTable => true,
+            Self::SetPreChargeVoltageLevel { voltage_level }
+                => *voltage_level <= 0b1_1111,
+            Self::SetComDeselectVoltageLevel { voltage_level }
+                => *voltage_level <= 0b111,
+            Self::SetColorContrast { .. } => true,
+            Self::SetMasterContrast { contrast }
+                => *contrast <= 0b1111,
+            Self::SetMuxRatio { ratio }
+                => *ratio >= 15 && *ratio <= 127,
+            Self::NopD1 => true,
+            Self::NopE3 => true,
+            Self::SetCommandLock { .. } => true,
+            Self::HorizontalScroll { start_row, scroll_row_count, .. }
+                => *start_row <= 0b111_1111 && *start_row as u16 + *scroll_row_count as u16 <= 128,
+            Self::StopMoving => true,
+            Self::StartMoving => true,
+        }
+    }
+
+    /// Encodes this command into its command code and the parameter bytes that follow it.
+    ///
+    /// The command should be valid; invalid parameter values may be garbled. Panics if the
+    /// grayscale lookup table is longer than [`MAX_PARAMETER_COUNT`].
+    pub fn encode(&self) -> (u8, Parameters) {
+        let mut parameters = Parameters::new();
+        match self {
+            Self::SetColumnAddress { start, end } => {
+                parameters.extend([*start, *end]);
+            },
+            Self::SetRowAddress { start, end } => {
+                parameters.extend([*start, *end]);
+            },
+            Self::SetMapping { address_increment, reverse_columns, swap_color_sequence, backward_scan, com_split_odd_even, color_depth } => {
+                let data_byte =
+                    (color_depth.as_bits() << 6)
+                    | if *com_split_odd_even { 1 << 5 } else { 0 }
+                    | if *backward_scan { 1 << 4 } else { 0 }
+                    // 3 is reserved
+                    | if *swap_color_sequence { 1 << 2 } else { 0 }
+                    | if *reverse_columns { 1 << 1 } else { 0 }
+                    | address_increment.as_bits()
+                ;
+                parameters.push(data_byte);
+            },
+            Self::SetDisplayStartLine { line } => {
+                parameters.push(*line);
+            },
+            Self::SetDisplayOffset { offset } => {
+                parameters.push(*offset);
+            },
+            Self::FunctionSelection { internal_vdd_regulator, interface } => {
+                let data_byte =
+                    (interface.as_bits() << 6)
+                    | if *internal_vdd_regulator { 1 } else { 0 }
+                ;
+                parameters.push(data_byte);
+            },
+            Self::SetPhasePeriods { phase1, phase2 } => {
+                let data_byte =
+                    *phase2 << 4
+                    | *phase1
+                ;
+                parameters.push(data_byte);
+            },
+            Self::DisplayEnhancement { enhance } => {
+                let first_data_byte = if *enhance { 0xA4 } else { 0x00 };
+                parameters.extend([first_data_byte, 0x00, 0x00]);
+            },
+            Self::SetFrequency { front_clock_divider_2pow, oscillator_frequency } => {
+                let data_byte =
+                    *oscillator_frequency << 4
+                    | *front_clock_divider_2pow
+                ;
+                parameters.push(data_byte);
+            },
+            Self::SetGpio { gpio0, gpio1 } => {
+                let data_byte =
+                    gpio1.as_bits() << 2
+                    | gpio0.as_bits()
+                ;
+                parameters.push(data_byte);
+            },
+            Self::SetSecondPreChargePeriod { period } => {
+                parameters.push(*period);
+            },
+            Self::SetGrayscaleLookUpTable { table } => {
+                parameters.try_extend_from_slice(table)
+                    .expect("grayscale lookup table too long");
+            },
+            Self::SetPreChargeVoltageLevel { voltage_level } => {
+                parameters.push(*voltage_level);
+            },
+            Self::SetComDeselectVoltageLevel { voltage_level } => {
+                parameters.push(*voltage_level);
+            },
+            Self::SetColorContrast { color1_contrast, color2_contrast, color3_contrast } => {
+                parameters.extend([*color1_contrast, *color2_contrast, *color3_contrast]);
+            },
+            Self::SetMasterContrast { contrast } => {
+                parameters.push(*contrast);
+            },
+            Self::SetMuxRatio { ratio } => {
+                parameters.push(*ratio);
+            },
+            Self::SetCommandLock { state } => {
+                parameters.push(state.as_bits());
+            },
+            Self::HorizontalScroll { scrolling, start_row, scroll_row_count, scroll_speed } => {
+                parameters.extend([
+                    *scrolling,
+                    *start_row,
+                    *scroll_row_count,
+                    0x00,
+                    scroll_speed.as_bits(),
+                ]);
+            },
+            _ => {
+                // command with no data
+            },
+        }
+        (self.as_command_code(), parameters)
+    }
+
+    /// Decodes a command from its command code and the parameter bytes that followed it.
+    ///
+    /// Reserved bits are ignored; settings that the controller treats identically are decoded into
+    /// the same command.
+    pub fn decode(command_code: u8, parameters: &'a [u8]) -> Result<Self, DecodeCommandError> {
+        let expected = Self::parameter_count(command_code)
+            .ok_or(DecodeCommandError::UnknownCommand(command_code))?;
+        if parameters.len() != expected {
+            let actual = parameters.len();
+            return Err(DecodeCommandError::WrongParameterCount { expected, actual });
+        }
+        let p = parameters;
+        let flag = |byte: u8, bit: u8| byte & (1 << bit) != 0;
+        let invalid = DecodeCommandError::InvalidParameter;
+
+        let command = match command_code {
+            0x15 => Self::SetColumnAddress { start: p[0], end: p[1] },
+            0x75 => Self::SetRowAddress { start: p[0], end: p[1] },
+            0x5C => Self::WriteRam,
+            0x5D => Self::ReadRam,
+            0xA0 => Self::SetMapping {
+                address_increment: AddressIncrement::from_bits(p[0] & 0b1).ok_or(invalid)?,
+                reverse_columns: flag(p[0], 1),
+                swap_color_sequence: flag(p[0], 2),
+                backward_scan: flag(p[0], 4),
+                com_split_odd_even: flag(p[0], 5),
+                color_depth: ColorDepth::from_bits(p[0] >> 6).ok_or(invalid)?,
+            },
+            0xA1 => Self::SetDisplayStartLine { line: p[0] },
+            0xA2 => Self::SetDisplayOffset { offset: p[0] },
+            0xA4 => Self::DisplayModeAllOff,
+            0xA5 => Self::DisplayModeAllOn,
+            0xA6 => Self::DisplayModeRegular,
+            0xA7 => Self::DisplayModeInverted,
+            0xAB => Self::FunctionSelection {
+                internal_vdd_regulator: flag(p[0], 0),
+                interface: ParallelInterface::from_bits(p[0] >> 6).ok_or(invalid)?,
+            },
+            0xAD => Self::NopAD,
+            0xAE => Self::DisplayOff,
+            0xAF => Self::DisplayOn,
+            0xB0 => Self::NopB0,
+            0xB1 => Self::SetPhasePeriods { phase1: p[0] & 0x0F, phase2: p[0] >> 4 },
+            0xB2 => match p[0] {
+                0xA4 => Self::DisplayEnhancement { enhance: true },
+                0x00 => Self::DisplayEnhancement { enhance: false },
+                _ => return Err(invalid),
+            },
+            0xB3 => Self::SetFrequency {
+                front_clock_divider_2pow: p[0] & 0x0F,
+                oscillator_frequency: p[0] >> 4,
+            },
+            0xB5 => Self::SetGpio {
+                gpio0: GpioState::from_bits(p[0] & 0b11).ok_or(invalid)?,
+                gpio1: GpioState::from_bits((p[0] >> 2) & 0b11).ok_or(invalid)?,
+            },
+            0xB6 => Self::SetSecondPreChargePeriod { period: p[0] },
+            0xB8 => Self::SetGrayscaleLookUpTable { table: p },
+            0xB9 => Self::ResetGrayscaleLookUpTable,
+            0xBB => Self::SetPreChargeVoltageLevel { voltage_level: p[0] },
+            0xBE => Self::SetComDeselectVoltageLevel { voltage_level: p[0] },
+            0xC1 => Self::SetColorContrast {
+                color1_contrast: p[0],
+                color2_contrast: p[1],
+                color3_contrast: p[2],
+            },
+            0xC7 => Self::SetMasterContrast { contrast: p[0] },
+            0xCA => Self::SetMuxRatio { ratio: p[0] },
+            0xD1 => Self::NopD1,
+            0xE3 => Self::NopE3,
+            0xFD => Self::SetCommandLock { state: LockState::from_bits(p[0]).ok_or(invalid)? },
+            0x96 => Self::HorizontalScroll {
+                scrolling: p[0],
+                start_row: p[1],
+                scroll_row_count: p[2],
+                scroll_speed: ScrollSpeed::from_bits(p[4]).ok_or(invalid)?,
+            },
+            0x9E => Self::StopMoving,
+            0x9F => Self::StartMoving,
+            _ => unreachable!("parameter_count accepted unknown command"),
+        };
+        Ok(command)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TABLE: [u8; MAX_PARAMETER_COUNT] = {
+        let mut table = [0u8; MAX_PARAMETER_COUNT];
+        let mut i = 0;
+        while i < MAX_PARAMETER_COUNT {
+            table[i] = (i * 2 + 1) as u8;
+            i += 1;
+        }
+        table
+    };
+
+    /// Valid examples of every command.
+    fn examples() -> [DisplayCommand<'static>; 39] {
+        [
+            DisplayCommand::SetColumnAddress { start: 16, end: 111 },
+            DisplayCommand::SetRowAddress { start: 0, end: 127 },
+            DisplayCommand::WriteRam,
+            DisplayCommand::ReadRam,
+            DisplayCommand::SetMapping {
+                address_increment: AddressIncrement::Vertical,
+                reverse_columns: true,
+                swap_color_sequence: false,
+                backward_scan: true,
+                com_split_odd_even: true,
+                color_depth: ColorDepth::Colors262k,
+            },
+            DisplayCommand::SetMapping {
+                address_increment: AddressIncrement::Horizontal,
+                reverse_columns: false,
+                swap_color_sequence: true,
+                backward_scan: false,
+                com_split_odd_even: false,
+                color_depth: ColorDepth::Colors262k16BitFormat2,
+            },
+            DisplayCommand::SetDisplayStartLine { line: 5 },
+            DisplayCommand::SetDisplayOffset { offset: 96 },
+            DisplayCommand::DisplayModeAllOff,
+            DisplayCommand::DisplayModeAllOn,
+            DisplayCommand::DisplayModeRegular,
+            DisplayCommand::DisplayModeInverted,
+            DisplayCommand::FunctionSelection {
+                internal_vdd_regulator: true,
+                interface: ParallelInterface::EighteenBit,
+            },
+            DisplayCommand::FunctionSelection {
+                internal_vdd_regulator: false,
+                interface: ParallelInterface::SixteenBit,
+            },
+            DisplayCommand::NopAD,
+            DisplayCommand::DisplayOff,
+            DisplayCommand::DisplayOn,
+            DisplayCommand::NopB0,
+            DisplayCommand::SetPhasePeriods { phase1: 2, phase2: 15 },
+            DisplayCommand::DisplayEnhancement { enhance: true },
+            DisplayCommand::DisplayEnhancement { enhance: false },
+            DisplayCommand::SetFrequency {
+                front_clock_divider_2pow: 0b1010,
+                oscillator_frequency: 0b1111,
+            },
+            DisplayCommand::SetGpio {
+                gpio0: GpioState::OutputHigh,
+                gpio1: GpioState::HiZInputEnabled,
+            },
+            DisplayCommand::SetSecondPreChargePeriod { period: 8 },
+            DisplayCommand::SetGrayscaleLookUpTable { table: &TABLE },
+            DisplayCommand::ResetGrayscaleLookUpTable,
+            DisplayCommand::SetPreChargeVoltageLevel { voltage_level: 0x17 },
+            DisplayCommand::SetComDeselectVoltageLevel { voltage_level: 0x05 },
+            DisplayCommand::SetColorContrast {
+                color1_contrast: 0xC8,
+                color2_contrast: 0x80,
+                color3_contrast: 0xC8,
+            },
+            DisplayCommand::SetMasterContrast { contrast: 0x0F },
+            DisplayCommand::SetMuxRatio { ratio: 95 },
+            DisplayCommand::NopD1,
+            DisplayCommand::NopE3,
+            DisplayCommand::SetCommandLock { state: LockState::CommandsUnlocked },
+            DisplayCommand::SetCommandLock { state: LockState::AdvancedCommandsUnlocked },
+            DisplayCommand::HorizontalScroll {
+                scrolling: 1,
+                start_row: 32,
+                scroll_row_count: 96,
+                scroll_speed: ScrollSpeed::Slowest,
+            },
+            DisplayCommand::StopMoving,
+            DisplayCommand::StartMoving,
+            DisplayCommand::SetCommandLock { state: LockState::CommandsLocked },
+        ]
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for command in examples() {
+            assert!(command.is_valid(), "{:?}", command);
+            let (code, parameters) = command.encode();
+            assert_eq!(code, command.as_command_code());
+            assert_eq!(DisplayCommand::parameter_count(code), Some(parameters.len()));
+            assert_eq!(DisplayCommand::decode(code, &parameters), Ok(command));
+        }
+    }
+
+    #[test]
+    fn test_encoding() {
+        let mapping = DisplayCommand::SetMapping {
+            address_increment: AddressIncrement::Vertical,
+            reverse_columns: true,
+            swap_color_sequence: false,
+            backward_scan: false,
+            com_split_odd_even: true,
+            color_depth: ColorDepth::Colors65k,
+        };
+        let (code, parameters) = mapping.encode();
+        assert_eq!((code, parameters.as_slice()), (0xA0, &[0b0010_0011][..]));
+
+        let (code, parameters) = DisplayCommand::SetPhasePeriods { phase1: 2, phase2: 3 }.encode();
+        assert_eq!((code, parameters.as_slice()), (0xB1, &[0x32][..]));
+
+        let (code, parameters) = DisplayCommand::DisplayOn.encode();
+        assert_eq!((code, parameters.as_slice()), (0xAF, &[][..]));
+    }
+
+    #[test]
+    fn test_decode_equivalent_settings() {
+        // the power-on value of the remap register, with color depth bits 0b01
+        assert_eq!(
+            DisplayCommand::decode(0xA0, &[0x40]),
+            Ok(DisplayCommand::SetMapping {
+                address_increment: AddressIncrement::Horizontal,
+                reverse_columns: false,
+                swap_color_sequence: false,
+                backward_scan: false,
+                com_split_odd_even: false,
+                color_depth: ColorDepth::Colors65k,
+            }),
+        );
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        use DecodeCommandError::*;
+
+        assert_eq!(DisplayCommand::decode(0x00, &[]), Err(UnknownCommand(0x00)));
+        assert_eq!(
+            DisplayCommand::decode(0x15, &[0]),
+            Err(WrongParameterCount { expected: 2, actual: 1 }),
+        );
+        assert_eq!(
+            DisplayCommand::decode(0xAF, &[0]),
+            Err(WrongParameterCount { expected: 0, actual: 1 }),
+        );
+        assert_eq!(DisplayCommand::decode(0xAB, &[0b1000_0001]), Err(InvalidParameter));
+        assert_eq!(DisplayCommand::decode(0xB2, &[0x01, 0x00, 0x00]), Err(InvalidParameter));
+        assert_eq!(DisplayCommand::decode(0xFD, &[0x1B]), Err(InvalidParameter));
+        assert_eq!(
+            DisplayCommand::decode(0x96, &[1, 0, 128, 0, 4]),
+            Err(InvalidParameter),
+        );
+    }
+
+    #[test]
+    fn test_validity() {
+        assert!(!DisplayCommand::SetColumnAddress { start: 10, end: 9 }.is_valid());
+        assert!(!DisplayCommand::SetMuxRatio { ratio: 14 }.is_valid());
+        assert!(!DisplayCommand::SetGrayscaleLookUpTable { table: &TABLE[1..] }.is_valid());
+        let mut decreasing = TABLE;
+        decreasing[10] = 0;
+        assert!(!DisplayCommand::SetGrayscaleLookUpTable { table: &decreasing }.is_valid());
+        let mut too_wide = TABLE;
+        too_wide[62] = MAX_GRAYSCALE_PULSE_WIDTH + 1;
+        assert!(!DisplayCommand::SetGrayscaleLookUpTable { table: &too_wide }.is_valid());
+        assert!(!DisplayCommand::HorizontalScroll {
+            scrolling: 1,
+            start_row: 127,
+            scroll_row_count: 255,
+            scroll_speed: ScrollSpeed::Normal,
+        }.is_valid());
+    }
+}
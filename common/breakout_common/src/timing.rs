@@ -0,0 +1,223 @@
+//! Pacing of the simulation independently of the rendering speed.
+
+
+use core::time::Duration;
+
+
+/// The rate at which the playfield simulation advances, in ticks per second.
+pub const TICKS_PER_SECOND: u32 = 60;
+
+/// The default maximum number of simulation ticks to catch up on before rendering the next frame.
+pub const DEFAULT_MAX_TICKS_PER_FRAME: u32 = 5;
+
+
+/// A fixed-timestep accumulator.
+///
+/// The time that has elapsed between frames is collected and converted into a whole number of
+/// simulation ticks; the remainder is carried over into the next frame. If rendering takes longer
+/// than a tick, multiple ticks are simulated before the next frame is rendered (the frames in
+/// between are skipped). To avoid a death spiral on slow machines, the number of ticks per frame
+/// is limited; any time beyond that is dropped, slowing the game down instead.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct FixedTimestep {
+    tick_duration: Duration,
+    accumulated: Duration,
+    max_ticks_per_frame: u32,
+}
+impl FixedTimestep {
+    pub const fn new(tick_duration: Duration, max_ticks_per_frame: u32) -> Self {
+        assert!(!tick_duration.is_zero());
+        assert!(max_ticks_per_frame > 0);
+        Self {
+            tick_duration,
+            accumulated: Duration::ZERO,
+            max_ticks_per_frame,
+        }
+    }
+
+    /// Creates a timestep running at the given number of ticks per second.
+    pub const fn from_rate(ticks_per_second: u32, max_ticks_per_frame: u32) -> Self {
+        assert!(ticks_per_second > 0);
+        Self::new(
+            Duration::from_nanos(1_000_000_000 / ticks_per_second as u64),
+            max_ticks_per_frame,
+        )
+    }
+
+    #[inline]
+    pub const fn tick_duration(&self) -> Duration { self.tick_duration }
+
+    /// Accounts for the given amount of elapsed time and returns the number of simulation ticks
+    /// that are now due.
+    pub fn advance(&mut self, elapsed: Duration) -> u32 {
+        self.accumulated += elapsed;
+
+        let mut ticks = 0;
+        while self.accumulated >= self.tick_duration && ticks < self.max_ticks_per_frame {
+            self.accumulated -= self.tick_duration;
+            ticks += 1;
+        }
+
+        if self.accumulated >= self.tick_duration {
+            // we are hopelessly behind; drop the excess time
+            // (64-bit nanoseconds suffice for centuries and avoid 128-bit division on the board)
+            let accumulated_nanos = self.accumulated.as_nanos() as u64;
+            let tick_nanos = self.tick_duration.as_nanos() as u64;
+            self.accumulated = Duration::from_nanos(accumulated_nanos % tick_nanos);
+        }
+
+        ticks
+    }
+
+    /// The time remaining until the next simulation tick is due.
+    #[inline]
+    pub fn time_until_next_tick(&self) -> Duration {
+        self.tick_duration.saturating_sub(self.accumulated)
+    }
+}
+
+
+/// Statistics about the time taken by frames over a measurement period.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct FrameStats {
+    frame_count: u32,
+    skipped_count: u32,
+    total_time: Duration,
+    min_frame_time: Duration,
+    max_frame_time: Duration,
+}
+impl FrameStats {
+    pub const fn new() -> Self {
+        Self {
+            frame_count: 0,
+            skipped_count: 0,
+            total_time: Duration::ZERO,
+            min_frame_time: Duration::ZERO,
+            max_frame_time: Duration::ZERO,
+        }
+    }
+
+    /// Records a rendered frame that took the given amount of time.
+    pub fn record_frame(&mut self, frame_time: Duration) {
+        if self.frame_count == 0 || frame_time < self.min_frame_time {
+            self.min_frame_time = frame_time;
+        }
+        if frame_time > self.max_frame_time {
+            self.max_frame_time = frame_time;
+        }
+        self.frame_count += 1;
+        self.total_time += frame_time;
+    }
+
+    /// Records frames that were skipped because rendering fell behind the simulation.
+    pub fn record_skipped(&mut self, skipped: u32) {
+        self.skipped_count += skipped;
+    }
+
+    #[inline]
+    pub const fn frame_count(&self) -> u32 { self.frame_count }
+
+    #[inline]
+    pub const fn skipped_count(&self) -> u32 { self.skipped_count }
+
+    #[inline]
+    pub const fn total_time(&self) -> Duration { self.total_time }
+
+    #[inline]
+    pub const fn min_frame_time(&self) -> Duration { self.min_frame_time }
+
+    #[inline]
+    pub const fn max_frame_time(&self) -> Duration { self.max_frame_time }
+
+    /// The average time per frame.
+    pub fn average_frame_time(&self) -> Duration {
+        if self.frame_count == 0 {
+            Duration::ZERO
+        } else {
+            self.total_time / self.frame_count
+        }
+    }
+
+    /// The number of frames per second, in hundredths.
+    pub fn centi_fps(&self) -> u32 {
+        (u64::from(self.frame_count) * 100_000_000)
+            .checked_div(self.total_time.as_micros() as u64)
+            .map_or(0, |centi_fps| centi_fps as u32)
+    }
+
+    /// Resets the statistics for the next measurement period.
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(millis: u64) -> Duration { Duration::from_millis(millis) }
+
+    #[test]
+    fn test_exact_ticks() {
+        let mut timestep = FixedTimestep::new(ms(20), 5);
+        assert_eq!(timestep.advance(ms(20)), 1);
+        assert_eq!(timestep.advance(ms(40)), 2);
+        assert_eq!(timestep.advance(ms(0)), 0);
+        assert_eq!(timestep.time_until_next_tick(), ms(20));
+    }
+
+    #[test]
+    fn test_remainder_carried_over() {
+        let mut timestep = FixedTimestep::new(ms(20), 5);
+        assert_eq!(timestep.advance(ms(15)), 0);
+        assert_eq!(timestep.time_until_next_tick(), ms(5));
+        assert_eq!(timestep.advance(ms(15)), 1);
+        assert_eq!(timestep.time_until_next_tick(), ms(10));
+        assert_eq!(timestep.advance(ms(15)), 1);
+        assert_eq!(timestep.advance(ms(15)), 1);
+        assert_eq!(timestep.time_until_next_tick(), ms(20));
+    }
+
+    #[test]
+    fn test_rate() {
+        // 60 Hz from 10 ms timer ticks: 60 simulation ticks in 100 timer ticks
+        let mut timestep = FixedTimestep::from_rate(60, 5);
+        let mut total = 0;
+        for _ in 0..100 {
+            total += timestep.advance(ms(10));
+        }
+        assert_eq!(total, 60);
+        assert_eq!(timestep.tick_duration(), Duration::from_nanos(16_666_666));
+    }
+
+    #[test]
+    fn test_catch_up_limit() {
+        let mut timestep = FixedTimestep::new(ms(20), 3);
+        assert_eq!(timestep.advance(ms(205)), 3);
+        // the excess has been dropped, only the remainder is left
+        assert_eq!(timestep.time_until_next_tick(), ms(15));
+        assert_eq!(timestep.advance(ms(15)), 1);
+    }
+
+    #[test]
+    fn test_frame_stats() {
+        let mut stats = FrameStats::new();
+        assert_eq!(stats.centi_fps(), 0);
+        assert_eq!(stats.average_frame_time(), Duration::ZERO);
+
+        stats.record_frame(ms(10));
+        stats.record_frame(ms(30));
+        stats.record_frame(ms(20));
+        stats.record_skipped(2);
+        assert_eq!(stats.frame_count(), 3);
+        assert_eq!(stats.skipped_count(), 2);
+        assert_eq!(stats.min_frame_time(), ms(10));
+        assert_eq!(stats.max_frame_time(), ms(30));
+        assert_eq!(stats.average_frame_time(), ms(20));
+        assert_eq!(stats.centi_fps(), 5000);
+
+        stats.reset();
+        assert_eq!(stats, FrameStats::new());
+    }
+}
@@ -0,0 +1,188 @@
+//! Sending data to SPI peripherals in the background using the Direct Memory Access Controller.
+
+
+use core::ptr::{addr_of, addr_of_mut, write_volatile};
+use core::sync::atomic::{Ordering, compiler_fence};
+
+use atsamd21g::Peripherals;
+use breakout_common::dmac::{TransferDescriptor, sercom_tx_trigger};
+use breakout_common::hal::{BackgroundSpiBus, ConfigurableSpiBus, SpiBus, SpiConfig};
+use breakout_common::sercom::{SERCOM_CLOCK_FREQUENCY, Sercom, spi_min_frequency};
+
+use crate::sercom::SercomSpi;
+use crate::spi::Spi;
+
+
+/// The number of DMAC channels in use.
+const CHANNEL_COUNT: usize = 1;
+
+/// The DMAC channel that feeds SERCOM1's transmitter.
+const SERCOM1_TX_CHANNEL: u8 = 0;
+
+/// The first transfer descriptor of each channel, from which the DMAC starts a transfer.
+static mut DESCRIPTORS: [TransferDescriptor; CHANNEL_COUNT] =
+    [TransferDescriptor::INVALID; CHANNEL_COUNT];
+
+/// The area into which the DMAC writes back the current descriptor of each channel.
+static mut WRITE_BACK: [TransferDescriptor; CHANNEL_COUNT] =
+    [TransferDescriptor::INVALID; CHANNEL_COUNT];
+
+
+/// Sets up the DMAC. The channels are configured whenever a transfer is started.
+pub(crate) fn set_up(peripherals: &mut Peripherals) {
+    // give power to DMAC
+    peripherals.PM.ahbmask.modify(|_, w| w
+        .dmac_().set_bit()
+    );
+    peripherals.PM.apbbmask.modify(|_, w| w
+        .dmac_().set_bit()
+    );
+
+    // reset DMAC
+    peripherals.DMAC.ctrl.modify(|_, w| w
+        .dmaenable().clear_bit()
+        .crcenable().clear_bit()
+    );
+    peripherals.DMAC.ctrl.modify(|_, w| w
+        .swrst().set_bit()
+    );
+    while peripherals.DMAC.ctrl.read().swrst().bit_is_set() {
+    }
+
+    // tell DMAC where to find the descriptors
+    // UNSAFE: only the addresses are taken; the DMAC is the only one accessing WRITE_BACK
+    unsafe {
+        peripherals.DMAC.baseaddr.write(|w| w
+            .baseaddr().bits(addr_of!(DESCRIPTORS) as u32)
+        );
+        peripherals.DMAC.wrbaddr.write(|w| w
+            .wrbaddr().bits(addr_of!(WRITE_BACK) as u32)
+        );
+    }
+
+    // enable DMAC with all priority levels
+    peripherals.DMAC.ctrl.modify(|_, w| w
+        .dmaenable().set_bit()
+        .lvlen0().set_bit()
+        .lvlen1().set_bit()
+        .lvlen2().set_bit()
+        .lvlen3().set_bit()
+    );
+}
+
+
+/// The SPI bus on SERCOM1, which can also send data in the background using DMA. The DMAC has to be
+/// set up using `dma::set_up` first.
+///
+/// While a background transfer is in progress, the bus must not be used in any other way. It is
+/// shared between devices through a [`Sercom1Bus`](crate::spi::Sercom1Bus), which only lets one of
+/// them use it at a time.
+pub struct Sercom1DmaSpi {
+    spi: SercomSpi,
+    in_flight: Option<&'static mut [u8]>,
+}
+impl Sercom1DmaSpi {
+    /// Takes over the SPI bus on SERCOM1.
+    pub fn new(spi: SercomSpi) -> Self {
+        assert_eq!(spi.sercom(), Sercom::Sercom1);
+        Self { spi, in_flight: None }
+    }
+
+    fn get_dmac(&self) -> &atsamd21g::dmac::RegisterBlock {
+        // UNSAFE: the channel registers are only accessed through this type once set up
+        unsafe { &*atsamd21g::DMAC::ptr() }
+    }
+
+    /// Whether the DMAC is still feeding bytes to SERCOM1.
+    fn is_channel_busy(&self) -> bool {
+        let dmac = self.get_dmac();
+        unsafe {
+            dmac.chid.write(|w| w
+                .id().bits(SERCOM1_TX_CHANNEL)
+            )
+        };
+
+        // the channel disables itself after the last block transfer
+        dmac.chctrla.read().enable().bit_is_set()
+    }
+}
+impl Spi for Sercom1DmaSpi {
+    fn get_sercom_spi(&self) -> &atsamd21g::sercom0::SPI { self.spi.get_sercom_spi() }
+}
+impl SpiBus for Sercom1DmaSpi {
+    fn write(&mut self, data: &[u8]) {
+        debug_assert!(self.in_flight.is_none());
+        self.send_data(data);
+    }
+
+    fn transfer(&mut self, data: &mut [u8]) {
+        debug_assert!(self.in_flight.is_none());
+        self.exchange_data(data);
+    }
+}
+impl ConfigurableSpiBus for Sercom1DmaSpi {
+    fn configure(&mut self, config: &SpiConfig) {
+        debug_assert!(self.in_flight.is_none());
+        self.apply_config(config);
+    }
+
+    fn min_frequency(&self) -> u32 { spi_min_frequency(SERCOM_CLOCK_FREQUENCY) }
+}
+impl BackgroundSpiBus for Sercom1DmaSpi {
+    fn start_write(&mut self, buffer: &'static mut [u8], length: usize) {
+        debug_assert!(self.in_flight.is_none());
+
+        let data_register = addr_of!(self.get_sercom_spi().data) as u32;
+        let descriptor = TransferDescriptor::memory_to_register(
+            buffer.as_ptr() as u32,
+            length,
+            data_register,
+        )
+            .expect("invalid DMA transfer");
+
+        // UNSAFE: the channel is idle, so the DMAC is not reading the descriptor right now
+        unsafe {
+            write_volatile(addr_of_mut!(DESCRIPTORS[usize::from(SERCOM1_TX_CHANNEL)]), descriptor)
+        };
+
+        // the buffer and the descriptor must be in RAM before the DMAC starts reading them
+        compiler_fence(Ordering::SeqCst);
+
+        let dmac = self.get_dmac();
+        unsafe {
+            dmac.chid.write(|w| w
+                .id().bits(SERCOM1_TX_CHANNEL)
+            );
+
+            // move one byte whenever SERCOM1 is ready to transmit the next one
+            dmac.chctrlb.write(|w| w
+                .trigsrc().bits(sercom_tx_trigger(Sercom::Sercom1.index()))
+                .trigact().beat()
+                .lvl().bits(0)
+            );
+        }
+        dmac.chctrla.modify(|_, w| w
+            .enable().set_bit()
+        );
+
+        self.in_flight = Some(buffer);
+    }
+
+    fn finish_write(&mut self) -> Option<&'static mut [u8]> {
+        let buffer = self.in_flight.take()?;
+
+        // wait for the DMAC to hand over the last byte
+        while self.is_channel_busy() {
+        }
+
+        // wait for transmission to end fully
+        let sercom_spi = self.get_sercom_spi();
+        while sercom_spi.intflag.read().txc().bit_is_clear() {
+        }
+
+        // the DMAC is done with the buffer; don't let accesses to it move before this point
+        compiler_fence(Ordering::SeqCst);
+
+        Some(buffer)
+    }
+}
@@ -0,0 +1,42 @@
+//! The I2C bus on the mikroBUS SDA/SCL pins, driven by SERCOM3.
+
+
+use core::time::Duration;
+
+use atsamd21g::Peripherals;
+use breakout_common::i2c::I2cController;
+use breakout_common::instant::Instant;
+use breakout_common::sercom::{I2cPins, PinBank, PinId, Sercom};
+
+use crate::sercom::{SercomI2c, set_up_i2c};
+
+
+/// SDA and SCL of the canonical Arduino Zero pinout, which are on SERCOM3 (peripheral C).
+const MIKROBUS_I2C_PINS: I2cPins = I2cPins {
+    data: PinId::new(PinBank::PA, 22),
+    clock: PinId::new(PinBank::PA, 23),
+};
+
+/// The SCL frequency; standard mode, since we do not know which pull-ups the click boards bring.
+const MAX_SCL_FREQUENCY: u32 = 100_000;
+
+/// The longest rise time the I2C specification allows in standard mode.
+const SCL_RISE_TIME_NS: u32 = 1_000;
+
+/// How long a transaction may take before giving up on the target. A transaction of a few bytes
+/// takes well under a millisecond at 100 kHz.
+const TRANSACTION_TIMEOUT: Duration = Duration::from_millis(10);
+
+
+/// The I2C bus on the mikroBUS slots.
+pub type MikroBusI2c = I2cController<SercomI2c, fn() -> Instant>;
+
+
+/// Sets up SERCOM3 as the host of the I2C bus on the mikroBUS slots.
+#[allow(unused)]
+pub fn set_up(peripherals: &mut Peripherals) -> MikroBusI2c {
+    let registers = set_up_i2c(
+        peripherals, Sercom::Sercom3, &MIKROBUS_I2C_PINS, MAX_SCL_FREQUENCY, SCL_RISE_TIME_NS,
+    );
+    I2cController::new(registers, crate::timer::now as fn() -> Instant, TRANSACTION_TIMEOUT)
+}
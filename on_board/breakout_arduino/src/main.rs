@@ -3,24 +3,90 @@
 
 
 mod calib;
+mod dma;
+mod i2c;
 mod init;
 mod keypad;
+mod nvm;
 mod oled;
 mod pin;
+mod sercom;
 mod spi;
 mod timer;
 mod usart;
 
 
+use core::fmt::Write;
 use core::panic::PanicInfo;
+use core::time::Duration;
 
 use atsamd21g::Peripherals;
+use breakout_common::burn_in::{BurnInConfig, BurnInGuard, PanelState};
+use breakout_common::controller::DisplayController;
+#[cfg(feature = "ssd1351")]
+use breakout_common::controller::Ssd1351Controller;
+use breakout_common::display::{DoubleBuffer, update_area_in_background};
+use breakout_common::effects::{Effect, Effects};
+#[cfg(feature = "ssd1351")]
+use breakout_common::display_config::DisplayConfig;
 use breakout_common::fixedpoint::FixedPoint;
-use breakout_common::playfield::{DISPLAY_BYTES, Playfield};
+#[cfg(feature = "ssd1351")]
+use breakout_common::grayscale::GAMMA_2_2;
+use breakout_common::keypad::KeypadState;
+use breakout_common::layout::DisplayLayout;
+use breakout_common::orientation::{Orientation, Rotation};
+use breakout_common::playfield::Playfield;
+use breakout_common::settings::Settings;
+#[cfg(feature = "ssd1331")]
+use breakout_common::ssd1331::Ssd1331Controller;
+use breakout_common::timing::{
+    DEFAULT_MAX_TICKS_PER_FRAME, FixedTimestep, FrameStats, TICKS_PER_SECOND,
+};
 use cortex_m::Peripherals as CorePeripherals;
+use cortex_m::asm::wfi;
 use cortex_m_rt::{entry, exception};
 
-use crate::oled::{ArduinoZeroClick1Interface, DisplayCommand, DisplayInterface};
+use crate::usart::UartWriter;
+
+
+#[cfg(all(feature = "ssd1351", feature = "ssd1331"))]
+compile_error!("select only one display controller feature");
+
+#[cfg(not(any(feature = "ssd1351", feature = "ssd1331")))]
+compile_error!("select a display controller feature (ssd1351 or ssd1331)");
+
+
+/// The orientation in which the display is mounted in the enclosure.
+const ORIENTATION: Orientation = Orientation::new(Rotation::Deg0, false);
+
+/// The geometry of the display connected to the board.
+#[cfg(feature = "ssd1351")]
+const LAYOUT: DisplayLayout = DisplayLayout::PSP27801.with_orientation(ORIENTATION);
+
+/// The geometry of the display connected to the board.
+#[cfg(feature = "ssd1331")]
+const LAYOUT: DisplayLayout = DisplayLayout::SSD1331_96X64.with_orientation(ORIENTATION);
+
+/// The analog and timing settings of the panel connected to the board, with the gamma curve of an
+/// sRGB monitor so that colors look as they do in the SDL version.
+#[cfg(feature = "ssd1351")]
+const DISPLAY_CONFIG: DisplayConfig = DisplayConfig::PSP27801
+    .with_grayscale_table(Some(GAMMA_2_2));
+
+/// How often frame statistics are reported over the EDBG UART.
+const STATS_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How long the settings have to stay unchanged before they are saved, so that stepping through
+/// several brightness levels erases the flash only once.
+const SETTINGS_SAVE_DELAY: Duration = Duration::from_secs(3);
+
+/// How many rows of the display are rendered and sent at once.
+///
+/// A full frame would take up more than half of the RAM, so the frame is streamed in bands.
+const BAND_ROWS: usize = 8;
+
+/// The size of each of the two band buffers.
+const BAND_BYTES: usize = BAND_ROWS * LAYOUT.row_bytes();
 
 
 #[panic_handler]
@@ -57,6 +123,22 @@ fn SysTick() {
 }
 
 
+/// The controller of the display connected to the board, set up to start at the given brightness.
+#[cfg(feature = "ssd1351")]
+fn display_controller(settings: &Settings) -> Ssd1351Controller {
+    Ssd1351Controller::new(
+        DISPLAY_CONFIG.with_master_contrast(settings.brightness.master_contrast())
+    )
+}
+
+/// The controller of the display connected to the board, set up to start at the given brightness.
+#[cfg(feature = "ssd1331")]
+fn display_controller(settings: &Settings) -> Ssd1331Controller {
+    Ssd1331Controller::PANEL_96X64
+        .with_master_current(settings.brightness.master_contrast())
+}
+
+
 fn nibble_to_hex_byte(nibble: u8) -> u8 {
     match nibble {
         0x0..=0x9 => nibble + b'0',
@@ -136,36 +218,149 @@ fn main() -> ! {
     // set up EDBG UART
     crate::init::init_edbg_uart(&mut peripherals);
 
-    // set up SPI and display
-    let display = ArduinoZeroClick1Interface;
-    display.set_up(&mut peripherals);
+    // restore the settings saved before the last power-off
+    let mut settings = crate::nvm::load_settings();
+
+    // set up the SPI bus shared by the display and the keypad
+    let spi_bus = crate::spi::set_up(&mut peripherals);
+
+    // set up display
+    let controller = display_controller(&settings);
+    let mut display = crate::oled::set_up(&mut peripherals, &spi_bus, &controller, &LAYOUT);
 
     // set up keypad
-    crate::keypad::setup_keypad_pins(&mut peripherals);
+    let mut keypad = crate::keypad::set_up(&mut peripherals, &spi_bus);
+
+    // one band is sent by DMA while the next one is being drawn
+    let mut band_buffers = DoubleBuffer::new(
+        cortex_m::singleton!(: [u8; BAND_BYTES] = [0; BAND_BYTES])
+            .expect("first band buffer already taken?!"),
+        cortex_m::singleton!(: [u8; BAND_BYTES] = [0; BAND_BYTES])
+            .expect("second band buffer already taken?!"),
+    );
 
     // set up the playfield
-    let mut playfield = Playfield::new();
+    let mut playfield = Playfield::new(LAYOUT);
 
     // move the ball a bit along the X axis for more interesting patterns
     playfield.ball.position.x += FixedPoint::new_integer(7);
 
-    let mut delay_counter: u8 = 0;
+    let mut timestep = FixedTimestep::from_rate(TICKS_PER_SECOND, DEFAULT_MAX_TICKS_PER_FRAME);
+    let mut stats = FrameStats::new();
+    let mut previous_keypad_state = KeypadState::NONE_PRESSED;
+    let mut burn_in_guard = BurnInGuard::new(BurnInConfig::DEFAULT);
+    let mut effects = Effects::new();
+    let mut unsaved_for: Option<Duration> = None;
+    let mut previous_instant = crate::timer::now();
+    let mut since_last_frame = Duration::ZERO;
     loop {
+        let now = crate::timer::now();
+        let elapsed = now.duration_since(previous_instant);
+        previous_instant = now;
+        since_last_frame += elapsed;
+
+        // advance the simulation by as many ticks as have become due; if rendering took longer than
+        // a tick, this skips frames
+        let ticks = timestep.advance(elapsed);
+        if ticks == 0 {
+            // nothing has changed; sleep until the next timer interrupt
+            wfi();
+            continue;
+        }
+        // the last band of the previous frame may still be on its way to the display meanwhile
+        for _ in 0..ticks {
+            playfield.advance();
+        }
+
+        // the keypad shares the bus with the display, so wait for the display to be done (the bus
+        // manager would panic otherwise)
+        band_buffers.reclaim(&mut display);
+
         // read keypad state
-        let state = crate::keypad::read_keypad(&mut peripherals);
-        // TODO: process keypad state
+        let state = keypad.read();
+        let mut pressed = state.newly_pressed(&previous_keypad_state);
+        previous_keypad_state = state;
+
+        // move the image now and then; dim and turn off the display while nobody is playing
+        let panel_state = burn_in_guard.state();
+        let guard_commands = burn_in_guard.advance(
+            since_last_frame, state != KeypadState::NONE_PRESSED, settings.brightness,
+        );
+        for command in &guard_commands {
+            controller.transmit(&mut display, command);
+        }
+        if panel_state != PanelState::Active {
+            // the key press that wakes up the display has no other effect
+            pressed = KeypadState::NONE_PRESSED;
+        }
+        // TODO: process the rest of the keypad state
 
-        delay_counter += 1;
-        if delay_counter == 2 {
-            delay_counter = 0;
-            playfield.advance();
+        // * and # change the brightness
+        let previous_settings = settings;
+        if pressed.is_asterisk_pressed() {
+            settings.brightness = settings.brightness.dimmer();
+        }
+        if pressed.is_hash_pressed() {
+            settings.brightness = settings.brightness.brighter();
+        }
+        if settings.brightness != previous_settings.brightness {
+            controller.set_brightness(&mut display, settings.brightness);
+            unsaved_for = Some(Duration::ZERO);
+        } else if let Some(duration) = unsaved_for.as_mut() {
+            *duration += since_last_frame;
+            if *duration >= SETTINGS_SAVE_DELAY {
+                if !crate::nvm::save_settings(&mut peripherals, &settings) {
+                    crate::usart::write(&mut peripherals, b"saving the settings failed\r\n");
+                }
+                unsaved_for = None;
+            }
         }
 
-        let mut pixbuf = [0u8; DISPLAY_BYTES];
-        playfield.draw(&mut pixbuf);
-        DisplayCommand::WriteRam.transmit(&display, &mut peripherals);
-        display.send(&mut peripherals, None, &pixbuf);
+        // there are no lives, bricks or levels yet; A, B and C show off the effects meanwhile (the
+        // SSD1331 cannot scroll, so the wipe merely holds the image there)
+        if pressed.is_a_pressed() {
+            effects.start(Effect::Flash);
+        }
+        if pressed.is_b_pressed() {
+            effects.start(Effect::Shake);
+        }
+        if pressed.is_c_pressed() {
+            effects.start(Effect::Wipe);
+        }
+        let effect_step = effects.advance(burn_in_guard.display_offset());
+        for command in &effect_step.commands {
+            controller.transmit(&mut display, command);
+        }
+        if effect_step.redraw {
+            playfield.invalidate();
+        }
+
+        // only send what has changed; the first frame redraws the whole display in bands (while the
+        // image is scrolling, the display RAM must not be touched at all)
+        if effects.may_update_display() {
+            for area in playfield.take_dirty_areas() {
+                update_area_in_background(
+                    &mut display, &controller, &LAYOUT, area, &mut band_buffers,
+                    |part, buffer| playfield.draw_area(part, buffer),
+                );
+            }
+        }
 
-        // TODO: delay?
+        stats.record_frame(since_last_frame);
+        since_last_frame = Duration::ZERO;
+        stats.record_skipped(ticks - 1);
+        if stats.total_time() >= STATS_INTERVAL {
+            let _ = write!(
+                UartWriter::new(&mut peripherals),
+                "{}.{:02} fps, frame time {}/{}/{} ms, {} skipped\r\n",
+                stats.centi_fps() / 100,
+                stats.centi_fps() % 100,
+                stats.min_frame_time().as_millis(),
+                stats.average_frame_time().as_millis(),
+                stats.max_frame_time().as_millis(),
+                stats.skipped_count(),
+            );
+            stats.reset();
+        }
     }
 }
@@ -1,6 +1,7 @@
 /* Linker script for the ATSAMD21G18 */
 MEMORY
 {
-  FLASH : ORIGIN = 0x00000000, LENGTH = 256K
+  /* the last row (256 bytes) holds the settings, see nvm.rs */
+  FLASH : ORIGIN = 0x00000000, LENGTH = 256K - 256
   RAM : ORIGIN = 0x20000000, LENGTH = 32K
 }
@@ -0,0 +1,121 @@
+//! Storage of the user settings in the internal flash.
+//!
+//! The SAM D21G18A has no separate EEPROM, so the last row of the main flash array is set aside
+//! for the settings (see `memory.x`). A row is the unit of erasure and consists of four pages; the
+//! settings fit into the first page.
+//!
+//! Erased flash reads as 0xFF, which is not a valid settings version, so a board that has never
+//! saved its settings starts with the defaults.
+
+
+use core::ptr::{read_volatile, write_volatile};
+
+use atsamd21g::{NVMCTRL, Peripherals};
+use breakout_common::settings::{SETTINGS_BYTES, Settings};
+
+
+/// The address of the flash row that holds the settings; it is excluded from `FLASH` in
+/// `memory.x`.
+const SETTINGS_ROW: usize = 0x0003_FF00;
+
+/// The settings rounded up to whole 32-bit words, the unit in which the page buffer is written.
+const SETTINGS_WORDS: usize = SETTINGS_BYTES.div_ceil(4);
+
+
+/// Reads the settings as they are currently stored.
+fn stored_bytes() -> [u8; SETTINGS_BYTES] {
+    let mut bytes = [0u8; SETTINGS_BYTES];
+    for (i, b) in bytes.iter_mut().enumerate() {
+        // UNSAFE: the settings row is ordinary, always readable flash
+        *b = unsafe { read_volatile((SETTINGS_ROW + i) as *const u8) };
+    }
+    bytes
+}
+
+/// Waits until the NVM controller is ready for the next command.
+fn wait_until_ready(nvmctrl: &NVMCTRL) {
+    while nvmctrl.intflag.read().ready().bit_is_clear() {
+    }
+}
+
+/// Points the next NVM command at the given byte address.
+fn set_address(nvmctrl: &NVMCTRL, address: usize) {
+    // the register takes the address of a 16-bit halfword
+    // UNSAFE: the address register accepts any value; the command checks it
+    unsafe {
+        nvmctrl.addr.write(|w| w
+            .addr().bits((address / 2) as u32)
+        )
+    };
+}
+
+
+/// Loads the stored settings, falling back to the defaults if none have been stored yet or they
+/// cannot be decoded.
+pub(crate) fn load_settings() -> Settings {
+    Settings::from_bytes(&stored_bytes())
+        .unwrap_or_default()
+}
+
+/// Stores the settings so that they are restored at the next boot, and returns whether they read
+/// back as written.
+///
+/// Flash endures only a limited number of erase cycles, so nothing is written if the settings are
+/// already stored. The CPU stalls while the row is being erased and written, which takes a few
+/// milliseconds.
+pub(crate) fn save_settings(peripherals: &mut Peripherals, settings: &Settings) -> bool {
+    let bytes = settings.to_bytes();
+    if stored_bytes() == bytes {
+        return true;
+    }
+
+    let nvmctrl = &peripherals.NVMCTRL;
+
+    // write pages only on explicit command
+    nvmctrl.ctrlb.modify(|_, w| w
+        .manw().set_bit()
+    );
+
+    // erase the whole row
+    wait_until_ready(nvmctrl);
+    set_address(nvmctrl, SETTINGS_ROW);
+    nvmctrl.ctrla.write(|w| w
+        .cmdex().key()
+        .cmd().er()
+    );
+    wait_until_ready(nvmctrl);
+
+    // fill the page buffer; the unused bytes stay erased
+    nvmctrl.ctrla.write(|w| w
+        .cmdex().key()
+        .cmd().pbc()
+    );
+    wait_until_ready(nvmctrl);
+    let mut words = [0xFFFF_FFFFu32; SETTINGS_WORDS];
+    for (i, b) in bytes.iter().enumerate() {
+        words[i / 4] &= !(0xFF << (8 * (i % 4)));
+        words[i / 4] |= u32::from(*b) << (8 * (i % 4));
+    }
+    for (i, word) in words.iter().enumerate() {
+        // UNSAFE: writes to the flash address space only end up in the page buffer
+        unsafe { write_volatile((SETTINGS_ROW + 4 * i) as *mut u32, *word) };
+    }
+
+    // write the page buffer into the first page of the row
+    set_address(nvmctrl, SETTINGS_ROW);
+    nvmctrl.ctrla.write(|w| w
+        .cmdex().key()
+        .cmd().wp()
+    );
+    wait_until_ready(nvmctrl);
+
+    // the NVM read cache is not updated by writes; drop the lines that still hold the old
+    // contents of the row, or they would be read back (and compared against) later
+    nvmctrl.ctrla.write(|w| w
+        .cmdex().key()
+        .cmd().invall()
+    );
+    wait_until_ready(nvmctrl);
+
+    stored_bytes() == bytes
+}
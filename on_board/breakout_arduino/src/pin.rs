@@ -1,6 +1,11 @@
 //! Macros to make manipulating digital I/O pins more straightforward.
 
 
+use atsamd21g::Peripherals;
+use breakout_common::hal::OutputPin;
+pub use breakout_common::sercom::PinBank;
+
+
 /// One of the peripherals to choose in the peripheral multiplexer.
 #[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
 #[allow(unused)]
@@ -55,6 +60,103 @@ impl_from_peripheral!(atsamd21g::port::pmux1_::PMUXE_A);
 impl_from_peripheral!(atsamd21g::port::pmux1_::PMUXO_A);
 
 
+/// A single I/O pin used as an output.
+#[derive(Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct PortPin {
+    bank: PinBank,
+    index: u8,
+}
+impl PortPin {
+    /// Wraps the given pin. It must already have been configured as an output using `iopin!`.
+    pub const fn new(bank: PinBank, index: u8) -> Self {
+        assert!(index < 32);
+        Self {
+            bank,
+            index,
+        }
+    }
+
+    #[inline]
+    const fn mask(&self) -> u32 { 1 << self.index }
+
+    #[inline]
+    fn port(&self) -> &'static atsamd21g::port::RegisterBlock {
+        // UNSAFE: the set and clear registers only affect the pins whose bits are written, so
+        // handles to different pins do not interfere with each other
+        unsafe { &*atsamd21g::PORT::ptr() }
+    }
+}
+impl OutputPin for PortPin {
+    fn set_high(&mut self) {
+        let port = self.port();
+        let val = self.mask();
+        // UNSAFE: setting any bit in that register leaves the SAM D21 in a valid state
+        unsafe {
+            match self.bank {
+                PinBank::PA => port.outset0.write(|w| w.outset().bits(val)),
+                PinBank::PB => port.outset1.write(|w| w.outset().bits(val)),
+            }
+        };
+    }
+
+    fn set_low(&mut self) {
+        let port = self.port();
+        let val = self.mask();
+        // UNSAFE: as above
+        unsafe {
+            match self.bank {
+                PinBank::PA => port.outclr0.write(|w| w.outclr().bits(val)),
+                PinBank::PB => port.outclr1.write(|w| w.outclr().bits(val)),
+            }
+        };
+    }
+}
+
+
+/// Hands over a pin to a peripheral; the same as `iopin!` with `select_peripheral` and
+/// `make_peripheral`, but for a pin that is only known at runtime.
+pub fn connect_to_peripheral(
+    peripherals: &mut Peripherals,
+    bank: PinBank,
+    index: u8,
+    peripheral: Peripheral,
+) {
+    let index = usize::from(index);
+
+    // select the peripheral before handing over the pin so that it never reaches another one
+    match bank {
+        PinBank::PA => {
+            if index % 2 == 0 {
+                peripherals.PORT.pmux0_[index / 2].modify(|_, w| w
+                    .pmuxe().variant(peripheral.into())
+                );
+            } else {
+                peripherals.PORT.pmux0_[index / 2].modify(|_, w| w
+                    .pmuxo().variant(peripheral.into())
+                );
+            }
+            peripherals.PORT.pincfg0_[index].modify(|_, w| w
+                .pmuxen().set_bit()
+            );
+        },
+        PinBank::PB => {
+            if index % 2 == 0 {
+                peripherals.PORT.pmux1_[index / 2].modify(|_, w| w
+                    .pmuxe().variant(peripheral.into())
+                );
+            } else {
+                peripherals.PORT.pmux1_[index / 2].modify(|_, w| w
+                    .pmuxo().variant(peripheral.into())
+                );
+            }
+            peripherals.PORT.pincfg1_[index].modify(|_, w| w
+                .pmuxen().set_bit()
+            );
+        },
+    }
+}
+
+
 /// The Universal Magic I/O Pin Macro.
 ///
 /// Examples of calls:
@@ -0,0 +1,349 @@
+//! Setting up the SAM D21's serial communication modules (SERCOMs) in any of their modes.
+//!
+//! Which pins reach which pads and how the pads and baud rates translate into register values is
+//! worked out in [`breakout_common::sercom`]; this module only writes the registers.
+
+
+use atsamd21g::Peripherals;
+use atsamd21g::sercom0::RegisterBlock;
+use breakout_common::hal::{ConfigurableSpiBus, SpiBus, SpiConfig};
+use breakout_common::i2c::{I2cCommand, I2cRegisters, I2cStatus};
+use breakout_common::sercom::{
+    I2cPins, SERCOM_CLOCK_FREQUENCY, Sercom, SercomFunction, SercomPad, SpiPins, UartPins,
+    clock_control, i2c_baud, spi_min_frequency, uart_baud,
+};
+
+use crate::pin::{Peripheral, connect_to_peripheral};
+use crate::spi::Spi;
+
+
+/// The registers of a SERCOM, in whichever mode it is.
+pub fn registers(sercom: Sercom) -> &'static RegisterBlock {
+    // UNSAFE: each SERCOM is only driven through the one value that was handed out when it was set
+    // up (or by the panic handler, which never returns)
+    unsafe {
+        match sercom {
+            Sercom::Sercom0 => &*atsamd21g::SERCOM0::ptr(),
+            Sercom::Sercom1 => &*atsamd21g::SERCOM1::ptr(),
+            Sercom::Sercom2 => &*atsamd21g::SERCOM2::ptr(),
+            Sercom::Sercom3 => &*atsamd21g::SERCOM3::ptr(),
+            Sercom::Sercom4 => &*atsamd21g::SERCOM4::ptr(),
+            Sercom::Sercom5 => &*atsamd21g::SERCOM5::ptr(),
+        }
+    }
+}
+
+
+/// Gives power to a SERCOM, feeds it GCLK0 (the main CPU clock), hands over the pins of the given
+/// pads to it and resets it.
+pub(crate) fn connect(peripherals: &mut Peripherals, sercom: Sercom, pads: &[SercomPad]) {
+    for pad in pads {
+        debug_assert_eq!(pad.sercom, sercom);
+        let peripheral = match pad.function {
+            SercomFunction::Sercom => Peripheral::C,
+            SercomFunction::SercomAlt => Peripheral::D,
+        };
+        connect_to_peripheral(peripherals, pad.pin.bank, pad.pin.index, peripheral);
+    }
+
+    // give power to the SERCOM
+    // UNSAFE: the other bits are written back as they were
+    peripherals.PM.apbcmask.modify(|r, w| unsafe { w
+        .bits(r.bits() | sercom.apbc_mask())
+    });
+
+    // connect GCLK0 to the SERCOM
+    // UNSAFE: the value selects an existing clock and generator
+    peripherals.GCLK.clkctrl.write(|w| unsafe { w
+        .bits(clock_control(sercom.core_clock_id(), 0))
+    });
+
+    // reset the SERCOM (CTRLA.SWRST is in the same place in every mode)
+    let sercom_spi = registers(sercom).spi();
+    sercom_spi.ctrla.modify(|_, w| w
+        .swrst().set_bit()
+    );
+    while sercom_spi.ctrla.read().swrst().bit_is_set() && sercom_spi.syncbusy.read().swrst().bit_is_set() {
+    }
+}
+
+
+/// Sets up a SERCOM as an SPI controller on the given pins, sending the MSB first.
+///
+/// Clock polarity, phase and frequency are set by [`ConfigurableSpiBus::configure`], so the
+/// controller stays disabled until then.
+pub fn set_up_spi(peripherals: &mut Peripherals, sercom: Sercom, pins: &SpiPins) -> SercomSpi {
+    let pinout = pins.resolve(sercom)
+        .expect("SPI pins not usable on this SERCOM");
+    connect(peripherals, sercom, &pinout.pads());
+
+    // switch the SERCOM to SPI controller mode
+    let sercom_spi = registers(sercom).spi();
+    sercom_spi.ctrla.modify(|_, w| w
+        .mode().spi_master()
+    );
+    // (no synchronization)
+
+    unsafe {
+        sercom_spi.ctrla.modify(|_, w| w
+            .dopo().bits(pinout.dopo()) // COPI and SCK; ~CS is controlled manually
+            .dipo().bits(pinout.dipo()) // CIPO
+            .form().bits(0) // data format: SPI frame without address
+            .dord().clear_bit() // MSB first
+        )
+    };
+    // (no synchronization)
+
+    unsafe {
+        sercom_spi.ctrlb.modify(|_, w| w
+            .chsize().bits(0) // 8 bits per byte
+            .ssde().clear_bit() // no wakeup on ~CS fall
+            .mssen().clear_bit() // no control of ~CS pin through SERCOM (we do it manually)
+            .rxen().set_bit() // enable receiver
+        )
+    };
+    // (no synchronization -- rxen is only synchronized if SPI is enabled)
+
+    SercomSpi { sercom }
+}
+
+
+/// Sets up a SERCOM as an asynchronous UART on the given pins: 8 data bits, no parity, one stop
+/// bit, LSB first (RS-232 standard).
+pub fn set_up_uart(peripherals: &mut Peripherals, sercom: Sercom, pins: &UartPins, baud_rate: u32) {
+    let pinout = pins.resolve(sercom)
+        .expect("UART pins not usable on this SERCOM");
+    let baud = uart_baud(SERCOM_CLOCK_FREQUENCY, baud_rate)
+        .expect("UART baud rate not possible");
+    connect(peripherals, sercom, &pinout.pads());
+
+    // switch the SERCOM to USART mode
+    let sercom_usart = registers(sercom).usart();
+    sercom_usart.ctrla.modify(|_, w| w
+        .mode().usart_int_clk()
+    );
+    // (no synchronization)
+
+    unsafe {
+        sercom_usart.ctrla.modify(|_, w| w
+            .sampr().bits(0x0) // 16x oversampling, arithmetic baud rate generation
+            .txpo().bits(pinout.txpo()) // transmit pad, no RTS/CTS
+            .rxpo().bits(pinout.rxpo()) // receive pad
+            .sampa().bits(0x0) // use samples 7-8-9
+            .form().bits(0x0) // regular USART frame, no parity
+            .cmode().clear_bit() // async communication (UART, not USRT)
+            .dord().set_bit() // LSB first
+        )
+    };
+    // (no synchronization)
+
+    unsafe {
+        sercom_usart.ctrlb.modify(|_, w| w
+            .chsize().bits(0x0) // 8 bits per byte
+            .sbmode().clear_bit() // single stop bit
+            .colden().clear_bit() // no collision detection
+            .sfde().clear_bit() // disable start-of-frame detection
+            .enc().clear_bit() // regular (non-IrDA) encoding
+            .txen().set_bit() // enable transmitter
+            .rxen().set_bit() // enable receiver
+        )
+    };
+    // (no synchronization -- txen/rxen are only synchronized if the USART is enabled)
+
+    unsafe {
+        sercom_usart.baud().modify(|_, w| w
+            .baud().bits(baud)
+        )
+    };
+    // (no synchronization)
+
+    // enable USART
+    sercom_usart.ctrla.modify(|_, w| w
+        .enable().set_bit()
+    );
+    while sercom_usart.syncbusy.read().enable().bit_is_set() {
+    }
+}
+
+
+/// Sets up a SERCOM as an I2C host on the given pins with an SCL frequency of at most
+/// `max_frequency`, given that SCL takes `rise_time_ns` nanoseconds to rise on this bus.
+///
+/// The bus is assumed to be idle afterwards, since this is the only host on it.
+pub fn set_up_i2c(
+    peripherals: &mut Peripherals,
+    sercom: Sercom,
+    pins: &I2cPins,
+    max_frequency: u32,
+    rise_time_ns: u32,
+) -> SercomI2c {
+    let pads = pins.resolve(sercom)
+        .expect("I2C pins not usable on this SERCOM");
+    let baud = i2c_baud(SERCOM_CLOCK_FREQUENCY, max_frequency, rise_time_ns)
+        .expect("I2C clock frequency not possible");
+    connect(peripherals, sercom, &pads);
+
+    // switch the SERCOM to I2C host mode
+    let sercom_i2cm = registers(sercom).i2cm();
+    sercom_i2cm.ctrla.modify(|_, w| w
+        .mode().i2c_master()
+    );
+    // (no synchronization)
+
+    unsafe {
+        sercom_i2cm.ctrla.modify(|_, w| w
+            .speed().bits(0x0) // standard and fast mode
+            .sdahold().bits(0x2) // hold SDA for 300-600 ns after SCL falls
+            .inactout().bits(0x0) // do not time out on an inactive bus (we are the only host)
+            .lowtouten().clear_bit() // no SCL low timeout (the driver has its own timeouts)
+        )
+    };
+    // (no synchronization)
+
+    sercom_i2cm.ctrlb.modify(|_, w| w
+        .smen().clear_bit() // no smart mode; every ACK is sent by command
+        .qcen().clear_bit() // no quick command
+    );
+    while sercom_i2cm.syncbusy.read().sysop().bit_is_set() {
+    }
+
+    unsafe {
+        sercom_i2cm.baud.modify(|_, w| w
+            .baud().bits(baud)
+            .baudlow().bits(0) // the low time of SCL is the same as the high time
+        )
+    };
+    // (no synchronization)
+
+    // enable I2C
+    sercom_i2cm.ctrla.modify(|_, w| w
+        .enable().set_bit()
+    );
+    while sercom_i2cm.syncbusy.read().enable().bit_is_set() {
+    }
+
+    let mut i2c = SercomI2c { sercom };
+    i2c.force_idle();
+    i2c
+}
+
+
+/// An I2C host on any SERCOM, which has to be set up using [`set_up_i2c`] first.
+pub struct SercomI2c {
+    sercom: Sercom,
+}
+impl SercomI2c {
+    fn get_sercom_i2cm(&self) -> &atsamd21g::sercom0::I2CM {
+        registers(self.sercom).i2cm()
+    }
+
+    fn wait_for_sync(&self) {
+        while self.get_sercom_i2cm().syncbusy.read().sysop().bit_is_set() {
+        }
+    }
+}
+impl I2cRegisters for SercomI2c {
+    fn status(&self) -> I2cStatus {
+        let sercom_i2cm = self.get_sercom_i2cm();
+        let intflag = sercom_i2cm.intflag.read();
+        let status = sercom_i2cm.status.read();
+        I2cStatus {
+            host_on_bus: intflag.mb().bit_is_set(),
+            target_on_bus: intflag.sb().bit_is_set(),
+            received_nack: status.rxnack().bit_is_set(),
+            arbitration_lost: status.arblost().bit_is_set(),
+            bus_error: status.buserr().bit_is_set(),
+        }
+    }
+
+    fn write_address(&mut self, address_byte: u8) {
+        // UNSAFE: 7-bit addressing without DMA length counter, so any byte is a valid ADDR value
+        unsafe {
+            self.get_sercom_i2cm().addr.write(|w| w
+                .addr().bits(u16::from(address_byte))
+            )
+        };
+        self.wait_for_sync();
+    }
+
+    fn write_data(&mut self, byte: u8) {
+        self.get_sercom_i2cm().data.write(|w| w
+            .data().bits(byte)
+        );
+        self.wait_for_sync();
+    }
+
+    fn read_data(&mut self) -> u8 {
+        let byte = self.get_sercom_i2cm().data.read().data().bits();
+        self.wait_for_sync();
+        byte
+    }
+
+    fn command(&mut self, command: I2cCommand) {
+        // UNSAFE: both commands are valid CMD values
+        unsafe {
+            self.get_sercom_i2cm().ctrlb.modify(|_, w| w
+                .ackact().bit(command.ackact())
+                .cmd().bits(command.cmd())
+            )
+        };
+        self.wait_for_sync();
+    }
+
+    fn clear_errors(&mut self) {
+        let sercom_i2cm = self.get_sercom_i2cm();
+
+        // these flags are cleared by writing ones
+        sercom_i2cm.status.write(|w| w
+            .buserr().set_bit()
+            .arblost().set_bit()
+        );
+        self.wait_for_sync();
+        sercom_i2cm.intflag.write(|w| w
+            .mb().set_bit()
+            .sb().set_bit()
+            .error().set_bit()
+        );
+    }
+
+    fn force_idle(&mut self) {
+        // UNSAFE: 0x1 is the IDLE bus state
+        unsafe {
+            self.get_sercom_i2cm().status.write(|w| w
+                .busstate().bits(0x1)
+            )
+        };
+        self.wait_for_sync();
+    }
+}
+
+
+/// An SPI bus on any SERCOM, which has to be set up using [`set_up_spi`] first.
+pub struct SercomSpi {
+    sercom: Sercom,
+}
+impl SercomSpi {
+    #[inline]
+    pub const fn sercom(&self) -> Sercom { self.sercom }
+}
+impl Spi for SercomSpi {
+    fn get_sercom_spi(&self) -> &atsamd21g::sercom0::SPI {
+        registers(self.sercom).spi()
+    }
+}
+impl SpiBus for SercomSpi {
+    fn write(&mut self, data: &[u8]) {
+        self.send_data(data);
+    }
+
+    fn transfer(&mut self, data: &mut [u8]) {
+        self.exchange_data(data);
+    }
+}
+impl ConfigurableSpiBus for SercomSpi {
+    fn configure(&mut self, config: &SpiConfig) {
+        self.apply_config(config);
+    }
+
+    fn min_frequency(&self) -> u32 { spi_min_frequency(SERCOM_CLOCK_FREQUENCY) }
+}
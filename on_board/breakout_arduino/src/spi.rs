@@ -1,21 +1,31 @@
 //! Interfacing with the SAM D21's Serial Peripheral Interface capabilities.
 
 use atsamd21g::Peripherals;
+use breakout_common::hal::SpiConfig;
+use breakout_common::sercom::{PinBank, PinId, SERCOM_CLOCK_FREQUENCY, Sercom, SpiPins, spi_baud};
+use breakout_common::spi_device::SharedSpiBus;
 
+use crate::dma::Sercom1DmaSpi;
+use crate::sercom::set_up_spi;
 
-pub trait Spi {
-    fn get_sercom_spi<'a>(&self, peripherals: &'a mut Peripherals) -> &'a atsamd21g::sercom0::SPI;
 
-    fn wait_for_ready(&self, peripherals: &mut Peripherals) {
-        let sercom_spi = self.get_sercom_spi(peripherals);
+/// The SPI pins of the canonical Arduino Zero pinout, which are on SERCOM1 (peripheral C).
+const ARDUINO_SPI_PINS: SpiPins = SpiPins {
+    data_out: PinId::new(PinBank::PA, 16),
+    clock: PinId::new(PinBank::PA, 17),
+    data_in: PinId::new(PinBank::PA, 19),
+};
 
-        // wait for SPI shift register to be ready for the next byte
-        while sercom_spi.intflag.read().dre().bit_is_clear() {
-        }
-    }
 
-    fn send_data(&self, peripherals: &mut Peripherals, data: &[u8]) {
-        let sercom_spi = self.get_sercom_spi(peripherals);
+/// The SPI bus on SERCOM1, shared by the display in mikroBUS slot 1 and the keypad in slot 2.
+pub type Sercom1Bus = SharedSpiBus<Sercom1DmaSpi>;
+
+
+pub trait Spi {
+    fn get_sercom_spi(&self) -> &atsamd21g::sercom0::SPI;
+
+    fn send_data(&self, data: &[u8]) {
+        let sercom_spi = self.get_sercom_spi();
 
         // wait for SPI shift register to be ready for the next byte
         while sercom_spi.intflag.read().dre().bit_is_clear() {
@@ -37,8 +47,8 @@ pub trait Spi {
         }
     }
 
-    fn exchange_data(&self, peripherals: &mut Peripherals, data: &mut [u8]) {
-        let sercom_spi = self.get_sercom_spi(peripherals);
+    fn exchange_data(&self, data: &mut [u8]) {
+        let sercom_spi = self.get_sercom_spi();
 
         // wait for SPI shift register to be ready for the next byte
         while sercom_spi.intflag.read().dre().bit_is_clear() {
@@ -66,11 +76,50 @@ pub trait Spi {
         while sercom_spi.intflag.read().txc().bit_is_clear() {
         }
     }
-}
 
-pub struct Sercom1Spi;
-impl Spi for Sercom1Spi {
-    fn get_sercom_spi<'a>(&self, peripherals: &'a mut Peripherals) -> &'a atsamd21g::sercom0::SPI {
-        peripherals.SERCOM1.spi()
+    /// Switches to the clock polarity and phase of the given settings and to the fastest clock
+    /// they allow. No transfer may be in progress.
+    ///
+    /// The settings must allow at least [`breakout_common::sercom::spi_min_frequency`], which the
+    /// bus manager checks when it hands out a device.
+    fn apply_config(&self, config: &SpiConfig) {
+        let sercom_spi = self.get_sercom_spi();
+
+        // clock polarity and phase can only be changed while the SERCOM is disabled
+        sercom_spi.ctrla.modify(|_, w| w
+            .enable().clear_bit()
+        );
+        while sercom_spi.syncbusy.read().enable().bit_is_set() {
+        }
+
+        sercom_spi.ctrla.modify(|_, w| w
+            .cpol().bit(config.mode.clock_idles_high())
+            .cpha().bit(config.mode.samples_at_trailing_edge())
+        );
+        // (no synchronization)
+
+        let baud = spi_baud(SERCOM_CLOCK_FREQUENCY, config.max_frequency)
+            .expect("SPI clock too slow");
+        unsafe {
+            sercom_spi.baud.modify(|_, w| w
+                .baud().bits(baud)
+            )
+        };
+        // (no synchronization)
+
+        sercom_spi.ctrla.modify(|_, w| w
+            .enable().set_bit()
+        );
+        while sercom_spi.syncbusy.read().enable().bit_is_set() {
+        }
     }
 }
+
+
+/// Sets up SERCOM1 for SPI and the DMAC for sending to it in the background, and hands the bus
+/// over to the manager from which the devices on it get their handles.
+pub fn set_up(peripherals: &mut Peripherals) -> Sercom1Bus {
+    let spi = set_up_spi(peripherals, Sercom::Sercom1, &ARDUINO_SPI_PINS);
+    crate::dma::set_up(peripherals);
+    SharedSpiBus::new(Sercom1DmaSpi::new(spi))
+}
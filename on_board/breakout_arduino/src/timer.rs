@@ -1,13 +1,19 @@
 use core::ptr::{read_volatile, write_volatile};
 use core::time::Duration;
 
+use breakout_common::instant::{Clock, Instant, MAX_DURATION};
 use cortex_m::Peripherals as CorePeripherals;
 use cortex_m::asm::nop;
-use cortex_m::peripheral::SYST;
+use cortex_m::peripheral::{SCB, SYST};
 
 
 static mut TICK_TIMER: u32 = 0;
 
+/// The time between two increments of the timer value.
+const TICK_INTERVAL: Duration = Duration::from_millis(10);
+
+const TICK_INTERVAL_MICROS: u32 = TICK_INTERVAL.as_micros() as u32;
+
 
 /// Sets up the timer to raise an interrupt every 10 milliseconds.
 pub(crate) fn set_up(core_peripherals: &mut CorePeripherals) {
@@ -23,7 +29,7 @@ pub(crate) fn set_up(core_peripherals: &mut CorePeripherals) {
 pub(crate) fn tick() {
     let current_value = value();
     // UNSAFE: performed in an exception (interrupt) handler; race conditions are unlikely
-    unsafe { write_volatile(&mut TICK_TIMER, current_value + 1) };
+    unsafe { write_volatile(&mut TICK_TIMER, current_value.wrapping_add(1)) };
 }
 
 /// Returns the current timer value.
@@ -33,13 +39,45 @@ pub(crate) fn value() -> u32 {
     unsafe { read_volatile(&TICK_TIMER) }
 }
 
+/// Returns the current instant with microsecond resolution.
+///
+/// The timer value is combined with the progress of the SysTick counter towards the next tick.
+/// Must not be called with interrupts disabled for longer than a tick.
+pub(crate) fn now() -> Instant {
+    loop {
+        let ticks = value();
+        let current = SYST::get_current();
+        let tick_pending = SCB::is_pendst_pending();
+
+        // try again if the tick handler ran or the counter wrapped around in the meantime
+        if value() != ticks || SYST::get_current() > current {
+            continue;
+        }
+
+        // the counter has wrapped around but the tick handler has not run yet
+        let ticks = if tick_pending { ticks.wrapping_add(1) } else { ticks };
+
+        // SysTick counts down from the reload value to zero
+        let reload = SYST::get_reload();
+        return Instant::from_ticks(ticks, TICK_INTERVAL_MICROS, reload - current, reload + 1);
+    }
+}
+
+
+/// The monotonic clock driven by SysTick.
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct SysTickClock;
+impl Clock for SysTickClock {
+    #[inline]
+    fn now(&self) -> Instant { now() }
+}
+
+
 /// Delays for the given duration.
 pub(crate) fn delay(duration: Duration) {
-    let tenms_units: u32 = (duration.as_millis() / 10).try_into().unwrap();
-    let current_timer = value();
-    let target_value = current_timer + tenms_units;
-
-    while value() < target_value {
+    assert!(duration < MAX_DURATION);
+    let start = now();
+    while start.elapsed(&SysTickClock) < duration {
         nop();
     }
 }
@@ -1,3 +1,5 @@
+use core::fmt;
+
 use atsamd21g::Peripherals;
 
 
@@ -21,3 +23,22 @@ pub(crate) fn write(peripherals: &mut Peripherals, buf: &[u8]) {
     while sercom5_usart.intflag.read().txc().bit_is_clear() {
     }
 }
+
+
+/// Allows writing formatted text to the EDBG UART using `write!`.
+pub(crate) struct UartWriter<'a> {
+    peripherals: &'a mut Peripherals,
+}
+impl<'a> UartWriter<'a> {
+    pub fn new(peripherals: &'a mut Peripherals) -> Self {
+        Self {
+            peripherals,
+        }
+    }
+}
+impl<'a> fmt::Write for UartWriter<'a> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        write(self.peripherals, s.as_bytes());
+        Ok(())
+    }
+}
@@ -0,0 +1,414 @@
+//! Running the game without a window, writing frames to image files.
+
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use breakout_common::color::Color;
+use breakout_common::layout::BYTES_PER_PIXEL;
+use breakout_common::playfield::Playfield;
+
+
+/// The file format in which frames are written.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum ImageFormat {
+    Png,
+    Ppm,
+}
+impl ImageFormat {
+    pub const fn extension(&self) -> &'static str {
+        match self {
+            Self::Png => "png",
+            Self::Ppm => "ppm",
+        }
+    }
+}
+impl FromStr for ImageFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "png" => Ok(Self::Png),
+            "ppm" => Ok(Self::Ppm),
+            _ => Err(format!("unknown image format {:?} (expected \"png\" or \"ppm\")", s)),
+        }
+    }
+}
+
+
+/// An input that can be scripted.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum ScriptedInput {
+    Left,
+    Right,
+}
+impl ScriptedInput {
+    pub fn apply(&self, playfield: &mut Playfield) {
+        match self {
+            Self::Left => playfield.move_paddle_left(),
+            Self::Right => playfield.move_paddle_right(),
+        }
+    }
+}
+impl FromStr for ScriptedInput {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "left" => Ok(Self::Left),
+            "right" => Ok(Self::Right),
+            _ => Err(format!("unknown input {:?} (expected \"left\" or \"right\")", s)),
+        }
+    }
+}
+
+
+/// An input that is applied during a range of frames.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct ScriptEntry {
+    pub frames: RangeInclusive<u64>,
+    pub input: ScriptedInput,
+}
+
+
+/// Parses an input script.
+///
+/// Each line contains a frame number and an input, separated by whitespace, e.g. `12 left`.
+/// Optionally, the input may be followed by a repeat count, e.g. `12 left 5` presses left during
+/// frames 12 through 16. Empty lines and lines starting with `#` are ignored.
+///
+/// Returns the entries in the order in which they were given, which is also the order in which
+/// inputs on the same frame are applied.
+pub fn parse_script(script: &str) -> Result<Vec<ScriptEntry>, String> {
+    let mut entries = Vec::new();
+    for (line_index, line) in script.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let pieces: Vec<&str> = line.split_whitespace().collect();
+        if pieces.len() < 2 || pieces.len() > 3 {
+            return Err(format!("line {}: expected \"FRAME INPUT [COUNT]\"", line_index + 1));
+        }
+        let frame: u64 = pieces[0].parse()
+            .map_err(|e| format!("line {}: invalid frame number {:?}: {}", line_index + 1, pieces[0], e))?;
+        let input: ScriptedInput = pieces[1].parse()
+            .map_err(|e| format!("line {}: {}", line_index + 1, e))?;
+        let count: u64 = match pieces.get(2) {
+            Some(count_str) => count_str.parse()
+                .map_err(|e| format!("line {}: invalid count {:?}: {}", line_index + 1, count_str, e))?,
+            None => 1,
+        };
+        if count == 0 {
+            continue;
+        }
+        let last = frame.checked_add(count - 1)
+            .ok_or_else(|| format!("line {}: frame number out of range", line_index + 1))?;
+        entries.push(ScriptEntry { frames: frame..=last, input });
+    }
+    Ok(entries)
+}
+
+
+/// A selection of frame numbers.
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+pub struct FrameSelection {
+    ranges: Vec<RangeInclusive<u64>>,
+}
+impl FrameSelection {
+    pub fn contains(&self, frame: u64) -> bool {
+        self.ranges.iter().any(|range| range.contains(&frame))
+    }
+}
+
+
+/// Parses a selection of frame numbers such as `0,10-12,59`.
+pub fn parse_frame_selection(selection: &str) -> Result<FrameSelection, String> {
+    let mut ranges = Vec::new();
+    for piece in selection.split(',') {
+        let piece = piece.trim();
+        if piece.is_empty() {
+            continue;
+        }
+
+        let parse_frame = |s: &str| -> Result<u64, String> {
+            s.trim().parse()
+                .map_err(|e| format!("invalid frame number {:?}: {}", s, e))
+        };
+        if let Some((first, last)) = piece.split_once('-') {
+            let first = parse_frame(first)?;
+            let last = parse_frame(last)?;
+            if first > last {
+                return Err(format!("invalid frame range {:?}", piece));
+            }
+            ranges.push(first..=last);
+        } else {
+            let frame = parse_frame(piece)?;
+            ranges.push(frame..=frame);
+        }
+    }
+    Ok(FrameSelection { ranges })
+}
+
+
+/// Converts a frame buffer into 8-bit-per-channel RGB data.
+pub fn frame_to_rgb888(frame: &[u8]) -> Vec<u8> {
+    let mut rgb = Vec::with_capacity(frame.len() / BYTES_PER_PIXEL * 3);
+    for pixel in frame.chunks_exact(BYTES_PER_PIXEL) {
+        let color = Color::from_pixel_bytes([pixel[0], pixel[1]]);
+        rgb.extend_from_slice(&[color.r, color.g, color.b]);
+    }
+    rgb
+}
+
+
+/// Writes RGB data as a binary PPM (P6) image.
+pub fn write_ppm<W: Write>(mut writer: W, width: usize, height: usize, rgb: &[u8]) -> io::Result<()> {
+    debug_assert_eq!(rgb.len(), width * height * 3);
+    write!(writer, "P6\n{} {}\n255\n", width, height)?;
+    writer.write_all(rgb)?;
+    writer.flush()
+}
+
+
+/// Writes RGB data as a PNG image.
+pub fn write_png<W: Write>(writer: W, width: usize, height: usize, rgb: &[u8]) -> io::Result<()> {
+    debug_assert_eq!(rgb.len(), width * height * 3);
+    let mut encoder = png::Encoder::new(
+        writer,
+        width.try_into().expect("width too large"),
+        height.try_into().expect("height too large"),
+    );
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut png_writer = encoder.write_header()
+        .map_err(io::Error::other)?;
+    png_writer.write_image_data(rgb)
+        .map_err(io::Error::other)?;
+    png_writer.finish()
+        .map_err(io::Error::other)
+}
+
+
+/// Settings for a headless run.
+pub struct HeadlessRun {
+    /// The number of frames to simulate.
+    pub frame_count: u64,
+
+    /// The inputs to apply.
+    pub inputs: Vec<ScriptEntry>,
+
+    /// The frames to write out; if `None`, all frames are written.
+    pub capture: Option<FrameSelection>,
+
+    pub format: ImageFormat,
+    pub output_dir: PathBuf,
+}
+impl HeadlessRun {
+    fn frame_path(&self, frame: u64) -> PathBuf {
+        self.output_dir.join(format!("frame{:06}.{}", frame, self.format.extension()))
+    }
+
+    fn write_frame(&self, path: &Path, playfield: &Playfield, frame: &[u8]) -> io::Result<()> {
+        let layout = &playfield.layout;
+        let rgb = frame_to_rgb888(frame);
+        let writer = BufWriter::new(File::create(path)?);
+        match self.format {
+            ImageFormat::Png => write_png(writer, layout.display_width, layout.display_height, &rgb),
+            ImageFormat::Ppm => write_ppm(writer, layout.display_width, layout.display_height, &rgb),
+        }
+    }
+
+    /// Steps the playfield through the frames, applying the scripted inputs and writing out the
+    /// selected frames.
+    pub fn run(&self, playfield: &mut Playfield) -> io::Result<()> {
+        std::fs::create_dir_all(&self.output_dir)?;
+        self.render(playfield, |frame_index, playfield, frame| {
+            self.write_frame(&self.frame_path(frame_index), playfield, frame)
+        })
+    }
+
+    /// Steps the playfield through the frames, applying the scripted inputs and passing the
+    /// selected frames to `output`. Each frame is processed in the same order as in the interactive
+    /// loop: first the simulation is advanced, then the inputs are applied, then the frame is
+    /// drawn.
+    pub fn render<F>(&self, playfield: &mut Playfield, mut output: F) -> io::Result<()>
+    where
+        F: FnMut(u64, &Playfield, &[u8]) -> io::Result<()>,
+    {
+        for frame_index in 0..self.frame_count {
+            playfield.advance();
+
+            for entry in &self.inputs {
+                if entry.frames.contains(&frame_index) {
+                    entry.input.apply(playfield);
+                }
+            }
+
+            let selected = self.capture.as_ref()
+                .map(|c| c.contains(frame_index))
+                .unwrap_or(true);
+            if selected {
+                let mut buf = vec![0u8; playfield.layout.display_bytes()];
+                playfield.draw(&mut buf);
+                output(frame_index, playfield, &buf)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use breakout_common::layout::DisplayLayout;
+
+    #[test]
+    fn test_parse_script() {
+        let script = "# move around\n3 left\n\n1 right 2\n  3 RIGHT\n4 left 0\n";
+        assert_eq!(
+            parse_script(script).unwrap(),
+            vec![
+                ScriptEntry { frames: 3..=3, input: ScriptedInput::Left },
+                ScriptEntry { frames: 1..=2, input: ScriptedInput::Right },
+                ScriptEntry { frames: 3..=3, input: ScriptedInput::Right },
+            ],
+        );
+
+        assert!(parse_script("3").is_err());
+        assert!(parse_script("three left").is_err());
+        assert!(parse_script("3 up").is_err());
+        assert!(parse_script("3 left 2 4").is_err());
+    }
+
+    #[test]
+    fn test_parse_script_bounds() {
+        // huge repeat counts do not allocate anything
+        assert_eq!(
+            parse_script("0 left 1000000000000").unwrap(),
+            vec![ScriptEntry { frames: 0..=999_999_999_999, input: ScriptedInput::Left }],
+        );
+        assert_eq!(
+            parse_script("1 right 18446744073709551615").unwrap(),
+            vec![ScriptEntry { frames: 1..=u64::MAX, input: ScriptedInput::Right }],
+        );
+        assert!(parse_script("2 right 18446744073709551615").is_err());
+        assert!(parse_script("18446744073709551615 left 2").is_err());
+    }
+
+    #[test]
+    fn test_parse_frame_selection() {
+        let selection = parse_frame_selection("0, 10-12,59").unwrap();
+        assert_eq!(
+            (0..=60).filter(|&frame| selection.contains(frame)).collect::<Vec<_>>(),
+            vec![0, 10, 11, 12, 59],
+        );
+        assert!(parse_frame_selection("12-10").is_err());
+        assert!(parse_frame_selection("x").is_err());
+
+        // huge ranges do not allocate anything
+        let selection = parse_frame_selection("0-18446744073709551615").unwrap();
+        assert!(selection.contains(0));
+        assert!(selection.contains(u64::MAX));
+    }
+
+    #[test]
+    fn test_frame_to_rgb888() {
+        let frame = [0xF8, 0x00, 0x07, 0xE0, 0x00, 0x1F, 0xFF, 0xFF];
+        assert_eq!(
+            frame_to_rgb888(&frame),
+            vec![
+                0xFF, 0x00, 0x00,
+                0x00, 0xFF, 0x00,
+                0x00, 0x00, 0xFF,
+                0xFF, 0xFF, 0xFF,
+            ],
+        );
+    }
+
+    #[test]
+    fn test_write_ppm() {
+        let mut output = Vec::new();
+        write_ppm(&mut output, 2, 1, &[1, 2, 3, 4, 5, 6]).unwrap();
+        assert_eq!(output, b"P6\n2 1\n255\n\x01\x02\x03\x04\x05\x06");
+    }
+
+    #[test]
+    fn test_write_png() {
+        let mut output = Vec::new();
+        write_png(&mut output, 2, 1, &[1, 2, 3, 4, 5, 6]).unwrap();
+
+        let decoder = png::Decoder::new(output.as_slice());
+        let mut reader = decoder.read_info().unwrap();
+        let mut buf = vec![0u8; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buf).unwrap();
+        assert_eq!((info.width, info.height), (2, 1));
+        assert_eq!(&buf[..info.buffer_size()], &[1, 2, 3, 4, 5, 6]);
+    }
+
+    /// 64-bit FNV-1a, to keep the golden values short.
+    fn fnv1a(bytes: &[u8]) -> u64 {
+        let mut hash = 0xcbf2_9ce4_8422_2325u64;
+        for &b in bytes {
+            hash ^= u64::from(b);
+            hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+        hash
+    }
+
+    /// A directory that is removed when the test is done with it, whether it passes or not.
+    struct TempDir(PathBuf);
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            Self(std::env::temp_dir().join(format!("{}_{}", name, std::process::id())))
+        }
+    }
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// Decodes a PNG file into its RGB pixels.
+    fn read_png(path: &Path) -> Vec<u8> {
+        let decoder = png::Decoder::new(File::open(path).unwrap());
+        let mut reader = decoder.read_info().unwrap();
+        let mut buf = vec![0u8; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buf).unwrap();
+        buf.truncate(info.buffer_size());
+        buf
+    }
+
+    #[test]
+    fn test_headless_run() {
+        let output_dir = TempDir::new("breakout_headless_test");
+        let run = HeadlessRun {
+            frame_count: 40,
+            inputs: parse_script("5 left 10\n20 right 15\n").unwrap(),
+            capture: Some(parse_frame_selection("0,14,39").unwrap()),
+            format: ImageFormat::Png,
+            output_dir: output_dir.0.clone(),
+        };
+        let mut playfield = Playfield::new(DisplayLayout::PSP27801);
+        run.run(&mut playfield).unwrap();
+
+        let mut files: Vec<String> = std::fs::read_dir(&output_dir.0).unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        files.sort();
+        assert_eq!(files, ["frame000000.png", "frame000014.png", "frame000039.png"]);
+
+        // hash the pixels rather than the files, which depend on how the encoder compresses
+        let hashes: Vec<u64> = files.iter()
+            .map(|file| fnv1a(&read_png(&output_dir.0.join(file))))
+            .collect();
+        // golden values; update them only after checking the frames by eye
+        assert_eq!(hashes, [0x1f29_86b8_e34f_a634, 0x48fa_3dce_8f77_89b4, 0x3c75_d643_7051_c48a]);
+    }
+}
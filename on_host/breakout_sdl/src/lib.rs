@@ -0,0 +1,5 @@
+//! The parts of the host build that do not depend on SDL, so that they can be tested without
+//! linking against libSDL2.
+
+
+pub mod headless;
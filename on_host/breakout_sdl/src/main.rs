@@ -1,93 +1,396 @@
+use std::path::{Path, PathBuf};
 use std::thread::sleep;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
-use breakout_common::playfield::{
-    BYTES_PER_PIXEL, DISPLAY_BYTES, DISPLAY_HEIGHT, DISPLAY_WIDTH, Playfield,
+use breakout_common::brightness::Brightness;
+use breakout_common::effects::{Effect, EffectState, Effects};
+use breakout_common::indexed::{IndexDepth, IndexedFrameBuffer};
+use breakout_common::layout::{CONTROLLER_RAM_EXTENT, DisplayLayout, Rect as DisplayRect};
+use breakout_common::orientation::{Orientation, Rotation};
+use breakout_common::playfield::{Playfield, default_palette};
+use breakout_common::settings::Settings;
+use breakout_common::timing::{
+    DEFAULT_MAX_TICKS_PER_FRAME, FixedTimestep, FrameStats, TICKS_PER_SECOND,
 };
+use breakout_sdl::headless::{
+    FrameSelection, HeadlessRun, ImageFormat, parse_frame_selection, parse_script,
+};
+use clap::Parser;
 use sdl2::event::Event;
-use sdl2::keyboard::Keycode;
+use sdl2::keyboard::{Keycode, Mod};
 use sdl2::pixels::{Color, PixelFormatEnum};
 use sdl2::rect::Rect;
-use sdl2::render::{Canvas, TextureAccess};
-use sdl2::video::Window;
+use sdl2::render::{Canvas, Texture};
+use sdl2::video::{FullscreenType, Window};
 
 
-fn render_playfield(playfield: &Playfield, canvas: &mut Canvas<Window>) {
-    let mut buf = [0u8; DISPLAY_BYTES];
-    playfield.draw(&mut buf);
 
-    let texture_creator = canvas.texture_creator();
-    let mut texture = texture_creator.create_texture(
-        Some(PixelFormatEnum::RGB565),
-        TextureAccess::Static,
-        DISPLAY_WIDTH.try_into().unwrap(),
-        DISPLAY_HEIGHT.try_into().unwrap(),
-    )
-        .expect("failed to create texture");
-    texture.update(
-        Some(Rect::new(
-            0,
-            0,
-            u32::try_from(DISPLAY_WIDTH).unwrap(),
-            u32::try_from(DISPLAY_HEIGHT).unwrap(),
-        )),
-        &buf,
-        DISPLAY_WIDTH*BYTES_PER_PIXEL,
-    )
+#[derive(Parser)]
+struct Opts {
+    /// Clockwise rotation of the image on the panel, in degrees (0, 90, 180 or 270).
+    #[arg(long, default_value = "0", value_parser = parse_rotation)]
+    rotate: Rotation,
+
+    /// Mirror the image horizontally (before rotating it).
+    #[arg(long)]
+    mirror: bool,
+
+    /// The initial integer factor by which the display is scaled up in the window. When the window
+    /// is resized, the largest integer factor that fits is used.
+    #[arg(long, default_value_t = 6, value_parser = clap::value_parser!(u32).range(1..))]
+    scale: u32,
+
+    /// The rate at which the simulation advances, in ticks per second.
+    #[arg(long, default_value_t = TICKS_PER_SECOND, value_parser = clap::value_parser!(u32).range(1..))]
+    rate: u32,
+
+    /// Synchronize rendering with the vertical refresh of the monitor instead of sleeping between
+    /// frames.
+    #[arg(long)]
+    vsync: bool,
+
+    /// Start in fullscreen mode. Fullscreen mode can be toggled using F11 or Alt+Enter.
+    #[arg(long)]
+    fullscreen: bool,
+
+    /// A file in which settings such as the brightness are stored. It is read at startup, if it
+    /// exists, and written whenever a setting changes. The brightness can be changed using the
+    /// minus and plus keys.
+    #[arg(long)]
+    settings: Option<PathBuf>,
+
+    /// Let F1, F2 and F3 trigger the flash, shake and wipe effects, respectively.
+    #[arg(long)]
+    effect_keys: bool,
+
+    /// Run without a window, writing frames to image files instead.
+    #[arg(long)]
+    headless: bool,
+
+    /// In headless mode, the number of frames to simulate.
+    #[arg(long, default_value_t = 600, requires = "headless")]
+    frames: u64,
+
+    /// In headless mode, a file containing scripted inputs. Each line has the format
+    /// "FRAME left|right [COUNT]".
+    #[arg(long, requires = "headless")]
+    script: Option<PathBuf>,
+
+    /// In headless mode, the frames to write out, e.g. "0,10-12,59". By default, all frames are
+    /// written.
+    #[arg(long, value_parser = parse_frame_selection, requires = "headless")]
+    capture: Option<FrameSelection>,
+
+    /// In headless mode, the format of the image files ("png" or "ppm").
+    #[arg(long, default_value = "png", requires = "headless")]
+    format: ImageFormat,
+
+    /// In headless mode, the directory into which the image files are written.
+    #[arg(long, default_value = "frames", requires = "headless")]
+    output_dir: PathBuf,
+}
+
+
+fn parse_rotation(s: &str) -> Result<Rotation, String> {
+    let degrees: u16 = s.parse()
+        .map_err(|e| format!("invalid rotation {:?}: {}", s, e))?;
+    Rotation::from_degrees(degrees)
+        .ok_or_else(|| format!("rotation must be a multiple of 90 degrees, not {}", degrees))
+}
+
+
+fn load_settings(path: &Path) -> Settings {
+    match std::fs::read(path) {
+        Ok(bytes) => Settings::from_bytes(&bytes)
+            .unwrap_or_else(|e| {
+                eprintln!("ignoring invalid settings in {}: {}", path.display(), e);
+                Settings::default()
+            }),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Settings::default(),
+        Err(e) => panic!("failed to read settings from {}: {}", path.display(), e),
+    }
+}
+
+
+fn render_playfield(
+    playfield: &Playfield,
+    brightness: Brightness,
+    effect_state: EffectState,
+    canvas: &mut Canvas<Window>,
+    texture: &mut Texture,
+) {
+    let layout = &playfield.layout;
+    let (width, height) = (layout.display_width, layout.display_height);
+    let depth = IndexDepth::Bits4;
+    let mut frame = IndexedFrameBuffer::new(
+        vec![0u8; depth.frame_bytes(width, height)], width, height, depth,
+    );
+    playfield.draw_indexed(&mut frame);
+
+    // the display inverts the colors, then dims them by lowering its current; do the same
+    let mut palette = default_palette();
+    for index in 0..=u8::try_from(depth.color_count() - 1).unwrap() {
+        let mut color = palette.color(index);
+        if effect_state.inverted {
+            color = color.inverted();
+        }
+        palette.set_color(index, brightness.scale_color(color));
+    }
+
+    let mut row = vec![0u8; layout.row_bytes()];
+    texture.with_lock(None, |pixels, pitch| {
+        for (y, texture_row) in pixels.chunks_mut(pitch).take(height).enumerate() {
+            // expanding yields big-endian pixels (as sent to the display), SDL expects native
+            // endianness
+            frame.expand_area(DisplayRect::new(0, y, width, 1), &palette, &mut row);
+            for (frame_pixel, texture_pixel) in row.chunks_exact(2).zip(texture_row.chunks_exact_mut(2)) {
+                let value = u16::from_be_bytes([frame_pixel[0], frame_pixel[1]]);
+                texture_pixel.copy_from_slice(&value.to_ne_bytes());
+            }
+        }
+    })
         .expect("failed to render into texture");
-    canvas.copy(&texture, None, None)
-        .expect("failed to copy texture");
+
+    // show the image on the panel as the display controller would, rotating and mirroring it
+    // (SDL mirrors first, then rotates around the center of the target rectangle); coordinates
+    // are in panel pixels, SDL takes care of scaling and letterboxing
+    let panel_width: u32 = layout.panel_width().try_into().unwrap();
+    let panel_height: u32 = layout.panel_height().try_into().unwrap();
+    let orientation = layout.orientation;
+    let mut target = if orientation.swaps_axes() {
+        let (width, height) = (panel_height, panel_width);
+        Rect::new(
+            (i64::from(panel_width) - i64::from(width)) as i32 / 2,
+            (i64::from(panel_height) - i64::from(height)) as i32 / 2,
+            width,
+            height,
+        )
+    } else {
+        Rect::new(0, 0, panel_width, panel_height)
+    };
+
+    // the display offset moves the image up, scrolling moves it along the RAM columns, wrapping
+    // around at the end of the RAM
+    target.offset(0, -i32::from(effect_state.vertical_shift));
+    let mut targets = vec![target];
+    if let Some(scrolled_columns) = effect_state.scrolled_columns {
+        let scrolled_columns: i32 = scrolled_columns.try_into().unwrap();
+        let ram_extent: i32 = CONTROLLER_RAM_EXTENT.try_into().unwrap();
+        target.offset(scrolled_columns, 0);
+        let mut wrapped = target;
+        wrapped.offset(-ram_extent, 0);
+        targets = vec![target, wrapped];
+    }
+
+    for target in targets {
+        canvas.copy_ex(
+            texture,
+            None,
+            target,
+            orientation.rotation.as_degrees().into(),
+            None,
+            orientation.mirror,
+            false,
+        )
+            .expect("failed to copy texture");
+    }
 }
 
 
-fn main() {
+fn toggle_fullscreen(canvas: &mut Canvas<Window>) {
+    let window = canvas.window_mut();
+    let new_state = match window.fullscreen_state() {
+        FullscreenType::Off => FullscreenType::Desktop,
+        FullscreenType::Desktop | FullscreenType::True => FullscreenType::Off,
+    };
+    window.set_fullscreen(new_state)
+        .expect("failed to toggle fullscreen mode");
+}
+
+
+fn run_headless(opts: &Opts, playfield: &mut Playfield) {
+    let inputs = match &opts.script {
+        Some(script_path) => {
+            let script = std::fs::read_to_string(script_path)
+                .expect("failed to read input script");
+            parse_script(&script)
+                .expect("failed to parse input script")
+        },
+        None => Vec::new(),
+    };
+
+    let run = HeadlessRun {
+        frame_count: opts.frames,
+        inputs,
+        capture: opts.capture.clone(),
+        format: opts.format,
+        output_dir: opts.output_dir.clone(),
+    };
+    run.run(playfield)
+        .expect("failed to write frames");
+}
+
+
+fn run_windowed(opts: &Opts, playfield: &mut Playfield) {
+    let panel_width: u32 = playfield.layout.panel_width().try_into().unwrap();
+    let panel_height: u32 = playfield.layout.panel_height().try_into().unwrap();
+
     let sdl_context = sdl2::init().unwrap();
     let video_subsystem = sdl_context.video().unwrap();
 
-    let window = video_subsystem.window("breakout", 800, 600)
+    let mut window_builder = video_subsystem.window(
+        "breakout",
+        panel_width * opts.scale,
+        panel_height * opts.scale,
+    );
+    window_builder
         .position_centered()
+        .resizable();
+    if opts.fullscreen {
+        window_builder.fullscreen_desktop();
+    }
+    let window = window_builder
         .build()
         .unwrap();
 
-    let mut canvas = window.into_canvas().build().unwrap();
+    let mut canvas_builder = window.into_canvas();
+    if opts.vsync {
+        canvas_builder = canvas_builder.present_vsync();
+    }
+    let mut canvas = canvas_builder.build().unwrap();
     let mut event_pump = sdl_context.event_pump().unwrap();
 
+    // scale by integer factors only, letterboxing the rest of the window
+    canvas.set_logical_size(panel_width, panel_height)
+        .expect("failed to set logical size");
+    canvas.set_integer_scale(true)
+        .expect("failed to enable integer scaling");
     canvas.set_draw_color(Color::RGB(0, 0, 0));
 
-    let mut playfield = Playfield::new();
+    let texture_creator = canvas.texture_creator();
+    let mut texture = texture_creator.create_texture_streaming(
+        Some(PixelFormatEnum::RGB565),
+        playfield.layout.display_width.try_into().unwrap(),
+        playfield.layout.display_height.try_into().unwrap(),
+    )
+        .expect("failed to create texture");
+
+    let mut effects = Effects::new();
+    let mut settings = match &opts.settings {
+        Some(path) => load_settings(path),
+        None => Settings::default(),
+    };
+
+    let mut timestep = FixedTimestep::from_rate(opts.rate, DEFAULT_MAX_TICKS_PER_FRAME);
+    let mut stats = FrameStats::new();
+    let mut previous_frame = Instant::now();
 
     'main_loop: loop {
-        playfield.advance();
+        let now = Instant::now();
+        let frame_time = now - previous_frame;
+        previous_frame = now;
 
-        canvas.clear();
+        let previous_settings = settings;
         for event in event_pump.poll_iter() {
             match event {
                 Event::Quit { .. } => {
                     break 'main_loop;
                 },
-                Event::KeyDown { keycode: keycode_opt, .. } => {
-                    if let Some(keycode) = keycode_opt {
-                        match keycode {
-                            Keycode::Escape => {
-                                break 'main_loop;
-                            },
-                            Keycode::Left => {
-                                playfield.move_paddle_left();
-                            },
-                            Keycode::Right => {
-                                playfield.move_paddle_right();
-                            },
-                            _ => {},
-                        }
+                Event::KeyDown { keycode: Some(keycode), keymod, .. } => {
+                    match keycode {
+                        Keycode::Escape => {
+                            break 'main_loop;
+                        },
+                        Keycode::F11 => {
+                            toggle_fullscreen(&mut canvas);
+                        },
+                        Keycode::Return if keymod.intersects(Mod::LALTMOD | Mod::RALTMOD) => {
+                            toggle_fullscreen(&mut canvas);
+                        },
+                        Keycode::Left => {
+                            playfield.move_paddle_left();
+                        },
+                        Keycode::Right => {
+                            playfield.move_paddle_right();
+                        },
+                        Keycode::F1 if opts.effect_keys => {
+                            effects.start(Effect::Flash);
+                        },
+                        Keycode::F2 if opts.effect_keys => {
+                            effects.start(Effect::Shake);
+                        },
+                        Keycode::F3 if opts.effect_keys => {
+                            effects.start(Effect::Wipe);
+                        },
+                        Keycode::Minus | Keycode::KpMinus => {
+                            settings.brightness = settings.brightness.dimmer();
+                        },
+                        Keycode::Equals | Keycode::Plus | Keycode::KpPlus => {
+                            settings.brightness = settings.brightness.brighter();
+                        },
+                        _ => {},
                     }
                 },
                 _ => {}
             }
         }
+        if settings != previous_settings {
+            if let Some(path) = &opts.settings {
+                std::fs::write(path, settings.to_bytes())
+                    .expect("failed to write settings");
+            }
+        }
 
-        render_playfield(&playfield, &mut canvas);
+        let ticks = timestep.advance(frame_time);
+        for _ in 0..ticks {
+            playfield.advance();
+        }
 
+        // there is no display controller to send the commands to; the effects are rendered from
+        // their state instead
+        effects.advance(0);
+
+        canvas.clear();
+        render_playfield(
+            playfield, settings.brightness, effects.state(), &mut canvas, &mut texture,
+        );
         canvas.present();
-        sleep(Duration::new(0, 1_000_000_000u32 / 60));
+
+        stats.record_frame(frame_time);
+        stats.record_skipped(ticks.saturating_sub(1));
+        if stats.total_time() >= Duration::from_secs(1) {
+            let title = format!(
+                "breakout - {}.{:02} fps, frame time {:.1}/{:.1}/{:.1} ms, {} skipped",
+                stats.centi_fps() / 100,
+                stats.centi_fps() % 100,
+                stats.min_frame_time().as_secs_f64() * 1000.0,
+                stats.average_frame_time().as_secs_f64() * 1000.0,
+                stats.max_frame_time().as_secs_f64() * 1000.0,
+                stats.skipped_count(),
+            );
+            canvas.window_mut().set_title(&title)
+                .expect("failed to set window title");
+            stats.reset();
+        }
+
+        if !opts.vsync {
+            // wait until the next simulation tick is due
+            sleep(timestep.time_until_next_tick());
+        }
+    }
+}
+
+
+fn main() {
+    let opts = Opts::parse();
+    let layout = DisplayLayout::PSP27801
+        .with_orientation(Orientation::new(opts.rotate, opts.mirror));
+
+    let mut playfield = Playfield::new(layout);
+
+    if opts.headless {
+        run_headless(&opts, &mut playfield);
+    } else {
+        run_windowed(&opts, &mut playfield);
     }
 }
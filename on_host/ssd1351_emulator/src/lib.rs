@@ -0,0 +1,895 @@
+//! An emulator of the SSD1351 OLED display controller.
+//!
+//! The emulator consumes the same byte stream that the display driver sends over SPI, with the
+//! level of the D/~C pin distinguishing command bytes from data bytes (command parameters are sent
+//! as data, as on the real controller). It models the display RAM and the commands that affect the
+//! image, and can render what the panel would show.
+//!
+//! The following aspects are modeled:
+//!
+//! * the column and row address window and the auto-incrementing address pointer while writing RAM
+//!
+//! * the remap and color depth settings (`0xA0`): address increment direction, column address
+//!   remapping, color sequence swapping, COM scan direction and 65k/262k color depth
+//!
+//! * the display start line (`0xA1`), the display offset (`0xA2`) and the multiplex ratio (`0xCA`)
+//!
+//! * the display modes (all off, all on, regular, inverted) and sleep mode (display off/on)
+//!
+//! * the color contrast (`0xC1`) and master contrast (`0xC7`) settings
+//!
+//! * the command lock (`0xFD`), including the lock on advanced commands
+//!
+//! The grayscale lookup table, scrolling, odd/even COM splitting, reading RAM and the analog
+//! settings (phase lengths, precharge voltages etc.) are accepted but have no effect; the
+//! parameters last sent with each command can be inspected, though.
+
+
+use std::collections::BTreeMap;
+
+use breakout_common::color::Color;
+use breakout_common::display::DisplayInterface;
+use breakout_common::ssd1351::{DisplayCommand, MAX_PARAMETER_COUNT};
+
+
+/// The number of columns and rows in the display RAM, as well as the size of the rendered image.
+pub const RAM_EXTENT: usize = 128;
+
+/// The highest value of a color channel in the display RAM.
+pub const MAX_LEVEL: u8 = 0b11_1111;
+
+/// The commands that are inaccessible while advanced commands are locked.
+const ADVANCED_COMMANDS: [u8; 6] = [0xA2, 0xB1, 0xB3, 0xBB, 0xBE, 0xC1];
+
+
+/// How the display shows the contents of its RAM.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum DisplayMode {
+    /// All pixels are off.
+    AllOff,
+
+    /// All pixels are at the highest level.
+    AllOn,
+
+    /// The contents of the RAM are shown.
+    Regular,
+
+    /// The contents of the RAM are shown with each channel inverted.
+    Inverted,
+}
+
+
+/// What the command decoder does with incoming data bytes.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+enum DecoderState {
+    /// Data bytes are ignored.
+    Ignoring,
+
+    /// Data bytes are collected as parameters of the given command.
+    Parameters { command: u8, expected: usize },
+
+    /// Data bytes are written into the display RAM.
+    WritingRam,
+}
+
+
+/// An emulated SSD1351 controller.
+#[derive(Clone, Debug)]
+pub struct Ssd1351 {
+    /// The display RAM, indexed by row and column, with 6 bits per channel.
+    ram: Vec<[u8; 3]>,
+
+    state: DecoderState,
+    parameters: Vec<u8>,
+    pending_pixel: Vec<u8>,
+
+    column_start: u8,
+    column_end: u8,
+    row_start: u8,
+    row_end: u8,
+    column: u8,
+    row: u8,
+
+    remap: u8,
+    start_line: u8,
+    display_offset: u8,
+    mux_ratio: u8,
+    display_mode: DisplayMode,
+    display_on: bool,
+    color_contrast: [u8; 3],
+    master_contrast: u8,
+    commands_locked: bool,
+    advanced_commands_locked: bool,
+
+    /// The parameters of the last execution of each command.
+    last_parameters: BTreeMap<u8, Vec<u8>>,
+}
+impl Ssd1351 {
+    /// Creates a controller in its power-on reset state.
+    pub fn new() -> Self {
+        Self {
+            ram: vec![[0, 0, 0]; RAM_EXTENT * RAM_EXTENT],
+
+            state: DecoderState::Ignoring,
+            parameters: Vec::with_capacity(MAX_PARAMETER_COUNT),
+            pending_pixel: Vec::with_capacity(3),
+
+            column_start: 0,
+            column_end: 127,
+            row_start: 0,
+            row_end: 127,
+            column: 0,
+            row: 0,
+
+            remap: 0x40,
+            start_line: 0x00,
+            display_offset: 0x60,
+            mux_ratio: 127,
+            display_mode: DisplayMode::Regular,
+            display_on: false,
+            color_contrast: [0x8A, 0x51, 0x8A],
+            master_contrast: 0x0F,
+            commands_locked: false,
+            advanced_commands_locked: true,
+
+            last_parameters: BTreeMap::new(),
+        }
+    }
+
+    /// Processes bytes sent to the controller. If `data` is `false` (D/~C pulled low), the bytes
+    /// are commands; otherwise, they are command parameters or pixel data.
+    pub fn write(&mut self, data: bool, bytes: &[u8]) {
+        for &b in bytes {
+            if data {
+                self.write_data_byte(b);
+            } else {
+                self.write_command_byte(b);
+            }
+        }
+    }
+
+    /// Sends a command followed by its parameters, as the display driver does.
+    pub fn command(&mut self, command: u8, parameters: &[u8]) {
+        self.write(false, &[command]);
+        self.write(true, parameters);
+    }
+
+    fn write_command_byte(&mut self, command: u8) {
+        // a new command aborts any incomplete parameter list and any partial pixel
+        self.parameters.clear();
+        self.pending_pixel.clear();
+
+        let locked = (self.commands_locked && command != 0xFD)
+            || (self.advanced_commands_locked && ADVANCED_COMMANDS.contains(&command));
+        self.state = match DisplayCommand::parameter_count(command) {
+            Some(_) if locked => DecoderState::Ignoring,
+            Some(0) => {
+                self.execute(command);
+                if command == 0x5C {
+                    DecoderState::WritingRam
+                } else {
+                    DecoderState::Ignoring
+                }
+            },
+            Some(expected) => DecoderState::Parameters { command, expected },
+            None => DecoderState::Ignoring,
+        };
+    }
+
+    fn write_data_byte(&mut self, b: u8) {
+        match self.state {
+            DecoderState::Ignoring => {},
+            DecoderState::Parameters { command, expected } => {
+                self.parameters.push(b);
+                if self.parameters.len() == expected {
+                    self.execute(command);
+                    self.parameters.clear();
+                    self.state = DecoderState::Ignoring;
+                }
+            },
+            DecoderState::WritingRam => {
+                if self.remap >> 6 == 0b11 {
+                    // 262k colors in 16-bit format 2 require the 16-bit parallel interface
+                    return;
+                }
+                self.pending_pixel.push(b);
+                if let Some(pixel) = self.decode_pixel() {
+                    self.pending_pixel.clear();
+                    self.store_pixel(pixel);
+                }
+            },
+        }
+    }
+
+    /// Executes a command whose parameters have been collected.
+    fn execute(&mut self, command: u8) {
+        self.last_parameters.insert(command, self.parameters.clone());
+        let p = &self.parameters;
+        match command {
+            0x15 => {
+                let (start, end) = (p[0] & 0x7F, p[1] & 0x7F);
+                if start <= end {
+                    self.column_start = start;
+                    self.column_end = end;
+                    self.column = start;
+                }
+            },
+            0x75 => {
+                let (start, end) = (p[0] & 0x7F, p[1] & 0x7F);
+                if start <= end {
+                    self.row_start = start;
+                    self.row_end = end;
+                    self.row = start;
+                }
+            },
+            0xA0 => self.remap = p[0],
+            0xA1 => self.start_line = p[0] & 0x7F,
+            0xA2 => self.display_offset = p[0] & 0x7F,
+            0xA4 => self.display_mode = DisplayMode::AllOff,
+            0xA5 => self.display_mode = DisplayMode::AllOn,
+            0xA6 => self.display_mode = DisplayMode::Regular,
+            0xA7 => self.display_mode = DisplayMode::Inverted,
+            0xAE => self.display_on = false,
+            0xAF => self.display_on = true,
+            0xC1 => self.color_contrast = [p[0], p[1], p[2]],
+            0xC7 => self.master_contrast = p[0] & 0x0F,
+            0xCA => {
+                let ratio = p[0] & 0x7F;
+                if ratio >= 15 {
+                    self.mux_ratio = ratio;
+                }
+            },
+            0xFD => match p[0] {
+                0x12 => self.commands_locked = false,
+                0x16 => self.commands_locked = true,
+                0xB0 => self.advanced_commands_locked = true,
+                0xB1 => self.advanced_commands_locked = false,
+                _ => {},
+            },
+            _ => {
+                // accepted but not modeled
+            },
+        }
+    }
+
+    /// Decodes the pending pixel bytes if enough of them have been received.
+    fn decode_pixel(&self) -> Option<[u8; 3]> {
+        match self.remap >> 6 {
+            0b00 | 0b01 => {
+                // 65k colors: R5:G6:B5 in two bytes, most significant byte first
+                if self.pending_pixel.len() < 2 {
+                    return None;
+                }
+                let value = u16::from_be_bytes([self.pending_pixel[0], self.pending_pixel[1]]);
+                let r5 = ((value >> 11) & 0b1_1111) as u8;
+                let g6 = ((value >> 5) & 0b11_1111) as u8;
+                let b5 = (value & 0b1_1111) as u8;
+                Some([(r5 << 1) | (r5 >> 4), g6, (b5 << 1) | (b5 >> 4)])
+            },
+            0b10 => {
+                // 262k colors: one byte per channel, 6 bits each
+                if self.pending_pixel.len() < 3 {
+                    return None;
+                }
+                Some([
+                    self.pending_pixel[0] & MAX_LEVEL,
+                    self.pending_pixel[1] & MAX_LEVEL,
+                    self.pending_pixel[2] & MAX_LEVEL,
+                ])
+            },
+            _ => None,
+        }
+    }
+
+    fn store_pixel(&mut self, mut pixel: [u8; 3]) {
+        if self.swap_color_sequence() {
+            pixel.swap(0, 2);
+        }
+        self.ram[usize::from(self.row) * RAM_EXTENT + usize::from(self.column)] = pixel;
+
+        if self.vertical_increment() {
+            if self.row < self.row_end {
+                self.row += 1;
+            } else {
+                self.row = self.row_start;
+                self.column = if self.column < self.column_end {
+                    self.column + 1
+                } else {
+                    self.column_start
+                };
+            }
+        } else if self.column < self.column_end {
+            self.column += 1;
+        } else {
+            self.column = self.column_start;
+            self.row = if self.row < self.row_end { self.row + 1 } else { self.row_start };
+        }
+    }
+
+    #[inline]
+    pub fn vertical_increment(&self) -> bool { self.remap & (1 << 0) != 0 }
+
+    #[inline]
+    pub fn reverse_columns(&self) -> bool { self.remap & (1 << 1) != 0 }
+
+    #[inline]
+    pub fn swap_color_sequence(&self) -> bool { self.remap & (1 << 2) != 0 }
+
+    #[inline]
+    pub fn backward_scan(&self) -> bool { self.remap & (1 << 4) != 0 }
+
+    /// The first and last column of the address window.
+    #[inline]
+    pub fn column_window(&self) -> (u8, u8) { (self.column_start, self.column_end) }
+
+    /// The first and last row of the address window.
+    #[inline]
+    pub fn row_window(&self) -> (u8, u8) { (self.row_start, self.row_end) }
+
+    /// The column and row to which the next pixel will be written.
+    #[inline]
+    pub fn address(&self) -> (u8, u8) { (self.column, self.row) }
+
+    #[inline]
+    pub fn start_line(&self) -> u8 { self.start_line }
+
+    #[inline]
+    pub fn display_offset(&self) -> u8 { self.display_offset }
+
+    #[inline]
+    pub fn mux_ratio(&self) -> u8 { self.mux_ratio }
+
+    #[inline]
+    pub fn display_mode(&self) -> DisplayMode { self.display_mode }
+
+    #[inline]
+    pub fn is_display_on(&self) -> bool { self.display_on }
+
+    #[inline]
+    pub fn color_contrast(&self) -> [u8; 3] { self.color_contrast }
+
+    #[inline]
+    pub fn master_contrast(&self) -> u8 { self.master_contrast }
+
+    /// The parameters with which the given command was last executed, or `None` if it has not been
+    /// executed since reset; commands ignored due to the command lock do not count.
+    pub fn last_parameters(&self, command: u8) -> Option<&[u8]> {
+        self.last_parameters.get(&command).map(Vec::as_slice)
+    }
+
+    /// The channel levels (6 bits each) stored in the display RAM at the given position.
+    #[inline]
+    pub fn ram_levels(&self, column: usize, row: usize) -> [u8; 3] {
+        self.ram[row * RAM_EXTENT + column]
+    }
+
+    /// The color stored in the display RAM at the given position, ignoring display modes and
+    /// contrast.
+    pub fn ram_color(&self, column: usize, row: usize) -> Color {
+        let [r, g, b] = self.ram_levels(column, row);
+        Color::new(expand_level(r), expand_level(g), expand_level(b))
+    }
+
+    /// Renders the image shown by the panel.
+    ///
+    /// The image is 128x128 pixels in row-major order. Each pixel corresponds to a segment (column)
+    /// and COM line (row) of the controller, regardless of which of them are actually connected to
+    /// the panel.
+    pub fn render(&self) -> Vec<Color> {
+        let mut image = vec![Color::BLACK; RAM_EXTENT * RAM_EXTENT];
+        if !self.display_on {
+            return image;
+        }
+
+        for (y, image_row) in image.chunks_exact_mut(RAM_EXTENT).enumerate() {
+            let com = if self.backward_scan() { RAM_EXTENT - 1 - y } else { y };
+            let display_row = (com + usize::from(self.display_offset)) % RAM_EXTENT;
+            if display_row > usize::from(self.mux_ratio) {
+                continue;
+            }
+            let ram_row = (display_row + usize::from(self.start_line)) % RAM_EXTENT;
+
+            for (x, pixel) in image_row.iter_mut().enumerate() {
+                let ram_column = if self.reverse_columns() { RAM_EXTENT - 1 - x } else { x };
+                let levels = match self.display_mode {
+                    DisplayMode::AllOff => [0; 3],
+                    DisplayMode::AllOn => [MAX_LEVEL; 3],
+                    DisplayMode::Regular => self.ram_levels(ram_column, ram_row),
+                    DisplayMode::Inverted => self.ram_levels(ram_column, ram_row)
+                        .map(|level| MAX_LEVEL - level),
+                };
+                *pixel = Color::new(
+                    self.apply_contrast(levels[0], 0),
+                    self.apply_contrast(levels[1], 1),
+                    self.apply_contrast(levels[2], 2),
+                );
+            }
+        }
+        image
+    }
+
+    /// Scales a channel level by the contrast settings; full contrast yields full brightness.
+    fn apply_contrast(&self, level: u8, channel: usize) -> u8 {
+        let scaled = u32::from(expand_level(level))
+            * u32::from(self.color_contrast[channel])
+            * (u32::from(self.master_contrast) + 1)
+            / (255 * 16);
+        scaled as u8
+    }
+}
+impl Default for Ssd1351 {
+    fn default() -> Self { Self::new() }
+}
+impl DisplayInterface for Ssd1351 {
+    fn send(&mut self, command: Option<u8>, data: &[u8]) {
+        if let Some(cmd) = command {
+            self.write(false, &[cmd]);
+        }
+        self.write(true, data);
+    }
+
+    /// Reading from the display is not modeled; the buffer is filled with zeroes.
+    fn receive(&mut self, command: Option<u8>, buffer: &mut [u8]) {
+        if let Some(cmd) = command {
+            self.write(false, &[cmd]);
+        }
+        buffer.fill(0x00);
+    }
+}
+
+
+/// Expands a 6-bit channel level to 8 bits by replicating its most significant bits.
+#[inline]
+const fn expand_level(level: u8) -> u8 {
+    (level << 2) | (level >> 4)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use breakout_common::brightness::Brightness;
+    use breakout_common::burn_in::{BurnInConfig, BurnInGuard};
+    use breakout_common::controller::Ssd1351Controller;
+    use breakout_common::display::{initialize, update_area};
+    use breakout_common::display_config::DisplayConfig;
+    use breakout_common::effects::{Effect, Effects};
+    use breakout_common::grayscale::GAMMA_2_2;
+    use breakout_common::layout::{BYTES_PER_PIXEL, DisplayLayout};
+    use breakout_common::orientation::{Orientation, Rotation};
+    use breakout_common::indexed::{IndexDepth, IndexedFrameBuffer};
+    use breakout_common::playfield::{Playfield, default_palette};
+
+    use super::*;
+
+    /// The panel settings used when the rendered image is compared against the frame buffer.
+    const TEST_CONFIG: DisplayConfig = DisplayConfig::PSP27801.with_color_contrast([0xFF; 3]);
+
+    /// Unlocks all commands and sets full contrast without any offset.
+    fn prepare(controller: &mut Ssd1351) {
+        controller.command(0xFD, &[0x12]);
+        controller.command(0xFD, &[0xB1]);
+        controller.command(0xA2, &[0x00]);
+        controller.command(0xC1, &[0xFF, 0xFF, 0xFF]);
+        controller.command(0xC7, &[0x0F]);
+        controller.command(0xAF, &[]);
+    }
+
+    #[test]
+    fn test_address_window() {
+        let mut controller = Ssd1351::new();
+        controller.command(0x15, &[10, 12]);
+        controller.command(0x75, &[20, 21]);
+        controller.command(0x5C, &[]);
+        for i in 0..7u8 {
+            controller.write(true, &[0x00, i]);
+        }
+        // 3x2 window: the seventh pixel wraps around to the start
+        assert_eq!(controller.ram_levels(10, 20), [0, 0, 12]);
+        assert_eq!(controller.ram_levels(11, 20), [0, 0, 2]);
+        assert_eq!(controller.ram_levels(12, 20), [0, 0, 4]);
+        assert_eq!(controller.ram_levels(10, 21), [0, 0, 6]);
+        assert_eq!(controller.ram_levels(12, 21), [0, 0, 10]);
+        assert_eq!(controller.address(), (11, 20));
+
+        // vertical increment
+        controller.command(0xA0, &[0x41]);
+        controller.command(0x15, &[10, 12]);
+        controller.command(0x75, &[20, 21]);
+        controller.command(0x5C, &[]);
+        controller.write(true, &[0xF8, 0x00, 0x07, 0xE0, 0x00, 0x1F]);
+        assert_eq!(controller.ram_color(10, 20), Color::new(0xFF, 0x00, 0x00));
+        assert_eq!(controller.ram_color(10, 21), Color::new(0x00, 0xFF, 0x00));
+        assert_eq!(controller.ram_color(11, 20), Color::new(0x00, 0x00, 0xFF));
+    }
+
+    #[test]
+    fn test_color_formats() {
+        let mut controller = Ssd1351::new();
+
+        // swapped color sequence
+        controller.command(0xA0, &[0x44]);
+        controller.command(0x5C, &[]);
+        controller.write(true, &[0xF8, 0x00]);
+        assert_eq!(controller.ram_levels(0, 0), [0, 0, MAX_LEVEL]);
+
+        // 262k colors
+        controller.command(0xA0, &[0x80]);
+        controller.command(0x5C, &[]);
+        controller.write(true, &[0x3F, 0x20, 0xC1]);
+        assert_eq!(controller.ram_levels(1, 0), [0x3F, 0x20, 0x01]);
+
+        // a partial pixel is discarded by the next command
+        controller.command(0x5C, &[]);
+        controller.write(true, &[0x3F]);
+        controller.command(0x5C, &[]);
+        controller.write(true, &[0x01, 0x02, 0x03]);
+        assert_eq!(controller.ram_levels(2, 0), [0x01, 0x02, 0x03]);
+    }
+
+    #[test]
+    fn test_command_lock() {
+        let mut controller = Ssd1351::new();
+
+        // advanced commands are locked after reset
+        controller.command(0xA2, &[0x00]);
+        assert_eq!(controller.display_offset(), 0x60);
+        controller.command(0xFD, &[0xB1]);
+        controller.command(0xA2, &[0x00]);
+        assert_eq!(controller.display_offset(), 0x00);
+
+        // a locked controller ignores everything but unlocking
+        controller.command(0xFD, &[0x16]);
+        controller.command(0xAF, &[]);
+        controller.command(0xA1, &[0x05]);
+        assert!(!controller.is_display_on());
+        assert_eq!(controller.start_line(), 0);
+        controller.command(0xFD, &[0x12]);
+        controller.command(0xAF, &[]);
+        assert!(controller.is_display_on());
+    }
+
+    #[test]
+    fn test_display_modes() {
+        let mut controller = Ssd1351::new();
+        assert!(controller.render().iter().all(|&p| p == Color::BLACK));
+
+        prepare(&mut controller);
+        controller.command(0x5C, &[]);
+        controller.write(true, &[0xFF, 0xFF]);
+        let image = controller.render();
+        assert_eq!(image[0], Color::WHITE);
+        assert_eq!(image[1], Color::BLACK);
+
+        controller.command(0xA7, &[]);
+        let image = controller.render();
+        assert_eq!(image[0], Color::BLACK);
+        assert_eq!(image[1], Color::WHITE);
+
+        controller.command(0xA5, &[]);
+        assert!(controller.render().iter().all(|&p| p == Color::WHITE));
+        controller.command(0xA4, &[]);
+        assert!(controller.render().iter().all(|&p| p == Color::BLACK));
+
+        controller.command(0xA6, &[]);
+        controller.command(0xAE, &[]);
+        assert!(controller.render().iter().all(|&p| p == Color::BLACK));
+    }
+
+    #[test]
+    fn test_contrast() {
+        let mut controller = Ssd1351::new();
+        prepare(&mut controller);
+        controller.command(0xA5, &[]);
+
+        controller.command(0xC7, &[0x07]);
+        assert_eq!(controller.render()[0], Color::new(0x7F, 0x7F, 0x7F));
+
+        controller.command(0xC7, &[0x0F]);
+        controller.command(0xC1, &[0xFF, 0x00, 0x80]);
+        assert_eq!(controller.render()[0], Color::new(0xFF, 0x00, 0x80));
+    }
+
+    #[test]
+    fn test_start_line_offset_and_mux() {
+        let mut controller = Ssd1351::new();
+        prepare(&mut controller);
+        controller.command(0x75, &[5, 5]);
+        controller.command(0x5C, &[]);
+        controller.write(true, &[0xFF, 0xFF]);
+
+        let lit_row = |controller: &Ssd1351| {
+            controller.render()
+                .chunks_exact(RAM_EXTENT)
+                .position(|row| row[0] == Color::WHITE)
+        };
+        assert_eq!(lit_row(&controller), Some(5));
+
+        controller.command(0xA1, &[2]);
+        assert_eq!(lit_row(&controller), Some(3));
+
+        controller.command(0xA2, &[1]);
+        assert_eq!(lit_row(&controller), Some(2));
+
+        controller.command(0xA0, &[0x50]);
+        assert_eq!(lit_row(&controller), Some(125));
+        controller.command(0xA0, &[0x40]);
+
+        controller.command(0xCA, &[1]);
+        assert_eq!(controller.mux_ratio(), 127);
+        controller.command(0xCA, &[15]);
+        assert_eq!(lit_row(&controller), Some(2));
+        controller.command(0xA1, &[20]);
+        assert_eq!(lit_row(&controller), None);
+    }
+
+    #[test]
+    fn test_unknown_commands_ignored() {
+        let mut controller = Ssd1351::new();
+        controller.command(0x00, &[0xAF, 0x12]);
+        controller.command(0xB8, &[0xAF; 63]);
+        assert!(!controller.is_display_on());
+        assert_eq!(controller.column_window(), (0, 127));
+        assert_eq!(controller.row_window(), (0, 127));
+    }
+
+    #[test]
+    fn test_playfield_orientations() {
+        // replay the driver's setup sequence and a frame; the panel must show the transformed image
+        for rotation in [Rotation::Deg0, Rotation::Deg90, Rotation::Deg180, Rotation::Deg270] {
+            for mirror in [false, true] {
+                let orientation = Orientation::new(rotation, mirror);
+                let layout = DisplayLayout::PSP27801.with_orientation(orientation);
+                let playfield = Playfield::new(layout);
+                let mut frame = vec![0u8; layout.display_bytes()];
+                playfield.draw(&mut frame);
+
+                let mut controller = Ssd1351::new();
+                prepare(&mut controller);
+                initialize(&mut controller, &layout, &TEST_CONFIG);
+                DisplayCommand::WriteRam.transmit(&mut controller);
+                controller.send(None, &frame);
+
+                let image = controller.render();
+                for y in 0..layout.display_height {
+                    for x in 0..layout.display_width {
+                        let offset = (y * layout.display_width + x) * 2;
+                        let expected = Color::from_pixel_bytes([frame[offset], frame[offset + 1]]);
+                        let (panel_x, panel_y) = orientation.transform(
+                            x, y, layout.display_width, layout.display_height,
+                        );
+                        let image_x = panel_x + usize::from(layout.ram_column_offset);
+                        let image_y = panel_y + usize::from(layout.ram_row_offset);
+                        assert_eq!(
+                            image[image_y * RAM_EXTENT + image_x], expected,
+                            "{:?} at ({}, {})", orientation, x, y,
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    /// Counts the bytes sent to the controller, command bytes included.
+    struct CountingInterface<'a> {
+        controller: &'a mut Ssd1351,
+        bytes_sent: usize,
+    }
+    impl DisplayInterface for CountingInterface<'_> {
+        fn send(&mut self, command: Option<u8>, data: &[u8]) {
+            self.bytes_sent += usize::from(command.is_some()) + data.len();
+            self.controller.send(command, data);
+        }
+
+        fn receive(&mut self, command: Option<u8>, buffer: &mut [u8]) {
+            self.controller.receive(command, buffer);
+        }
+    }
+
+    #[test]
+    fn test_partial_updates() {
+        // updating only the dirty areas must keep the panel identical to full-frame updates while
+        // sending far fewer bytes
+        for rotation in [Rotation::Deg0, Rotation::Deg90, Rotation::Deg180, Rotation::Deg270] {
+            let orientation = Orientation::new(rotation, rotation == Rotation::Deg90);
+            let layout = DisplayLayout::PSP27801.with_orientation(orientation);
+            let mut playfield = Playfield::new(layout);
+
+            let mut partial = Ssd1351::new();
+            prepare(&mut partial);
+            initialize(&mut partial, &layout, &TEST_CONFIG);
+            let mut full = partial.clone();
+            let mut partial = CountingInterface { controller: &mut partial, bytes_sent: 0 };
+            let mut full = CountingInterface { controller: &mut full, bytes_sent: 0 };
+
+            let mut buffer = [0u8; 8 * 96 * BYTES_PER_PIXEL];
+            let mut frame = vec![0u8; layout.display_bytes()];
+            for tick in 0..100 {
+                playfield.advance();
+                if tick % 4 == 0 {
+                    playfield.move_paddle_right();
+                }
+
+                for area in playfield.take_dirty_areas() {
+                    update_area(
+                        &mut partial, &Ssd1351Controller::new(TEST_CONFIG), &layout, area,
+                        &mut buffer,
+                        |part, chunk| playfield.draw_area(part, chunk),
+                    );
+                }
+
+                frame.fill(0x00);
+                playfield.draw(&mut frame);
+                DisplayCommand::WriteRam.transmit(&mut full);
+                full.send(None, &frame);
+
+                assert!(partial.controller.render() == full.controller.render(), "tick {}", tick);
+            }
+
+            // the first partial update covers the whole display, all others just a few pixels
+            let full_frame = layout.display_bytes();
+            assert!(full.bytes_sent >= 100 * full_frame);
+            assert!(
+                partial.bytes_sent < 2 * full_frame,
+                "{:?}: {} bytes", orientation, partial.bytes_sent,
+            );
+        }
+    }
+
+    #[test]
+    fn test_indexed_frame() {
+        // a 4-bit frame expanded while streaming must show the same image as a full RGB565 frame
+        let layout = DisplayLayout::SSD1351_128X128;
+        let mut playfield = Playfield::new(layout);
+        playfield.ball.velocity.x = -playfield.ball.velocity.x;
+        for _ in 0..10 {
+            playfield.advance();
+        }
+
+        let depth = IndexDepth::Bits4;
+        let mut frame = IndexedFrameBuffer::new(
+            vec![0u8; depth.frame_bytes(128, 128)], 128, 128, depth,
+        );
+        playfield.draw_indexed(&mut frame);
+        let palette = default_palette();
+
+        let mut indexed = Ssd1351::new();
+        prepare(&mut indexed);
+        initialize(&mut indexed, &layout, &TEST_CONFIG);
+        let mut full = indexed.clone();
+
+        let mut band = [0u8; 8 * 128 * BYTES_PER_PIXEL];
+        update_area(
+            &mut indexed, &Ssd1351Controller::new(TEST_CONFIG), &layout, layout.display_area(),
+            &mut band,
+            |part, chunk| frame.expand_area(part, &palette, chunk),
+        );
+
+        let mut screen = vec![0u8; layout.display_bytes()];
+        playfield.draw(&mut screen);
+        DisplayCommand::WriteRam.transmit(&mut full);
+        full.send(None, &screen);
+
+        assert!(indexed.render() == full.render());
+        assert!(indexed.render().contains(&Color::WHITE));
+    }
+
+    #[test]
+    fn test_config_applied() {
+        // all settings of the profile must get past the command lock of a freshly reset controller
+        let configs = [
+            DisplayConfig::PSP27801,
+            DisplayConfig::SSD1351_128X128,
+            DisplayConfig::PSP27801.with_grayscale_table(Some(GAMMA_2_2)),
+        ];
+        for config in configs {
+            let mut controller = Ssd1351::new();
+            initialize(&mut controller, &DisplayLayout::PSP27801, &config);
+
+            // compare against the last occurrence of each command
+            let mut expected = BTreeMap::new();
+            for command in config.commands().unwrap() {
+                let (code, parameters) = command.encode();
+                expected.insert(code, parameters);
+            }
+            for (code, parameters) in expected {
+                assert_eq!(
+                    controller.last_parameters(code), Some(parameters.as_slice()),
+                    "command 0x{:02X}", code,
+                );
+            }
+            assert_eq!(controller.mux_ratio(), config.mux_ratio);
+            assert_eq!(controller.display_offset(), 0);
+            assert_eq!(controller.start_line(), 0);
+            assert_eq!(controller.color_contrast(), config.color_contrast);
+            assert_eq!(controller.master_contrast(), config.master_contrast);
+            assert!(controller.is_display_on());
+        }
+    }
+
+    #[test]
+    fn test_burn_in_guard() {
+        let layout = DisplayLayout::PSP27801;
+        let playfield = Playfield::new(layout);
+        let mut frame = vec![0u8; layout.display_bytes()];
+        playfield.draw(&mut frame);
+
+        let mut controller = Ssd1351::new();
+        initialize(&mut controller, &layout, &TEST_CONFIG);
+        DisplayCommand::WriteRam.transmit(&mut controller);
+        controller.send(None, &frame);
+        let unshifted = controller.render();
+
+        // moving the image up by a row shows the black RAM row below it at the bottom
+        let config = BurnInConfig::DEFAULT;
+        let mut guard = BurnInGuard::new(config);
+        for command in guard.advance(config.shift_interval, true, Brightness::MAX) {
+            command.transmit(&mut controller);
+        }
+        let shifted = controller.render();
+        let height = layout.panel_height();
+        for column in 0..RAM_EXTENT {
+            for row in 0..height - 1 {
+                assert_eq!(
+                    shifted[row * RAM_EXTENT + column], unshifted[(row + 1) * RAM_EXTENT + column],
+                    "at ({}, {})", column, row,
+                );
+            }
+            assert_eq!(shifted[(height - 1) * RAM_EXTENT + column], Color::BLACK);
+        }
+
+        for command in guard.advance(config.dim_after, false, Brightness::MAX) {
+            command.transmit(&mut controller);
+        }
+        assert_eq!(controller.master_contrast(), Brightness::MIN.master_contrast());
+        for command in guard.advance(config.off_after, false, Brightness::MAX) {
+            command.transmit(&mut controller);
+        }
+        assert!(!controller.is_display_on());
+        for command in guard.advance(Duration::ZERO, true, Brightness::MAX) {
+            command.transmit(&mut controller);
+        }
+        assert!(controller.is_display_on());
+        assert_eq!(controller.master_contrast(), Brightness::MAX.master_contrast());
+    }
+
+    #[test]
+    fn test_effects_cleaned_up() {
+        let mut controller = Ssd1351::new();
+        initialize(&mut controller, &DisplayLayout::PSP27801, &TEST_CONFIG);
+        (DisplayCommand::SetDisplayOffset { offset: 1 })
+            .transmit(&mut controller);
+
+        let mut effects = Effects::new();
+        let mut modes = Vec::new();
+        let mut offsets = Vec::new();
+        for effect in [Effect::Flash, Effect::Shake, Effect::Wipe] {
+            effects.start(effect);
+            while effects.running().is_some() {
+                for command in effects.advance(1).commands {
+                    command.transmit(&mut controller);
+                }
+                modes.push(controller.display_mode());
+                offsets.push(controller.display_offset());
+            }
+            assert_eq!(controller.display_mode(), DisplayMode::Regular);
+            assert_eq!(controller.display_offset(), 1);
+        }
+        assert!(modes.contains(&DisplayMode::Inverted));
+        assert!(offsets.contains(&3) && offsets.contains(&127));
+
+        // replacing an effect cleans it up as well
+        effects.start(Effect::Flash);
+        for command in effects.advance(1).commands {
+            command.transmit(&mut controller);
+        }
+        assert_eq!(controller.display_mode(), DisplayMode::Inverted);
+        effects.start(Effect::Shake);
+        for command in effects.advance(1).commands {
+            command.transmit(&mut controller);
+        }
+        assert_eq!(controller.display_mode(), DisplayMode::Regular);
+        assert_eq!(controller.display_offset(), 3);
+    }
+}